@@ -1,14 +1,14 @@
 fn main() {
-    // This build script will compile Cap'n Proto schemas if they exist.
-    // For now, it's a placeholder to ensure the environment is ready for RT-001.
-    if std::path::Path::new("schema.capnp").exists() {
-        if std::process::Command::new("capnp").arg("--version").output().is_ok() {
-            capnpc::CompilerCommand::new()
-                .file("schema.capnp")
-                .run()
-                .expect("schema compilation failed");
-        } else {
-            println!("cargo:warning=capnp binary not found, skipping schema compilation");
-        }
+    // Generates `schema_capnp.rs` in OUT_DIR, which `src/lib.rs` includes
+    // (RT-001). Without the `capnp` compiler the crate cannot build, so fail
+    // here with the reason instead of at the `include!`.
+    println!("cargo:rerun-if-changed=schema.capnp");
+    if let Err(e) = capnpc::CompilerCommand::new().file("schema.capnp").run() {
+        panic!(
+            "failed to compile schema.capnp: {}\n\
+             The Cap'n Proto compiler (`capnp`) must be installed and on PATH; \
+             the dev container already includes it.",
+            e
+        );
     }
 }
@@ -0,0 +1,13 @@
+-- Changes made through the TaxAdmin interface, one row per write
+CREATE TABLE IF NOT EXISTS admin_audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    actor TEXT NOT NULL,
+    entity TEXT NOT NULL, -- 'profile' or 'iva_rate'
+    entity_key TEXT NOT NULL, -- client_id or jurisdiction
+    action TEXT NOT NULL, -- 'CREATE', 'UPDATE' or 'DELETE'
+    old_value JSONB,
+    new_value JSONB
+);
+
+CREATE INDEX IF NOT EXISTS idx_admin_audit_log_entity ON admin_audit_log(entity, entity_key, changed_at);
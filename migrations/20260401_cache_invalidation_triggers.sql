@@ -0,0 +1,45 @@
+-- Announces every change to profiles and iva_rates on the cache_invalidation
+-- channel, including edits made directly in SQL, so the service can evict
+-- the affected cache keys. Payloads use the cache key format:
+-- 'profile:<client_id>', 'iva_rate:<jurisdiction>' or 'iva_rate:*'.
+CREATE OR REPLACE FUNCTION notify_profile_change() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        PERFORM pg_notify('cache_invalidation', 'profile:' || OLD.client_id);
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        PERFORM pg_notify('cache_invalidation', 'profile:' || NEW.client_id);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Jurisdictions without their own rate are cached with the DEFAULT one, so a
+-- DEFAULT change invalidates every cached rate
+CREATE OR REPLACE FUNCTION notify_iva_rate_change() RETURNS trigger AS $$
+DECLARE
+    jurisdiction TEXT;
+BEGIN
+    FOREACH jurisdiction IN ARRAY ARRAY[
+        CASE WHEN TG_OP IN ('UPDATE', 'DELETE') THEN OLD.jurisdiction END,
+        CASE WHEN TG_OP IN ('INSERT', 'UPDATE') THEN NEW.jurisdiction END
+    ] LOOP
+        IF jurisdiction = 'DEFAULT' THEN
+            PERFORM pg_notify('cache_invalidation', 'iva_rate:*');
+        ELSIF jurisdiction IS NOT NULL THEN
+            PERFORM pg_notify('cache_invalidation', 'iva_rate:' || jurisdiction);
+        END IF;
+    END LOOP;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS profiles_cache_invalidation ON profiles;
+CREATE TRIGGER profiles_cache_invalidation
+    AFTER INSERT OR UPDATE OR DELETE ON profiles
+    FOR EACH ROW EXECUTE FUNCTION notify_profile_change();
+
+DROP TRIGGER IF EXISTS iva_rates_cache_invalidation ON iva_rates;
+CREATE TRIGGER iva_rates_cache_invalidation
+    AFTER INSERT OR UPDATE OR DELETE ON iva_rates
+    FOR EACH ROW EXECUTE FUNCTION notify_iva_rate_change();
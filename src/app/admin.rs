@@ -0,0 +1,256 @@
+use anyhow::Result;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::app::changes::{Change, ChangeAction, ChangeKind, ChangeNotifier, DEFAULT_JURISDICTION};
+use crate::app::errors::{ErrorCode, ServiceError};
+use crate::domain::models::{IvaRate, Profile};
+use crate::domain::traits::{ProfileAdminRepositoryTrait, ProfileCacheTrait};
+
+/// Profile and IVA rate management behind the `TaxAdmin` interface.
+///
+/// Writes go to the database first and then drop the affected cache keys, so
+/// the next calculation reads the new values instead of waiting for the TTL.
+/// Each committed change is then published to `subscribe` listeners.
+pub struct AdminService<A, C>
+where
+    A: ProfileAdminRepositoryTrait,
+    C: ProfileCacheTrait,
+{
+    repo: A,
+    cache: C,
+    notifier: Option<Arc<ChangeNotifier>>,
+}
+
+impl<A, C> AdminService<A, C>
+where
+    A: ProfileAdminRepositoryTrait,
+    C: ProfileCacheTrait,
+{
+    pub fn new(repo: A, cache: C) -> Self {
+        Self { repo, cache, notifier: None }
+    }
+
+    pub fn with_notifier(mut self, notifier: Arc<ChangeNotifier>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    pub async fn list_profiles(&self) -> Result<Vec<Profile>> {
+        self.repo.list_profiles().await
+    }
+
+    pub async fn create_profile(&self, profile: &Profile, actor: &str) -> Result<()> {
+        validate_actor(actor)?;
+        validate_profile(profile)?;
+        if !self.repo.insert_profile(profile, actor).await? {
+            return Err(already_exists(format!("profile {} already exists", profile.client_id)));
+        }
+        info!("Profile {} created by {}", profile.client_id, actor);
+        self.profile_changed(&profile.client_id, ChangeAction::Created).await;
+        Ok(())
+    }
+
+    pub async fn update_profile(&self, profile: &Profile, actor: &str) -> Result<()> {
+        validate_actor(actor)?;
+        validate_profile(profile)?;
+        if !self.repo.update_profile(profile, actor).await? {
+            return Err(not_found(format!("profile {} does not exist", profile.client_id)));
+        }
+        info!("Profile {} updated by {}", profile.client_id, actor);
+        self.profile_changed(&profile.client_id, ChangeAction::Updated).await;
+        Ok(())
+    }
+
+    pub async fn delete_profile(&self, client_id: &str, actor: &str) -> Result<()> {
+        validate_actor(actor)?;
+        if !self.repo.delete_profile(client_id, actor).await? {
+            return Err(not_found(format!("profile {} does not exist", client_id)));
+        }
+        info!("Profile {} deleted by {}", client_id, actor);
+        self.profile_changed(client_id, ChangeAction::Deleted).await;
+        Ok(())
+    }
+
+    pub async fn list_iva_rates(&self) -> Result<Vec<IvaRate>> {
+        self.repo.list_iva_rates().await
+    }
+
+    pub async fn create_iva_rate(&self, rate: &IvaRate, actor: &str) -> Result<()> {
+        validate_actor(actor)?;
+        validate_iva_rate(rate)?;
+        if !self.repo.insert_iva_rate(rate, actor).await? {
+            return Err(already_exists(format!("IVA rate for {} already exists", rate.jurisdiction)));
+        }
+        info!("IVA rate for {} created by {}", rate.jurisdiction, actor);
+        self.iva_rate_changed(&rate.jurisdiction, ChangeAction::Created).await;
+        Ok(())
+    }
+
+    pub async fn update_iva_rate(&self, rate: &IvaRate, actor: &str) -> Result<()> {
+        validate_actor(actor)?;
+        validate_iva_rate(rate)?;
+        if !self.repo.update_iva_rate(rate, actor).await? {
+            return Err(not_found(format!("IVA rate for {} does not exist", rate.jurisdiction)));
+        }
+        info!("IVA rate for {} updated by {}", rate.jurisdiction, actor);
+        self.iva_rate_changed(&rate.jurisdiction, ChangeAction::Updated).await;
+        Ok(())
+    }
+
+    pub async fn delete_iva_rate(&self, jurisdiction: &str, actor: &str) -> Result<()> {
+        validate_actor(actor)?;
+        if !self.repo.delete_iva_rate(jurisdiction, actor).await? {
+            return Err(not_found(format!("IVA rate for {} does not exist", jurisdiction)));
+        }
+        info!("IVA rate for {} deleted by {}", jurisdiction, actor);
+        self.iva_rate_changed(jurisdiction, ChangeAction::Deleted).await;
+        Ok(())
+    }
+
+    // The write is already committed at this point, so a cache failure is
+    // logged rather than returned: the stale entry expires with its TTL.
+    async fn profile_changed(&self, client_id: &str, action: ChangeAction) {
+        if let Err(e) = self.cache.invalidate_profile(client_id).await {
+            error!("Failed to invalidate cached profile {}: {:#}", client_id, e);
+        }
+        self.publish(ChangeKind::Profile, client_id, action);
+    }
+
+    async fn iva_rate_changed(&self, jurisdiction: &str, action: ChangeAction) {
+        // Jurisdictions without their own rate are cached with the DEFAULT one
+        let outcome = if jurisdiction == DEFAULT_JURISDICTION {
+            self.cache.invalidate_all_iva_rates().await
+        } else {
+            self.cache.invalidate_iva_rate(jurisdiction).await
+        };
+        if let Err(e) = outcome {
+            error!("Failed to invalidate cached IVA rate {}: {:#}", jurisdiction, e);
+        }
+        self.publish(ChangeKind::IvaRate, jurisdiction, action);
+    }
+
+    fn publish(&self, kind: ChangeKind, key: &str, action: ChangeAction) {
+        if let Some(notifier) = &self.notifier {
+            notifier.publish(Change { kind, key: key.to_string(), action });
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> anyhow::Error {
+    ServiceError::new(ErrorCode::InvalidRequest, message).into()
+}
+
+fn not_found(message: impl Into<String>) -> anyhow::Error {
+    ServiceError::new(ErrorCode::NotFound, message).into()
+}
+
+fn already_exists(message: impl Into<String>) -> anyhow::Error {
+    ServiceError::new(ErrorCode::AlreadyExists, message).into()
+}
+
+fn validate_actor(actor: &str) -> Result<()> {
+    if actor.trim().is_empty() {
+        return Err(invalid("actor is required"));
+    }
+    Ok(())
+}
+
+fn validate_profile(profile: &Profile) -> Result<()> {
+    if profile.client_id.trim().is_empty() {
+        return Err(invalid("clientId is required"));
+    }
+    if profile.fiscal_category.trim().is_empty() {
+        return Err(invalid("fiscalCategory is required"));
+    }
+    if !profile.config.is_object() {
+        return Err(invalid("config must be a JSON object"));
+    }
+    Ok(())
+}
+
+fn validate_iva_rate(rate: &IvaRate) -> Result<()> {
+    if rate.jurisdiction.trim().is_empty() {
+        return Err(invalid("jurisdiction is required"));
+    }
+    if !(0.0..=1.0).contains(&rate.rate) {
+        return Err(invalid(format!("rate must be between 0 and 1, got {}", rate.rate)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockAdminRepo, MockCache};
+    use mockall::predicate::eq;
+
+    fn profile() -> Profile {
+        Profile {
+            client_id: "c1".to_string(),
+            fiscal_category: "MONOTRIBUTO".to_string(),
+            config: serde_json::json!({}),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_profile_invalidates_cache_and_notifies() {
+        let mut repo = MockAdminRepo::new();
+        let mut cache = MockCache::new();
+
+        repo.expect_update_profile()
+            .withf(|p, actor| p.client_id == "c1" && actor == "ops@example.com")
+            .returning(|_, _| Ok(true));
+        cache.expect_invalidate_profile().with(eq("c1")).times(1).returning(|_| Ok(()));
+
+        let notifier = Arc::new(ChangeNotifier::new());
+        let mut changes = notifier.subscribe();
+
+        let service = AdminService::new(repo, cache).with_notifier(notifier);
+        service.update_profile(&profile(), "ops@example.com").await.unwrap();
+
+        let change = changes.try_recv().unwrap();
+        assert_eq!(change.kind, ChangeKind::Profile);
+        assert_eq!(change.key, "c1");
+        assert_eq!(change.action, ChangeAction::Updated);
+    }
+
+    #[tokio::test]
+    async fn test_update_missing_profile_is_not_found() {
+        let mut repo = MockAdminRepo::new();
+        let mut cache = MockCache::new();
+
+        repo.expect_update_profile().returning(|_, _| Ok(false));
+        cache.expect_invalidate_profile().never();
+
+        let service = AdminService::new(repo, cache);
+        let err = service.update_profile(&profile(), "ops").await.unwrap_err();
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_default_rate_change_invalidates_all_rates() {
+        let mut repo = MockAdminRepo::new();
+        let mut cache = MockCache::new();
+
+        repo.expect_update_iva_rate().returning(|_, _| Ok(true));
+        cache.expect_invalidate_all_iva_rates().times(1).returning(|| Ok(()));
+        cache.expect_invalidate_iva_rate().never();
+
+        let service = AdminService::new(repo, cache);
+        let rate = IvaRate { jurisdiction: "DEFAULT".to_string(), rate: 0.27 };
+        service.update_iva_rate(&rate, "ops").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_writes_are_rejected() {
+        let service = AdminService::new(MockAdminRepo::new(), MockCache::new());
+
+        let err = service.create_profile(&profile(), " ").await.unwrap_err();
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::InvalidRequest);
+
+        let rate = IvaRate { jurisdiction: "BUENOS_AIRES".to_string(), rate: 21.0 };
+        let err = service.create_iva_rate(&rate, "ops").await.unwrap_err();
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::InvalidRequest);
+    }
+}
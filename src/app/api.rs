@@ -0,0 +1,304 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize, Serializer};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{error, warn};
+
+use crate::app::auth::KeySet;
+use crate::app::deadline::TimeoutBounds;
+use crate::app::degradation;
+use crate::app::errors::{ErrorCode, ServiceError};
+use crate::app::orchestrator::Orchestrator;
+use crate::app::rate_limit::RateLimiter;
+use crate::app::rpc::decimal_from_f64;
+use crate::app::shutdown::Shutdown;
+use crate::domain::models::{TaxBreakdown, TaxType, Transaction};
+use crate::domain::traits::{ProfileCacheTrait, ProfileRepositoryTrait};
+
+/// Body of `POST /v1/calculate`, mirroring `TaxEngine.calculate`'s request.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalculateRequest {
+    pub client_id: String,
+    pub amount: f64,
+    pub jurisdiction: String,
+    pub product: String,
+    /// Deadline for this request; `0` or absent uses `REQUEST_TIMEOUT_MS`.
+    #[serde(default)]
+    pub timeout_ms: u32,
+    /// `YYYY-MM-DD`; absent uses the current date.
+    #[serde(default)]
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalculateResponse {
+    pub total_amount: f64,
+    #[serde(serialize_with = "serialize_breakdown")]
+    pub breakdown: Vec<TaxBreakdown>,
+    /// The cache failed and values were read from the database instead.
+    pub degraded: bool,
+    /// The database failed and values came from stale cache copies.
+    pub stale: bool,
+    /// Age of the oldest stale copy used, when `stale` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stale_age_ms: Option<u64>,
+}
+
+/// One tax of a breakdown as JSON clients see it.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreakdownLine {
+    pub tax_type: TaxType,
+    pub base: f64,
+    pub rate: f64,
+    pub amount: f64,
+}
+
+impl From<&TaxBreakdown> for BreakdownLine {
+    fn from(breakdown: &TaxBreakdown) -> Self {
+        Self {
+            tax_type: breakdown.tax_type,
+            base: breakdown.base.to_f64().unwrap_or_default(),
+            rate: breakdown.rate,
+            amount: breakdown.amount.to_f64().unwrap_or_default(),
+        }
+    }
+}
+
+/// Writes a breakdown as [`BreakdownLine`]s, for `#[serde(serialize_with)]`.
+pub fn serialize_breakdown<S>(breakdown: &[TaxBreakdown], serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(breakdown.iter().map(BreakdownLine::from))
+}
+
+/// The calculation behind the HTTP/JSON API, so the HTTP server does not
+/// depend on the repository types.
+#[async_trait]
+pub trait CalculateApiTrait: Send + Sync {
+    /// `token` is the bearer token of the request, if it sent one.
+    async fn calculate(&self, request: CalculateRequest, token: Option<&str>, received_at: Instant)
+        -> Result<CalculateResponse>;
+}
+
+/// `TaxEngine.calculate` for HTTP/JSON clients: the same consumer scoping,
+/// rate limit, deadline and error codes, with the `Gateway` token sent as
+/// `Authorization: Bearer <token>` instead of through `login`.
+pub struct CalculateApi<R, C>
+where
+    R: ProfileRepositoryTrait,
+    C: ProfileCacheTrait,
+{
+    orchestrator: Arc<Orchestrator<R, C>>,
+    shutdown: Shutdown,
+    request_timeout: Duration,
+    timeout_bounds: TimeoutBounds,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    keys: Option<Arc<KeySet>>,
+}
+
+impl<R, C> CalculateApi<R, C>
+where
+    R: ProfileRepositoryTrait,
+    C: ProfileCacheTrait,
+{
+    pub fn new(orchestrator: Arc<Orchestrator<R, C>>, shutdown: Shutdown, request_timeout: Duration) -> Self {
+        Self {
+            orchestrator,
+            shutdown,
+            request_timeout,
+            timeout_bounds: TimeoutBounds::default(),
+            rate_limiter: None,
+            keys: None,
+        }
+    }
+
+    /// Range `timeoutMs` is clamped to, as for RPC.
+    pub fn with_timeout_bounds(mut self, bounds: TimeoutBounds) -> Self {
+        self.timeout_bounds = bounds;
+        self
+    }
+
+    /// Shares the per-`clientId` budget with the RPC server.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Requires a token from `keys` and scopes requests to its consumer.
+    pub fn with_keys(mut self, keys: Arc<KeySet>) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+}
+
+#[async_trait]
+impl<R, C> CalculateApiTrait for CalculateApi<R, C>
+where
+    R: ProfileRepositoryTrait + Send + Sync,
+    C: ProfileCacheTrait + Send + Sync,
+{
+    async fn calculate(
+        &self,
+        request: CalculateRequest,
+        token: Option<&str>,
+        received_at: Instant,
+    ) -> Result<CalculateResponse> {
+        let Some(_in_flight) = self.shutdown.track() else {
+            return Err(ServiceError::new(ErrorCode::Unavailable, "server is shutting down").into());
+        };
+
+        if let Some(keys) = &self.keys {
+            let Some(consumer) = token.and_then(|token| keys.authenticate(token)) else {
+                warn!("HTTP calculate rejected: missing or unknown token");
+                return Err(ServiceError::new(ErrorCode::Unauthenticated, "invalid token").into());
+            };
+            if !consumer.can_access(&request.client_id) {
+                warn!("Consumer {} is not allowed to calculate for client {}", consumer.name, request.client_id);
+                return Err(ServiceError::new(
+                    ErrorCode::PermissionDenied,
+                    format!("not allowed to calculate for client {}", request.client_id),
+                )
+                .into());
+            }
+        }
+        if let Some(limiter) = &self.rate_limiter
+            && !limiter.try_acquire(&request.client_id)
+        {
+            warn!("Rate limit exceeded for client: {} (HTTP)", request.client_id);
+            return Err(ServiceError::new(
+                ErrorCode::Overloaded,
+                format!("rate limit exceeded for client {}", request.client_id),
+            )
+            .into());
+        }
+
+        let timeout = match request.timeout_ms {
+            0 => self.request_timeout,
+            ms => self.timeout_bounds.clamp(Duration::from_millis(ms.into())),
+        };
+        let tx = Transaction {
+            amount: decimal_from_f64(request.amount)?,
+            product: request.product,
+            jurisdiction: request.jurisdiction,
+            client_id: request.client_id,
+            date: request.date.unwrap_or_else(|| chrono::Local::now().date_naive()),
+        };
+
+        let (outcome, degradation) =
+            degradation::track(self.orchestrator.process_calculation_until(tx, received_at + timeout)).await;
+        match outcome {
+            Ok(breakdown) => Ok(CalculateResponse {
+                total_amount: breakdown.iter().map(|b| b.amount).sum::<Decimal>().to_f64().unwrap_or_default(),
+                breakdown,
+                degraded: degradation.cache_failed,
+                stale: degradation.stale_age.is_some(),
+                stale_age_ms: degradation.stale_age.map(|age| age.as_millis() as u64),
+            }),
+            Err(e) => {
+                let code = ErrorCode::from_error(&e);
+                error!("Calculation error ({}) over HTTP: {:?}", code, e);
+                Err(ServiceError::new(code, format!("Calculation failed: {}", e)).into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::resolver::ProfileResolver;
+    use crate::domain::calculators::IVACalculator;
+    use crate::domain::models::{IvaRate, Profile};
+    use crate::test_support::{MockCache, MockRepo};
+    use sha2::{Digest, Sha256};
+
+    fn api() -> CalculateApi<MockRepo, MockCache> {
+        let repo = MockRepo::new();
+        let mut cache = MockCache::new();
+        cache.expect_get_by_id().returning(|id| {
+            Ok(Some(Profile {
+                client_id: id.to_string(),
+                fiscal_category: "RESPONSABLE_INSCRIPTO".to_string(),
+                config: serde_json::json!({}),
+            }))
+        });
+        cache
+            .expect_get_iva_rate()
+            .returning(|j| Ok(Some(IvaRate { jurisdiction: j.to_string(), rate: 0.21 })));
+
+        let orchestrator = Orchestrator::new(ProfileResolver::new(repo, cache), IVACalculator);
+        CalculateApi::new(Arc::new(orchestrator), Shutdown::new(), Duration::from_secs(1))
+    }
+
+    fn request(client_id: &str) -> CalculateRequest {
+        serde_json::from_value(serde_json::json!({
+            "clientId": client_id,
+            "amount": 100.0,
+            "jurisdiction": "BUENOS_AIRES",
+            "product": "PROD",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_calculate_returns_breakdown() {
+        let response = api().calculate(request("c1"), None, Instant::now()).await.unwrap();
+
+        assert_eq!(response.total_amount, 21.0);
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["breakdown"][0]["taxType"], "IVA");
+        assert_eq!(json["breakdown"][0]["rate"], 0.21);
+    }
+
+    #[tokio::test]
+    async fn test_calculate_unknown_client_is_not_found() {
+        let mut repo = MockRepo::new();
+        let mut cache = MockCache::new();
+        cache.expect_get_by_id().returning(|_| Ok(None));
+        repo.expect_get_by_id().returning(|_| Ok(None));
+        let orchestrator = Orchestrator::new(ProfileResolver::new(repo, cache), IVACalculator);
+        let api = CalculateApi::new(Arc::new(orchestrator), Shutdown::new(), Duration::from_secs(1));
+
+        let err = api.calculate(request("missing"), None, Instant::now()).await.unwrap_err();
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::NotFound);
+        assert_eq!(ErrorCode::from_error(&err).http_status(), 404);
+        assert_eq!(err.to_string(), "Calculation failed: Profile not found");
+    }
+
+    #[tokio::test]
+    async fn test_calculate_requires_token_scoped_to_client() {
+        let hash = Sha256::digest(b"secret-token").iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        let keys = KeySet::from_json(&format!(
+            r#"[{{"consumer": "billing", "token_sha256": "{}", "client_ids": ["c1"]}}]"#,
+            hash
+        ))
+        .unwrap();
+        let api = api().with_keys(Arc::new(keys));
+
+        let err = api.calculate(request("c1"), None, Instant::now()).await.unwrap_err();
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::Unauthenticated);
+
+        let err = api.calculate(request("c2"), Some("secret-token"), Instant::now()).await.unwrap_err();
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::PermissionDenied);
+
+        assert!(api.calculate(request("c1"), Some("secret-token"), Instant::now()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_calculate_rejected_during_shutdown() {
+        let api = api();
+        api.shutdown.trigger();
+
+        let err = api.calculate(request("c1"), None, Instant::now()).await.unwrap_err();
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::Unavailable);
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Grants access to every client id.
+const ANY_CLIENT: &str = "*";
+
+/// A caller authenticated through `Gateway.login`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Consumer {
+    pub name: String,
+    /// Client ids this consumer can calculate for; `None` means any.
+    pub client_ids: Option<HashSet<String>>,
+}
+
+impl Consumer {
+    pub fn can_access(&self, client_id: &str) -> bool {
+        self.client_ids.as_ref().is_none_or(|ids| ids.contains(client_id))
+    }
+}
+
+#[derive(Deserialize)]
+struct KeyEntry {
+    consumer: String,
+    /// Hex-encoded SHA-256 of the token, so the file holds no secrets.
+    token_sha256: String,
+    client_ids: Vec<String>,
+}
+
+/// Tokens accepted by `Gateway.login`, loaded from a JSON file:
+///
+/// ```json
+/// [{ "consumer": "billing", "token_sha256": "<hex>", "client_ids": ["client_1"] }]
+/// ```
+///
+/// `"client_ids": ["*"]` grants access to every client.
+pub struct KeySet {
+    keys: Vec<([u8; 32], Arc<Consumer>)>,
+}
+
+impl KeySet {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read key set {}", path.display()))?;
+        Self::from_json(&json).with_context(|| format!("Invalid key set {}", path.display()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let entries: Vec<KeyEntry> = serde_json::from_str(json)?;
+        let mut keys = Vec::with_capacity(entries.len());
+        for entry in entries {
+            anyhow::ensure!(!entry.consumer.is_empty(), "Key without consumer name");
+            let hash = decode_sha256(&entry.token_sha256)
+                .with_context(|| format!("Invalid token_sha256 for consumer {}", entry.consumer))?;
+            let client_ids = if entry.client_ids.iter().any(|id| id == ANY_CLIENT) {
+                None
+            } else {
+                Some(entry.client_ids.into_iter().collect())
+            };
+            keys.push((hash, Arc::new(Consumer { name: entry.consumer, client_ids })));
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns the consumer owning `token`, if any.
+    pub fn authenticate(&self, token: &str) -> Option<Arc<Consumer>> {
+        let hash: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        // Compare against every key without short-circuiting so the time
+        // taken does not reveal which key (or how much of it) matched
+        let mut found = None;
+        for (key, consumer) in &self.keys {
+            if constant_time_eq(key, &hash) {
+                found = Some(consumer.clone());
+            }
+        }
+        found
+    }
+}
+
+fn constant_time_eq(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn decode_sha256(hex: &str) -> Result<[u8; 32]> {
+    anyhow::ensure!(hex.len() == 64 && hex.is_ascii(), "expected 64 hex characters");
+    let mut out = [0u8; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).context("not a hex string")?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_set(client_ids: &str) -> KeySet {
+        let hash = Sha256::digest(b"secret-token")
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        KeySet::from_json(&format!(
+            r#"[{{"consumer": "billing", "token_sha256": "{}", "client_ids": {}}}]"#,
+            hash, client_ids
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_authenticate_scoped_consumer() {
+        let keys = key_set(r#"["client_1"]"#);
+
+        let consumer = keys.authenticate("secret-token").unwrap();
+        assert_eq!(consumer.name, "billing");
+        assert!(consumer.can_access("client_1"));
+        assert!(!consumer.can_access("client_2"));
+
+        assert!(keys.authenticate("wrong-token").is_none());
+    }
+
+    #[test]
+    fn test_wildcard_grants_every_client() {
+        let consumer = key_set(r#"["*"]"#).authenticate("secret-token").unwrap();
+        assert!(consumer.client_ids.is_none());
+        assert!(consumer.can_access("anyone"));
+    }
+
+    #[test]
+    fn test_rejects_malformed_hash() {
+        let json = r#"[{"consumer": "x", "token_sha256": "abcd", "client_ids": []}]"#;
+        assert!(KeySet::from_json(json).is_err());
+
+        let json = format!(r#"[{{"consumer": "x", "token_sha256": "{}", "client_ids": []}}]"#, "zz".repeat(32));
+        assert!(KeySet::from_json(&json).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::domain::invalidation::Invalidation;
+pub use crate::domain::models::DEFAULT_JURISDICTION;
+
+/// Pending changes kept per subscriber before it starts missing them.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// One change reaches the notifier from several paths (the admin write, the
+/// Redis invalidation and the database trigger); repeats of a key within
+/// this window are folded into a single trailing notification.
+const COALESCE_WINDOW: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    IvaRate,
+    Profile,
+    /// Changes may have been missed; anything may have changed.
+    Resync,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeAction {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// A committed change to an IVA rate or a profile. `key` is the jurisdiction
+/// or the client id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub key: String,
+    pub action: ChangeAction,
+}
+
+impl Change {
+    /// A change seen as a cache invalidation, from SQL or another instance.
+    /// Invalidations do not tell creations and deletions apart, so they are
+    /// reported as updates.
+    pub fn from_invalidation(invalidation: &Invalidation) -> Self {
+        let (kind, key) = match invalidation {
+            Invalidation::Profile(client_id) => (ChangeKind::Profile, client_id.as_str()),
+            Invalidation::IvaRate(jurisdiction) => (ChangeKind::IvaRate, jurisdiction.as_str()),
+            Invalidation::AllIvaRates => (ChangeKind::IvaRate, DEFAULT_JURISDICTION),
+            // Any profile may have changed
+            Invalidation::AllProfiles => return Self::resync(),
+        };
+        Self { kind, key: key.to_string(), action: ChangeAction::Updated }
+    }
+
+    pub fn resync() -> Self {
+        Self { kind: ChangeKind::Resync, key: String::new(), action: ChangeAction::Updated }
+    }
+}
+
+/// What a subscriber cares about. An empty list matches every key of that
+/// kind.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeFilter {
+    pub jurisdictions: Vec<String>,
+    pub client_ids: Vec<String>,
+}
+
+impl ChangeFilter {
+    pub fn matches(&self, change: &Change) -> bool {
+        match change.kind {
+            // The DEFAULT rate applies to every jurisdiction without its own
+            ChangeKind::IvaRate => {
+                self.jurisdictions.is_empty()
+                    || change.key == DEFAULT_JURISDICTION
+                    || self.jurisdictions.contains(&change.key)
+            }
+            ChangeKind::Profile => self.client_ids.is_empty() || self.client_ids.contains(&change.key),
+            ChangeKind::Resync => true,
+        }
+    }
+}
+
+/// Fans out committed changes to every `subscribe` listener, on any worker
+/// thread. They come from local admin writes and from the invalidations
+/// announced by other instances and by the database.
+///
+/// The first change to a key is sent right away; repeats within
+/// `COALESCE_WINDOW` are held and the last of them is sent when the window
+/// ends, so a listener always hears about a key after its latest change.
+pub struct ChangeNotifier {
+    sender: broadcast::Sender<Change>,
+    recent: Arc<Mutex<Recent>>,
+}
+
+/// Keys sent within the window, with the last change held since.
+type Recent = HashMap<(ChangeKind, String), Option<Change>>;
+
+impl Default for ChangeNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChangeNotifier {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender, recent: Arc::default() }
+    }
+
+    /// Must be called from a Tokio runtime, which ends the coalescing window.
+    pub fn publish(&self, change: Change) {
+        let key = (change.kind, change.key.clone());
+        {
+            let mut recent = self.recent.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(held) = recent.get_mut(&key) {
+                *held = Some(change);
+                return;
+            }
+            recent.insert(key.clone(), None);
+        }
+        // Fails only when nobody is subscribed
+        let _ = self.sender.send(change);
+
+        let (sender, recent) = (self.sender.clone(), self.recent.clone());
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(COALESCE_WINDOW).await;
+                let held = {
+                    let mut recent = recent.lock().unwrap_or_else(PoisonError::into_inner);
+                    match recent.remove(&key).flatten() {
+                        // Sending it opens a new window for the key
+                        Some(change) => {
+                            recent.insert(key.clone(), None);
+                            change
+                        }
+                        None => return,
+                    }
+                };
+                let _ = sender.send(held);
+            }
+        });
+    }
+
+    /// A subscriber that falls more than `CHANNEL_CAPACITY` changes behind
+    /// gets `RecvError::Lagged` and should assume anything may have changed.
+    pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(kind: ChangeKind, key: &str) -> Change {
+        Change { kind, key: key.to_string(), action: ChangeAction::Updated }
+    }
+
+    #[test]
+    fn test_filter_matches_selected_keys() {
+        let filter = ChangeFilter {
+            jurisdictions: vec!["BUENOS_AIRES".to_string()],
+            client_ids: vec!["c1".to_string()],
+        };
+
+        assert!(filter.matches(&change(ChangeKind::IvaRate, "BUENOS_AIRES")));
+        assert!(!filter.matches(&change(ChangeKind::IvaRate, "TIERRA_DEL_FUEGO")));
+        assert!(filter.matches(&change(ChangeKind::Profile, "c1")));
+        assert!(!filter.matches(&change(ChangeKind::Profile, "c2")));
+    }
+
+    #[test]
+    fn test_filter_default_rate_matches_every_jurisdiction() {
+        let filter = ChangeFilter {
+            jurisdictions: vec!["BUENOS_AIRES".to_string()],
+            client_ids: Vec::new(),
+        };
+
+        assert!(filter.matches(&change(ChangeKind::IvaRate, DEFAULT_JURISDICTION)));
+        assert!(filter.matches(&change(ChangeKind::Profile, "any")));
+    }
+
+    #[test]
+    fn test_invalidations_map_to_changes() {
+        assert_eq!(Change::from_invalidation(&Invalidation::Profile("c1".to_string())), change(ChangeKind::Profile, "c1"));
+        assert_eq!(
+            Change::from_invalidation(&Invalidation::AllIvaRates),
+            change(ChangeKind::IvaRate, DEFAULT_JURISDICTION)
+        );
+        assert_eq!(Change::from_invalidation(&Invalidation::AllProfiles), Change::resync());
+        assert!(ChangeFilter { jurisdictions: vec!["TDF".to_string()], client_ids: Vec::new() }.matches(&Change::resync()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_repeated_changes_are_coalesced() {
+        let notifier = ChangeNotifier::new();
+        let mut receiver = notifier.subscribe();
+
+        // The admin write, then its Redis and database echoes
+        notifier.publish(Change { kind: ChangeKind::Profile, key: "c1".to_string(), action: ChangeAction::Deleted });
+        notifier.publish(change(ChangeKind::Profile, "c1"));
+        notifier.publish(change(ChangeKind::Profile, "c1"));
+        notifier.publish(change(ChangeKind::Profile, "c2"));
+
+        assert_eq!(receiver.recv().await.unwrap().action, ChangeAction::Deleted);
+        assert_eq!(receiver.recv().await.unwrap(), change(ChangeKind::Profile, "c2"));
+        assert!(receiver.try_recv().is_err());
+
+        // The last repeat is sent once the window ends, and nothing after it
+        tokio::time::sleep(COALESCE_WINDOW).await;
+        assert_eq!(receiver.recv().await.unwrap(), change(ChangeKind::Profile, "c1"));
+        tokio::time::sleep(COALESCE_WINDOW * 3).await;
+        assert!(receiver.try_recv().is_err());
+        assert!(notifier.recent.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_published_changes_reach_subscribers() {
+        let notifier = ChangeNotifier::new();
+        let mut receiver = notifier.subscribe();
+
+        notifier.publish(change(ChangeKind::Profile, "c1"));
+
+        assert_eq!(receiver.recv().await.unwrap(), change(ChangeKind::Profile, "c1"));
+    }
+}
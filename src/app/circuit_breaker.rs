@@ -0,0 +1,489 @@
+use crate::domain::models::{IvaRate, Profile};
+use crate::domain::traits::{CacheLookup, Generation, ProfileCacheTrait, ProfileRepositoryTrait, StaleEntry};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerConfig {
+    /// Calls whose outcome is tracked. `0` disables the breaker.
+    pub window: usize,
+    /// Share of failed calls in a full window that opens the circuit.
+    pub failure_rate: f64,
+    /// How long an open circuit rejects calls before letting one through.
+    pub open_for: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    /// 50% errors over 10 requests as per RT-003.
+    fn default() -> Self {
+        Self { window: 10, failure_rate: 0.5, open_for: Duration::from_secs(5) }
+    }
+}
+
+impl CircuitBreakerConfig {
+    /// Reads `CIRCUIT_BREAKER_WINDOW` (default 10),
+    /// `CIRCUIT_BREAKER_FAILURE_RATE` (default 0.5) and
+    /// `CIRCUIT_BREAKER_OPEN_MS` (default 5000).
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+        if let Ok(value) = env::var("CIRCUIT_BREAKER_WINDOW") {
+            config.window = value.parse().context("Invalid value for CIRCUIT_BREAKER_WINDOW")?;
+        }
+        if let Ok(value) = env::var("CIRCUIT_BREAKER_FAILURE_RATE") {
+            config.failure_rate = value.parse().context("Invalid value for CIRCUIT_BREAKER_FAILURE_RATE")?;
+            anyhow::ensure!(
+                config.failure_rate > 0.0 && config.failure_rate <= 1.0,
+                "CIRCUIT_BREAKER_FAILURE_RATE must be in (0, 1]"
+            );
+        }
+        if let Ok(value) = env::var("CIRCUIT_BREAKER_OPEN_MS") {
+            config.open_for =
+                Duration::from_millis(value.parse().context("Invalid value for CIRCUIT_BREAKER_OPEN_MS")?);
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CircuitState {
+    Closed,
+    /// Calls fail immediately with `CircuitOpen`.
+    Open,
+    /// A single trial call is let through; its outcome closes or reopens the
+    /// circuit.
+    HalfOpen,
+}
+
+/// State of one breaker, for monitoring.
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitSnapshot {
+    pub name: &'static str,
+    pub state: CircuitState,
+    /// Calls tracked in the current window and how many of them failed.
+    pub calls: usize,
+    pub failures: usize,
+}
+
+/// Returned instead of calling a dependency whose circuit is open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitOpen {
+    pub name: &'static str,
+}
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "circuit breaker for {} is open", self.name)
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+struct Breaker {
+    state: CircuitState,
+    opened_at: Instant,
+    /// Whether the half-open trial call is in flight.
+    probing: bool,
+    /// Outcomes of the last calls while closed, `true` for a failure.
+    outcomes: VecDeque<bool>,
+}
+
+/// Count-based circuit breaker (RT-003): opens when the share of failures
+/// among the last `window` calls reaches `failure_rate`, rejects calls for
+/// `open_for`, then lets one call through to decide whether to close again.
+pub struct CircuitBreaker {
+    name: &'static str,
+    config: CircuitBreakerConfig,
+    breaker: Mutex<Breaker>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, config: CircuitBreakerConfig) -> Self {
+        Self {
+            name,
+            config,
+            breaker: Mutex::new(Breaker {
+                state: CircuitState::Closed,
+                opened_at: Instant::now(),
+                probing: false,
+                outcomes: VecDeque::with_capacity(config.window),
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.snapshot().state
+    }
+
+    pub fn snapshot(&self) -> CircuitSnapshot {
+        let breaker = self.breaker.lock().unwrap_or_else(PoisonError::into_inner);
+        // An open circuit whose wait is over lets the next call through
+        let state = match breaker.state {
+            CircuitState::Open if breaker.opened_at.elapsed() >= self.config.open_for => CircuitState::HalfOpen,
+            state => state,
+        };
+        CircuitSnapshot {
+            name: self.name,
+            state,
+            calls: breaker.outcomes.len(),
+            failures: breaker.outcomes.iter().filter(|failed| **failed).count(),
+        }
+    }
+
+    /// Runs `call` unless the circuit is open, recording whether it failed.
+    /// A call dropped before completing (e.g. on deadline) counts as failed:
+    /// a dependency that only hangs must still open the circuit.
+    pub async fn call<T, F>(&self, call: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let permit = self.acquire()?;
+        let result = call.await;
+        permit.record(result.is_err());
+        result
+    }
+
+    fn acquire(&self) -> Result<Permit<'_>, CircuitOpen> {
+        let mut breaker = self.breaker.lock().unwrap_or_else(PoisonError::into_inner);
+        match breaker.state {
+            CircuitState::Closed => {}
+            CircuitState::Open if breaker.opened_at.elapsed() < self.config.open_for => {
+                return Err(CircuitOpen { name: self.name });
+            }
+            CircuitState::Open => {
+                info!("Circuit breaker for {} is half-open", self.name);
+                breaker.state = CircuitState::HalfOpen;
+                breaker.probing = true;
+            }
+            CircuitState::HalfOpen if breaker.probing => return Err(CircuitOpen { name: self.name }),
+            CircuitState::HalfOpen => breaker.probing = true,
+        }
+        Ok(Permit { breaker: self, probe: breaker.state == CircuitState::HalfOpen, recorded: false })
+    }
+
+    fn record(&self, probe: bool, failed: bool) {
+        if self.config.window == 0 {
+            return;
+        }
+        let mut breaker = self.breaker.lock().unwrap_or_else(PoisonError::into_inner);
+        if probe {
+            breaker.probing = false;
+            if failed {
+                self.open(&mut breaker);
+            } else {
+                info!("Circuit breaker for {} is closed", self.name);
+                breaker.state = CircuitState::Closed;
+            }
+            return;
+        }
+        // Calls started before the circuit opened do not count
+        if breaker.state != CircuitState::Closed {
+            return;
+        }
+
+        breaker.outcomes.push_back(failed);
+        if breaker.outcomes.len() > self.config.window {
+            breaker.outcomes.pop_front();
+        }
+        let failures = breaker.outcomes.iter().filter(|failed| **failed).count();
+        if breaker.outcomes.len() == self.config.window
+            && failures as f64 >= self.config.failure_rate * self.config.window as f64
+        {
+            self.open(&mut breaker);
+        }
+    }
+
+    /// Gives back a permit whose call was cancelled. A cancelled probe lets
+    /// the next call probe instead.
+    fn release(&self, probe: bool) {
+        if probe {
+            self.breaker.lock().unwrap_or_else(PoisonError::into_inner).probing = false;
+        }
+    }
+
+    fn open(&self, breaker: &mut Breaker) {
+        warn!("Circuit breaker for {} is open for {:?}", self.name, self.config.open_for);
+        breaker.state = CircuitState::Open;
+        breaker.opened_at = Instant::now();
+        breaker.outcomes.clear();
+    }
+}
+
+/// Allows one call through the breaker. Dropping it without recording an
+/// outcome, as when the request's deadline cancels the call, records
+/// nothing: clients choose their deadlines, so a short one must not open a
+/// circuit shared by every caller. Hung dependencies are caught by their
+/// own timeout (`DependencyTimeout`), which is recorded as a failure.
+struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    recorded: bool,
+}
+
+impl Permit<'_> {
+    fn record(mut self, failed: bool) {
+        self.recorded = true;
+        self.breaker.record(self.probe, failed);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if !self.recorded {
+            self.breaker.release(self.probe);
+        }
+    }
+}
+
+/// Runs every call to the wrapped repository or cache through a breaker.
+#[derive(Clone)]
+pub struct Guarded<T> {
+    inner: T,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl<T> Guarded<T> {
+    pub fn new(inner: T, breaker: Arc<CircuitBreaker>) -> Self {
+        Self { inner, breaker }
+    }
+
+    pub fn breaker(&self) -> &Arc<CircuitBreaker> {
+        &self.breaker
+    }
+}
+
+#[async_trait]
+impl<R: ProfileRepositoryTrait> ProfileRepositoryTrait for Guarded<R> {
+    async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
+        self.breaker.call(self.inner.get_by_id(client_id)).await
+    }
+
+    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>> {
+        self.breaker.call(self.inner.get_iva_rate(jurisdiction)).await
+    }
+}
+
+#[async_trait]
+impl<C: ProfileCacheTrait> ProfileCacheTrait for Guarded<C> {
+    async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
+        self.breaker.call(self.inner.get_by_id(client_id)).await
+    }
+
+    async fn set(&self, profile: &Profile) -> Result<()> {
+        self.breaker.call(self.inner.set(profile)).await
+    }
+
+    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>> {
+        self.breaker.call(self.inner.get_iva_rate(jurisdiction)).await
+    }
+
+    async fn set_iva_rate(&self, rate: &IvaRate) -> Result<()> {
+        self.breaker.call(self.inner.set_iva_rate(rate)).await
+    }
+
+    async fn lookup_profile(&self, client_id: &str) -> Result<CacheLookup<Profile>> {
+        self.breaker.call(self.inner.lookup_profile(client_id)).await
+    }
+
+    async fn set_profile_not_found(&self, client_id: &str) -> Result<()> {
+        self.breaker.call(self.inner.set_profile_not_found(client_id)).await
+    }
+
+    async fn lookup_iva_rate(&self, jurisdiction: &str) -> Result<CacheLookup<IvaRate>> {
+        self.breaker.call(self.inner.lookup_iva_rate(jurisdiction)).await
+    }
+
+    async fn set_iva_rate_not_found(&self, jurisdiction: &str) -> Result<()> {
+        self.breaker.call(self.inner.set_iva_rate_not_found(jurisdiction)).await
+    }
+
+    async fn get_stale_profile(&self, client_id: &str) -> Result<Option<StaleEntry<Profile>>> {
+        self.breaker.call(self.inner.get_stale_profile(client_id)).await
+    }
+
+    async fn get_stale_iva_rate(&self, jurisdiction: &str) -> Result<Option<StaleEntry<IvaRate>>> {
+        self.breaker.call(self.inner.get_stale_iva_rate(jurisdiction)).await
+    }
+
+    async fn profile_generation(&self, client_id: &str) -> Result<Generation> {
+        self.breaker.call(self.inner.profile_generation(client_id)).await
+    }
+
+    async fn fill_profile(&self, client_id: &str, profile: Option<&Profile>, generation: Generation) -> Result<()> {
+        self.breaker.call(self.inner.fill_profile(client_id, profile, generation)).await
+    }
+
+    async fn iva_rate_generation(&self, jurisdiction: &str) -> Result<Generation> {
+        self.breaker.call(self.inner.iva_rate_generation(jurisdiction)).await
+    }
+
+    async fn fill_iva_rate(&self, jurisdiction: &str, rate: Option<&IvaRate>, generation: Generation) -> Result<()> {
+        self.breaker.call(self.inner.fill_iva_rate(jurisdiction, rate, generation)).await
+    }
+
+    async fn invalidate_profile(&self, client_id: &str) -> Result<()> {
+        self.breaker.call(self.inner.invalidate_profile(client_id)).await
+    }
+
+    async fn invalidate_iva_rate(&self, jurisdiction: &str) -> Result<()> {
+        self.breaker.call(self.inner.invalidate_iva_rate(jurisdiction)).await
+    }
+
+    async fn invalidate_all_profiles(&self) -> Result<()> {
+        self.breaker.call(self.inner.invalidate_all_profiles()).await
+    }
+
+    async fn invalidate_all_iva_rates(&self) -> Result<()> {
+        self.breaker.call(self.inner.invalidate_all_iva_rates()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::errors::ErrorCode;
+    use crate::app::timeout::{DependencyTimeout, TimeLimited};
+    use crate::test_support::{MockRepo, SlowRepo};
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new("postgres", CircuitBreakerConfig::default())
+    }
+
+    async fn ok(breaker: &CircuitBreaker) -> Result<()> {
+        breaker.call(async { Ok(()) }).await
+    }
+
+    async fn fail(breaker: &CircuitBreaker) -> Result<()> {
+        breaker.call(async { anyhow::bail!("connection refused") }).await
+    }
+
+    #[tokio::test]
+    async fn test_opens_at_half_failures_of_a_full_window() {
+        let breaker = breaker();
+        for _ in 0..5 {
+            ok(&breaker).await.unwrap();
+        }
+        for _ in 0..4 {
+            fail(&breaker).await.unwrap_err();
+        }
+        // 4 of 9, then 4 of 10
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        ok(&breaker).await.unwrap();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        // The oldest success leaves the window: 5 of 10
+        fail(&breaker).await.unwrap_err();
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        let err = breaker.call::<(), _>(async { unreachable!("must not run") }).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<CircuitOpen>(), Some(&CircuitOpen { name: "postgres" }));
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::Unavailable);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_trial_call_closes_or_reopens() {
+        let breaker = breaker();
+        for _ in 0..10 {
+            fail(&breaker).await.unwrap_err();
+        }
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        fail(&breaker).await.unwrap_err();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(ok(&breaker).await.unwrap_err().is::<CircuitOpen>());
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        ok(&breaker).await.unwrap();
+        let snapshot = breaker.snapshot();
+        assert_eq!(snapshot.state, CircuitState::Closed);
+        assert_eq!((snapshot.calls, snapshot.failures), (0, 0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_half_open_lets_one_call_through() {
+        let breaker = breaker();
+        for _ in 0..10 {
+            fail(&breaker).await.unwrap_err();
+        }
+        tokio::time::advance(Duration::from_secs(5)).await;
+
+        let slow = breaker.call(async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        });
+        let (trial, other) = tokio::join!(
+            tokio::time::timeout(Duration::from_millis(10), slow),
+            async {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                ok(&breaker).await
+            }
+        );
+        assert!(trial.is_err());
+        assert!(other.unwrap_err().is::<CircuitOpen>());
+
+        // The abandoned trial counts for nothing: the next call probes
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        ok(&breaker).await.unwrap();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_calls_dropped_on_deadline_are_neutral() {
+        let breaker = breaker();
+        for _ in 0..10 {
+            let hung = breaker.call(std::future::pending::<Result<()>>());
+            assert!(tokio::time::timeout(Duration::from_millis(10), hung).await.is_err());
+        }
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        ok(&breaker).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dependency_timeouts_count_as_failures() {
+        let slow = SlowRepo::new(Duration::from_secs(1));
+        let guarded = Guarded::new(TimeLimited::new(slow, "postgres", Duration::from_millis(5)), Arc::new(breaker()));
+
+        for _ in 0..10 {
+            assert!(guarded.get_by_id("c1").await.unwrap_err().is::<DependencyTimeout>());
+        }
+        assert_eq!(guarded.breaker().state(), CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn test_zero_window_never_opens() {
+        let breaker = CircuitBreaker::new("redis", CircuitBreakerConfig { window: 0, ..Default::default() });
+        for _ in 0..20 {
+            fail(&breaker).await.unwrap_err();
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_guarded_repository_stops_calling_when_open() {
+        let mut repo = MockRepo::new();
+        repo.expect_get_by_id().times(10).returning(|_| Err(anyhow::anyhow!("timeout")));
+        let guarded = Guarded::new(repo, Arc::new(breaker()));
+
+        for _ in 0..10 {
+            assert_eq!(guarded.get_by_id("c1").await.unwrap_err().to_string(), "timeout");
+        }
+        assert!(guarded.get_by_id("c1").await.unwrap_err().is::<CircuitOpen>());
+        assert_eq!(guarded.breaker().state(), CircuitState::Open);
+    }
+}
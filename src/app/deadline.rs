@@ -0,0 +1,101 @@
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// Returned when a request does not complete before its deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("request deadline exceeded")
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+/// Range the `timeoutMs` sent by a client is clamped to. Too short a
+/// deadline would only cancel dependency calls; too long a one would hold
+/// server resources for a client that gave up long ago.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutBounds {
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl Default for TimeoutBounds {
+    /// Enough for a cache miss with its retries; at most 10 seconds.
+    fn default() -> Self {
+        Self { min: Duration::from_millis(20), max: Duration::from_secs(10) }
+    }
+}
+
+impl TimeoutBounds {
+    pub fn clamp(&self, timeout: Duration) -> Duration {
+        timeout.clamp(self.min, self.max)
+    }
+}
+
+/// Runs `fut` with `deadline` as the current request deadline. If it expires
+/// first, `fut` is dropped, which cancels any dependency call it is awaiting.
+pub async fn run_until<F: Future>(deadline: Instant, fut: F) -> Result<F::Output, DeadlineExceeded> {
+    DEADLINE
+        .scope(deadline, async move {
+            tokio::time::timeout_at(deadline, fut).await.map_err(|_| DeadlineExceeded)
+        })
+        .await
+}
+
+/// Deadline of the request being processed by the current task, if any.
+pub fn current() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Time left before the current request's deadline, if it has one.
+pub fn remaining() -> Option<Duration> {
+    current().map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_until_completes_before_deadline() {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let res = run_until(deadline, async { 42 }).await;
+        assert_eq!(res, Ok(42));
+    }
+
+    #[tokio::test]
+    async fn test_run_until_deadline_exceeded() {
+        let deadline = Instant::now() + Duration::from_millis(10);
+        let res = run_until(deadline, tokio::time::sleep(Duration::from_secs(1))).await;
+        assert_eq!(res, Err(DeadlineExceeded));
+    }
+
+    #[test]
+    fn test_timeout_bounds_clamp() {
+        let bounds = TimeoutBounds::default();
+        assert_eq!(bounds.clamp(Duration::from_millis(1)), Duration::from_millis(20));
+        assert_eq!(bounds.clamp(Duration::from_millis(250)), Duration::from_millis(250));
+        assert_eq!(bounds.clamp(Duration::from_secs(3600)), Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_current_deadline_is_scoped() {
+        assert!(current().is_none());
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let inside = run_until(deadline, async { (current(), remaining()) }).await.unwrap();
+
+        assert_eq!(inside.0, Some(deadline));
+        assert!(inside.1.unwrap() <= Duration::from_secs(1));
+        assert!(current().is_none());
+    }
+}
@@ -0,0 +1,100 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::time::Duration;
+
+tokio::task_local! {
+    static DEGRADATION: Cell<Degradation>;
+}
+
+/// How a request was served when a dependency failed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Degradation {
+    /// The cache failed and values were read from the database instead.
+    pub cache_failed: bool,
+    /// The database failed and values were taken from stale cache copies;
+    /// the age of the oldest one.
+    pub stale_age: Option<Duration>,
+}
+
+/// Runs `fut` and reports whether any step of it was served in degraded mode.
+pub async fn track<F: Future>(fut: F) -> (F::Output, Degradation) {
+    DEGRADATION
+        .scope(Cell::new(Degradation::default()), async move {
+            let output = fut.await;
+            (output, DEGRADATION.with(Cell::get))
+        })
+        .await
+}
+
+/// Flags the request being processed by the current task as served without
+/// the cache. Does nothing outside `track`.
+pub fn mark() {
+    update(|degradation| degradation.cache_failed = true);
+}
+
+/// Flags the request as computed from stale data `age` old.
+pub fn mark_stale(age: Duration) {
+    update(|degradation| degradation.stale_age = degradation.stale_age.max(Some(age)));
+}
+
+/// Flags the request with everything `other` reports, e.g. the degradation
+/// of a load shared with other requests.
+pub fn merge(other: Degradation) {
+    update(|degradation| {
+        degradation.cache_failed |= other.cache_failed;
+        degradation.stale_age = degradation.stale_age.max(other.stale_age);
+    });
+}
+
+fn update(f: impl FnOnce(&mut Degradation)) {
+    let _ = DEGRADATION.try_with(|cell| {
+        let mut degradation = cell.get();
+        f(&mut degradation);
+        cell.set(degradation);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_marks_are_scoped_to_the_tracked_future() {
+        mark();
+
+        let ((), degradation) = track(async {}).await;
+        assert_eq!(degradation, Degradation::default());
+
+        let ((), degradation) = track(async {
+            tokio::task::yield_now().await;
+            mark();
+        })
+        .await;
+        assert!(degradation.cache_failed);
+        assert_eq!(degradation.stale_age, None);
+    }
+
+    #[tokio::test]
+    async fn test_merge_keeps_both_flags() {
+        let ((), degradation) = track(async {
+            mark_stale(Duration::from_secs(90));
+            merge(Degradation { cache_failed: true, stale_age: Some(Duration::from_secs(30)) });
+        })
+        .await;
+
+        assert!(degradation.cache_failed);
+        assert_eq!(degradation.stale_age, Some(Duration::from_secs(90)));
+    }
+
+    #[tokio::test]
+    async fn test_keeps_the_oldest_stale_age() {
+        let ((), degradation) = track(async {
+            mark_stale(Duration::from_secs(90));
+            mark_stale(Duration::from_secs(30));
+        })
+        .await;
+
+        assert!(!degradation.cache_failed);
+        assert_eq!(degradation.stale_age, Some(Duration::from_secs(90)));
+    }
+}
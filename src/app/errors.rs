@@ -0,0 +1,137 @@
+use std::fmt;
+
+use crate::app::circuit_breaker::CircuitOpen;
+use crate::app::deadline::DeadlineExceeded;
+use crate::app::timeout::DependencyTimeout;
+
+/// Error codes reported to RPC clients.
+///
+/// Cap'n Proto exceptions only carry a kind and a description, so the code is
+/// sent as a prefix of the description (e.g. `OVERLOADED: rate limit exceeded`).
+/// Codes that have a matching exception kind also use it, so generic clients
+/// can react without parsing the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The request is malformed or fails validation.
+    InvalidRequest,
+    /// `Gateway.login` was called with an unknown token.
+    Unauthenticated,
+    /// The consumer is not allowed to act on the requested client.
+    PermissionDenied,
+    /// The referenced profile or rate does not exist.
+    NotFound,
+    /// The profile or rate being created already exists.
+    AlreadyExists,
+    /// Connection, in-flight or rate limit exceeded; retry later.
+    Overloaded,
+    /// The request did not complete before its deadline.
+    Timeout,
+    /// The server is shutting down or a dependency's circuit breaker is
+    /// open; retry later or on another instance.
+    Unavailable,
+    /// Unexpected failure while calculating.
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest => "INVALID_REQUEST",
+            ErrorCode::Unauthenticated => "UNAUTHENTICATED",
+            ErrorCode::PermissionDenied => "PERMISSION_DENIED",
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::AlreadyExists => "ALREADY_EXISTS",
+            ErrorCode::Overloaded => "OVERLOADED",
+            ErrorCode::Timeout => "TIMEOUT",
+            ErrorCode::Unavailable => "UNAVAILABLE",
+            ErrorCode::Internal => "INTERNAL",
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<Self> {
+        [
+            ErrorCode::InvalidRequest,
+            ErrorCode::Unauthenticated,
+            ErrorCode::PermissionDenied,
+            ErrorCode::NotFound,
+            ErrorCode::AlreadyExists,
+            ErrorCode::Overloaded,
+            ErrorCode::Timeout,
+            ErrorCode::Unavailable,
+            ErrorCode::Internal,
+        ]
+        .into_iter()
+        .find(|candidate| candidate.as_str() == code)
+    }
+
+    /// Classifies an error returned by the application services.
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if let Some(service_error) = cause.downcast_ref::<ServiceError>() {
+                return service_error.code;
+            }
+            if cause.is::<DeadlineExceeded>() || cause.is::<DependencyTimeout>() {
+                return ErrorCode::Timeout;
+            }
+            if cause.is::<CircuitOpen>() {
+                return ErrorCode::Unavailable;
+            }
+        }
+        ErrorCode::Internal
+    }
+
+    /// The exception for this code, with the code as a prefix of the
+    /// description. `Unavailable` is raised as overloaded rather than
+    /// disconnected: the connection still works, only the call should be
+    /// retried later.
+    pub fn to_capnp(self, message: impl fmt::Display) -> capnp::Error {
+        let description = format!("{}: {}", self.as_str(), message);
+        match self {
+            ErrorCode::Overloaded | ErrorCode::Unavailable => capnp::Error::overloaded(description),
+            _ => capnp::Error::failed(description),
+        }
+    }
+
+    /// Status used by the HTTP/JSON API.
+    pub fn http_status(self) -> u16 {
+        match self {
+            ErrorCode::InvalidRequest => 400,
+            ErrorCode::Unauthenticated => 401,
+            ErrorCode::PermissionDenied => 403,
+            ErrorCode::NotFound => 404,
+            ErrorCode::AlreadyExists => 409,
+            ErrorCode::Overloaded => 429,
+            ErrorCode::Internal => 500,
+            ErrorCode::Unavailable => 503,
+            ErrorCode::Timeout => 504,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An error raised by the application layer with a specific code, e.g. a
+/// validation failure or a missing entity.
+#[derive(Debug)]
+pub struct ServiceError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ServiceError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ServiceError {}
@@ -0,0 +1,221 @@
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::app::circuit_breaker::{CircuitBreaker, CircuitSnapshot, CircuitState};
+use crate::app::shutdown::Shutdown;
+use crate::domain::traits::HealthCheckTrait;
+
+/// Upper bound for a single dependency probe.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum HealthStatus {
+    Serving,
+    /// A non-critical dependency is failing; calculations still succeed.
+    Degraded,
+    NotServing,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyHealth {
+    pub name: &'static str,
+    pub status: HealthStatus,
+    pub critical: bool,
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    pub dependencies: Vec<DependencyHealth>,
+    /// Probes bypass the breakers, so a circuit that is not closed makes the
+    /// report at least `DEGRADED` on its own.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub circuits: Vec<CircuitSnapshot>,
+}
+
+impl HealthReport {
+    /// Whether the instance should receive traffic.
+    pub fn is_ready(&self) -> bool {
+        self.status != HealthStatus::NotServing
+    }
+}
+
+struct RegisteredCheck {
+    check: Arc<dyn HealthCheckTrait>,
+    critical: bool,
+}
+
+/// Probes the service dependencies for the `Health` RPC interface and the
+/// `/readyz` endpoint. A failing critical dependency makes the instance
+/// `NOT_SERVING`; a failing non-critical one only `DEGRADED`.
+#[derive(Default)]
+pub struct HealthService {
+    checks: Vec<RegisteredCheck>,
+    circuits: Vec<Arc<CircuitBreaker>>,
+    shutdown: Shutdown,
+}
+
+impl HealthService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_check(mut self, check: Arc<dyn HealthCheckTrait>, critical: bool) -> Self {
+        self.checks.push(RegisteredCheck { check, critical });
+        self
+    }
+
+    /// Includes the breaker's state in every report.
+    pub fn with_circuit(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.circuits.push(breaker);
+        self
+    }
+
+    /// Reports `NOT_SERVING` once shutdown starts so traffic is routed away
+    /// while in-flight calls drain.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub async fn check(&self) -> HealthReport {
+        let dependencies = join_all(self.checks.iter().map(|registered| async move {
+            let started = Instant::now();
+            let outcome = tokio::time::timeout(CHECK_TIMEOUT, registered.check.ping()).await;
+            let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+            let error = match outcome {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(format!("{:#}", e)),
+                Err(_) => Some(format!("no response within {:?}", CHECK_TIMEOUT)),
+            };
+
+            DependencyHealth {
+                name: registered.check.name(),
+                status: if error.is_none() { HealthStatus::Serving } else { HealthStatus::NotServing },
+                critical: registered.critical,
+                latency_ms,
+                error,
+            }
+        }))
+        .await;
+
+        // A probe can succeed while calls still time out; the breakers see those
+        let circuits: Vec<CircuitSnapshot> = self.circuits.iter().map(|breaker| breaker.snapshot()).collect();
+        let status = if self.shutdown.is_triggered()
+            || dependencies.iter().any(|d| d.critical && d.status != HealthStatus::Serving)
+        {
+            HealthStatus::NotServing
+        } else if dependencies.iter().any(|d| d.status != HealthStatus::Serving)
+            || circuits.iter().any(|c| c.state != CircuitState::Closed)
+        {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Serving
+        };
+
+        HealthReport { status, dependencies, circuits }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use async_trait::async_trait;
+
+    struct FakeCheck {
+        name: &'static str,
+        healthy: bool,
+    }
+
+    #[async_trait]
+    impl HealthCheckTrait for FakeCheck {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn ping(&self) -> Result<()> {
+            if self.healthy {
+                Ok(())
+            } else {
+                anyhow::bail!("connection refused")
+            }
+        }
+    }
+
+    fn service(db_healthy: bool, cache_healthy: bool) -> HealthService {
+        HealthService::new()
+            .with_check(Arc::new(FakeCheck { name: "postgres", healthy: db_healthy }), true)
+            .with_check(Arc::new(FakeCheck { name: "redis", healthy: cache_healthy }), false)
+    }
+
+    #[tokio::test]
+    async fn test_all_dependencies_healthy() {
+        let report = service(true, true).check().await;
+
+        assert_eq!(report.status, HealthStatus::Serving);
+        assert!(report.is_ready());
+        assert!(report.dependencies.iter().all(|d| d.error.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_non_critical_failure_is_degraded() {
+        let report = service(true, false).check().await;
+
+        assert_eq!(report.status, HealthStatus::Degraded);
+        assert!(report.is_ready());
+        let redis = report.dependencies.iter().find(|d| d.name == "redis").unwrap();
+        assert_eq!(redis.status, HealthStatus::NotServing);
+        assert_eq!(redis.error.as_deref(), Some("connection refused"));
+    }
+
+    #[tokio::test]
+    async fn test_critical_failure_is_not_serving() {
+        let report = service(false, true).check().await;
+
+        assert_eq!(report.status, HealthStatus::NotServing);
+        assert!(!report.is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_reports_circuit_state() {
+        let breaker = Arc::new(CircuitBreaker::new("redis", Default::default()));
+        let report = service(true, true).with_circuit(breaker).check().await;
+
+        assert_eq!(report.status, HealthStatus::Serving);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json["circuits"],
+            serde_json::json!([{"name": "redis", "state": "CLOSED", "calls": 0, "failures": 0}])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_is_degraded() {
+        let breaker = Arc::new(CircuitBreaker::new("postgres", Default::default()));
+        for _ in 0..10 {
+            breaker.call::<(), _>(async { anyhow::bail!("timed out") }).await.unwrap_err();
+        }
+        // The probe itself still succeeds
+        let report = service(true, true).with_circuit(breaker).check().await;
+
+        assert_eq!(report.status, HealthStatus::Degraded);
+        assert!(report.is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_not_ready_while_shutting_down() {
+        let shutdown = Shutdown::new();
+        let service = service(true, true).with_shutdown(shutdown.clone());
+        shutdown.trigger();
+
+        assert_eq!(service.check().await.status, HealthStatus::NotServing);
+    }
+}
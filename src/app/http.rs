@@ -0,0 +1,539 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
+
+use crate::app::api::{CalculateApiTrait, CalculateRequest};
+use crate::app::errors::ErrorCode;
+use crate::app::health::HealthService;
+use crate::app::server::accept_failed;
+use crate::app::tls;
+
+/// Largest request head accepted; probes send a few hundred bytes at most.
+const MAX_HEAD_BYTES: usize = 8 * 1024;
+/// Largest request body accepted; a calculation request is well under 1 KiB.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+/// Bounds reading one request, including the idle time before it on a
+/// kept-alive connection.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Minimal HTTP/1.1 listener for Kubernetes probes and JSON clients:
+///
+/// - `GET /livez`: 200 while the process is running.
+/// - `GET /readyz`: checks every dependency and answers 200 (`SERVING` or
+///   `DEGRADED`) or 503 (`NOT_SERVING`) with the JSON `HealthReport`.
+/// - `POST /v1/calculate`: when an API is configured, `TaxEngine.calculate`
+///   with a JSON body; errors answer `{"code", "message"}` with the status of
+///   the `ErrorCode`.
+///
+/// Connections are kept alive unless the client asks otherwise. The API is
+/// served on its own listener (`API_ADDR`), not on the probe one.
+pub struct HttpServer {
+    listener: TcpListener,
+    addr: SocketAddr,
+    health: Arc<HealthService>,
+    api: Option<Arc<dyn CalculateApiTrait>>,
+    tls: Option<TlsAcceptor>,
+    connections: Option<Arc<Semaphore>>,
+}
+
+impl HttpServer {
+    /// Binds `addr` right away, so an unavailable address fails startup
+    /// instead of leaving the process without probes.
+    pub async fn bind(addr: SocketAddr, health: Arc<HealthService>) -> Result<Self> {
+        let listener =
+            TcpListener::bind(&addr).await.with_context(|| format!("Failed to bind HTTP listener on {}", addr))?;
+        let addr = listener.local_addr()?;
+        Ok(Self { listener, addr, health, api: None, tls: None, connections: None })
+    }
+
+    /// The bound address, with the actual port when `0` was requested.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Serves `POST /v1/calculate` with `api`.
+    pub fn with_api(mut self, api: Arc<dyn CalculateApiTrait>) -> Self {
+        self.api = Some(api);
+        self
+    }
+
+    /// Runs the TLS handshake on every connection.
+    pub fn with_tls(mut self, acceptor: TlsAcceptor) -> Self {
+        self.tls = Some(acceptor);
+        self
+    }
+
+    /// Closes connections beyond `max_connections` open ones right after
+    /// accepting them.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.connections = Some(Arc::new(Semaphore::new(max_connections)));
+        self
+    }
+
+    pub async fn run(self) -> Result<()> {
+        info!(
+            "HTTP server listening on {} (calculate API {}, {})",
+            self.addr,
+            if self.api.is_some() { "enabled" } else { "disabled" },
+            if self.tls.is_some() { "TLS" } else { "plaintext" }
+        );
+
+        loop {
+            let (stream, peer) = match self.listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    accept_failed("HTTP", &e).await;
+                    continue;
+                }
+            };
+            let permit = match &self.connections {
+                Some(connections) => match connections.clone().try_acquire_owned() {
+                    Ok(permit) => Some(permit),
+                    Err(_) => {
+                        warn!("Dropping HTTP connection from {}: connection limit reached", peer);
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            if let Err(e) = stream.set_nodelay(true) {
+                debug!("Dropping HTTP connection from {}: {}", peer, e);
+                continue;
+            }
+            let health = self.health.clone();
+            let api = self.api.clone();
+            let tls = self.tls.clone();
+            tokio::spawn(async move {
+                let _permit = permit;
+                let served = match tls {
+                    Some(acceptor) => match tls::accept(&acceptor, stream).await {
+                        Ok((stream, _)) => handle_connection(stream, &health, api.as_deref()).await,
+                        Err(e) => Err(e),
+                    },
+                    None => handle_connection(stream, &health, api.as_deref()).await,
+                };
+                if let Err(e) = served {
+                    debug!("HTTP request from {} failed: {:#}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Request {
+    method: String,
+    path: String,
+    /// Header names are lowercased.
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    keep_alive: bool,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    fn bearer_token(&self) -> Option<&str> {
+        let value = self.header("authorization")?;
+        let (scheme, token) = value.split_once(' ')?;
+        scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+    }
+}
+
+/// Outcome of reading from a connection.
+enum Incoming {
+    Request(Request),
+    /// The client closed the connection between requests.
+    Closed,
+    /// The request cannot be served; the connection is closed after this.
+    Invalid(Response),
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    message: String,
+}
+
+impl Response {
+    fn text(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn json(status: u16, body: &impl Serialize) -> Self {
+        match serde_json::to_vec(body) {
+            Ok(body) => Self {
+                status,
+                content_type: "application/json",
+                body,
+            },
+            Err(_) => Self::text(500, "serialization error\n"),
+        }
+    }
+
+    fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::json(code.http_status(), &ErrorBody { code: code.as_str(), message: message.into() })
+    }
+
+    fn into_bytes(self, keep_alive: bool) -> Vec<u8> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            415 => "Unsupported Media Type",
+            429 => "Too Many Requests",
+            431 => "Request Header Fields Too Large",
+            501 => "Not Implemented",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            _ => "Internal Server Error",
+        };
+        let mut bytes = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: {}\r\n\r\n",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len(),
+            if keep_alive { "keep-alive" } else { "close" }
+        )
+        .into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+async fn handle_connection<S>(mut stream: S, health: &HealthService, api: Option<&dyn CalculateApiTrait>) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // Bytes read past the end of the previous request (pipelining)
+    let mut buf = Vec::with_capacity(512);
+    loop {
+        let incoming = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream, &mut buf)).await {
+            Ok(incoming) => incoming?,
+            // An idle kept-alive connection is simply closed
+            Err(_) if buf.is_empty() => Incoming::Closed,
+            Err(_) => anyhow::bail!("timed out reading request"),
+        };
+
+        let (response, keep_alive) = match incoming {
+            Incoming::Request(request) => (route(&request, health, api).await, request.keep_alive),
+            Incoming::Closed => return Ok(()),
+            Incoming::Invalid(response) => (response, false),
+        };
+        stream.write_all(&response.into_bytes(keep_alive)).await?;
+        if !keep_alive {
+            stream.shutdown().await?;
+            return Ok(());
+        }
+    }
+}
+
+/// Reads the next request from `stream`, starting with the bytes left in
+/// `buf`, and leaves in `buf` whatever follows it.
+async fn read_request<S>(stream: &mut S, buf: &mut Vec<u8>) -> Result<Incoming>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        if buf.len() > MAX_HEAD_BYTES {
+            return Ok(Incoming::Invalid(Response::text(431, "request head too large\n")));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            if buf.is_empty() {
+                return Ok(Incoming::Closed);
+            }
+            anyhow::bail!("connection closed before end of request head");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let Some(mut request) = parse_request(&buf[..head_end]) else {
+        return Ok(Incoming::Invalid(Response::text(400, "malformed request\n")));
+    };
+    if request.header("transfer-encoding").is_some() {
+        return Ok(Incoming::Invalid(Response::text(501, "chunked bodies are not supported\n")));
+    }
+    let body_len = match request.header("content-length").map(str::parse::<usize>) {
+        None => 0,
+        Some(Ok(len)) if len <= MAX_BODY_BYTES => len,
+        Some(Ok(_)) => return Ok(Incoming::Invalid(Response::text(413, "request body too large\n"))),
+        Some(Err(_)) => return Ok(Incoming::Invalid(Response::text(400, "invalid content-length\n"))),
+    };
+
+    let body_start = head_end + 4;
+    while buf.len() < body_start + body_len {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            anyhow::bail!("connection closed before end of request body");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    request.body = buf[body_start..body_start + body_len].to_vec();
+    buf.drain(..body_start + body_len);
+    Ok(Incoming::Request(request))
+}
+
+fn parse_request(head: &[u8]) -> Option<Request> {
+    let head = std::str::from_utf8(head).ok()?;
+    let mut lines = head.lines();
+    let mut parts = lines.next()?.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?;
+    let version = parts.next()?;
+    if !version.starts_with("HTTP/1.") {
+        return None;
+    }
+    let path = target.split('?').next().unwrap_or(target).to_string();
+
+    let mut headers = Vec::new();
+    for line in lines {
+        let (name, value) = line.split_once(':')?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+
+    let mut request = Request { method, path, headers, body: Vec::new(), keep_alive: false };
+    // HTTP/1.1 keeps the connection open by default, HTTP/1.0 closes it
+    request.keep_alive = match request.header("connection") {
+        Some(value) if value.eq_ignore_ascii_case("close") => false,
+        Some(value) if value.eq_ignore_ascii_case("keep-alive") => true,
+        _ => version == "HTTP/1.1",
+    };
+    Some(request)
+}
+
+async fn route(request: &Request, health: &HealthService, api: Option<&dyn CalculateApiTrait>) -> Response {
+    if request.path == "/v1/calculate"
+        && let Some(api) = api
+    {
+        return match request.method.as_str() {
+            "POST" => calculate(request, api).await,
+            _ => Response::text(405, "method not allowed\n"),
+        };
+    }
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/livez") => Response::text(200, "ok\n"),
+        ("GET", "/readyz") => {
+            let report = health.check().await;
+            let status = if report.is_ready() { 200 } else { 503 };
+            Response::json(status, &report)
+        }
+        (_, "/livez") | (_, "/readyz") => Response::text(405, "method not allowed\n"),
+        _ => Response::text(404, "not found\n"),
+    }
+}
+
+async fn calculate(request: &Request, api: &dyn CalculateApiTrait) -> Response {
+    let received_at = Instant::now();
+    if let Some(content_type) = request.header("content-type")
+        && !content_type.starts_with("application/json")
+    {
+        return Response::text(415, "expected application/json\n");
+    }
+    let body: CalculateRequest = match serde_json::from_slice(&request.body) {
+        Ok(body) => body,
+        Err(e) => return Response::error(ErrorCode::InvalidRequest, format!("invalid request body: {}", e)),
+    };
+
+    match api.calculate(body, request.bearer_token(), received_at).await {
+        Ok(response) => Response::json(200, &response),
+        Err(e) => Response::error(ErrorCode::from_error(&e), e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::api::CalculateResponse;
+    use crate::app::errors::ServiceError;
+    use crate::domain::models::{TaxBreakdown, TaxType};
+    use async_trait::async_trait;
+    use rust_decimal::Decimal;
+
+    /// Calculates 21% for `c1` when called with `token`; any other client is
+    /// denied.
+    struct FixedApi;
+
+    #[async_trait]
+    impl CalculateApiTrait for FixedApi {
+        async fn calculate(
+            &self,
+            request: CalculateRequest,
+            token: Option<&str>,
+            _received_at: Instant,
+        ) -> Result<CalculateResponse> {
+            if token != Some("token") {
+                return Err(ServiceError::new(ErrorCode::Unauthenticated, "invalid token").into());
+            }
+            if request.client_id != "c1" {
+                return Err(ServiceError::new(ErrorCode::PermissionDenied, "not allowed").into());
+            }
+            let base = Decimal::try_from(request.amount)?;
+            let amount = base * Decimal::new(21, 2);
+            let breakdown = TaxBreakdown { tax_type: TaxType::IVA, base, rate: 0.21, amount };
+            Ok(CalculateResponse {
+                total_amount: request.amount * 0.21,
+                breakdown: vec![breakdown],
+                degraded: false,
+                stale: false,
+                stale_age_ms: None,
+            })
+        }
+    }
+
+    fn calculate_request(body: &str, token: &str) -> Request {
+        let head = format!(
+            "POST /v1/calculate HTTP/1.1\r\nContent-Type: application/json\r\nAuthorization: Bearer {}\r\nContent-Length: {}",
+            token,
+            body.len()
+        );
+        let mut request = parse_request(head.as_bytes()).unwrap();
+        request.body = body.as_bytes().to_vec();
+        request
+    }
+
+    #[test]
+    fn test_parse_request_line() {
+        let req = parse_request(b"GET /readyz?verbose=1 HTTP/1.1\r\nHost: localhost").unwrap();
+        assert_eq!(req.method, "GET");
+        assert_eq!(req.path, "/readyz");
+        assert_eq!(req.header("host"), Some("localhost"));
+        assert!(req.keep_alive);
+
+        let req = parse_request(b"GET /livez HTTP/1.0").unwrap();
+        assert!(!req.keep_alive);
+    }
+
+    #[test]
+    fn test_parse_request_rejects_garbage() {
+        assert!(parse_request(b"\x16\x03\x01").is_none());
+        assert!(parse_request(b"GET /livez").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_route_probes() {
+        let health = HealthService::new();
+
+        let live = route(&parse_request(b"GET /livez HTTP/1.1").unwrap(), &health, None).await;
+        assert_eq!(live.status, 200);
+
+        let ready = route(&parse_request(b"GET /readyz HTTP/1.1").unwrap(), &health, None).await;
+        assert_eq!(ready.status, 200);
+        assert_eq!(ready.content_type, "application/json");
+
+        let missing = route(&parse_request(b"GET /metrics HTTP/1.1").unwrap(), &health, None).await;
+        assert_eq!(missing.status, 404);
+
+        let disabled = route(&calculate_request("{}", "token"), &health, None).await;
+        assert_eq!(disabled.status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_route_calculate() {
+        let health = HealthService::new();
+        let body = r#"{"clientId": "c1", "amount": 100.0, "jurisdiction": "BUENOS_AIRES", "product": "PROD"}"#;
+
+        let ok = route(&calculate_request(body, "token"), &health, Some(&FixedApi)).await;
+        assert_eq!(ok.status, 200);
+        let json: serde_json::Value = serde_json::from_slice(&ok.body).unwrap();
+        assert_eq!(json["totalAmount"], 21.0);
+        assert_eq!(json["breakdown"][0]["taxType"], "IVA");
+
+        let unauthenticated = route(&calculate_request(body, "other"), &health, Some(&FixedApi)).await;
+        assert_eq!(unauthenticated.status, 401);
+        let json: serde_json::Value = serde_json::from_slice(&unauthenticated.body).unwrap();
+        assert_eq!(json["code"], "UNAUTHENTICATED");
+
+        let denied = route(&calculate_request(&body.replace("c1", "c2"), "token"), &health, Some(&FixedApi)).await;
+        assert_eq!(denied.status, 403);
+
+        let invalid = route(&calculate_request(r#"{"clientId": "c1"}"#, "token"), &health, Some(&FixedApi)).await;
+        assert_eq!(invalid.status, 400);
+        let json: serde_json::Value = serde_json::from_slice(&invalid.body).unwrap();
+        assert_eq!(json["code"], "INVALID_REQUEST");
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_serves_pipelined_requests() {
+        let (mut client, server) = tokio::io::duplex(16 * 1024);
+        let health = HealthService::new();
+        let serve = handle_connection(server, &health, None);
+
+        let exchange = async {
+            client
+                .write_all(b"GET /livez HTTP/1.1\r\n\r\nGET /livez HTTP/1.1\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let mut response = String::new();
+            client.read_to_string(&mut response).await.unwrap();
+            response
+        };
+        let (served, response) = tokio::join!(serve, exchange);
+
+        served.unwrap();
+        assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(response.contains("Connection: keep-alive"));
+        assert!(response.ends_with("Connection: close\r\n\r\nok\n"));
+    }
+
+    #[tokio::test]
+    async fn test_bind_fails_when_address_is_in_use() {
+        let health = Arc::new(HealthService::new());
+        let server = HttpServer::bind("127.0.0.1:0".parse().unwrap(), health.clone()).await.unwrap();
+
+        let err = HttpServer::bind(server.local_addr(), health).await.err().unwrap();
+        assert!(err.to_string().starts_with("Failed to bind HTTP listener"));
+    }
+
+    #[tokio::test]
+    async fn test_connections_beyond_the_limit_are_closed() {
+        let health = Arc::new(HealthService::new());
+        let server = HttpServer::bind("127.0.0.1:0".parse().unwrap(), health).await.unwrap().with_max_connections(1);
+        let addr = server.local_addr();
+        tokio::spawn(server.run());
+
+        let mut first = tokio::net::TcpStream::connect(addr).await.unwrap();
+        first.write_all(b"GET /livez HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = [0u8; 64];
+        let n = first.read(&mut response).await.unwrap();
+        assert!(response[..n].starts_with(b"HTTP/1.1 200 OK"));
+
+        // The first connection is kept alive and holds the only permit
+        let mut second = tokio::net::TcpStream::connect(addr).await.unwrap();
+        second.write_all(b"GET /livez HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut rest = Vec::new();
+        let closed = tokio::time::timeout(Duration::from_secs(1), second.read_to_end(&mut rest)).await.unwrap();
+        assert!(closed.is_err() || rest.is_empty());
+    }
+}
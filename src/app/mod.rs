@@ -1,3 +1,20 @@
 pub mod resolver;
 pub mod orchestrator;
 pub mod rpc;
+pub mod server;
+pub mod shutdown;
+pub mod errors;
+pub mod rate_limit;
+pub mod deadline;
+pub mod health;
+pub mod http;
+pub mod admin;
+pub mod changes;
+pub mod tls;
+pub mod auth;
+pub mod api;
+pub mod single_flight;
+pub mod circuit_breaker;
+pub mod retry;
+pub mod timeout;
+pub mod degradation;
@@ -1,8 +1,12 @@
-use crate::domain::models::{Transaction, TaxBreakdown};
+use crate::domain::models::{Profile, Transaction, TaxBreakdown, TaxType};
+use crate::app::deadline;
+use crate::app::errors::{ErrorCode, ServiceError};
 use crate::app::resolver::ProfileResolver;
 use crate::domain::calculators::IVACalculator;
 use crate::domain::traits::{ProfileRepositoryTrait, ProfileCacheTrait};
-use anyhow::{Context, Result};
+use anyhow::Result;
+use tokio::time::Instant;
+use std::collections::HashMap;
 use tracing::info;
 
 #[derive(Clone)]
@@ -33,50 +37,73 @@ where
         let profile = self.profile_resolver
             .resolve(&tx.client_id)
             .await?
-            .context("Profile not found")?;
+            .ok_or_else(|| ServiceError::new(ErrorCode::NotFound, "Profile not found"))?;
 
+        self.run_calculators(&tx, &profile, &HashMap::new()).await
+    }
+
+    /// What-if calculation for a prospect: runs the same calculators over an
+    /// inline `profile`, which is never read from or written to the DB or
+    /// cache. `rate_overrides` replace the resolved rate per tax type. The
+    /// result is non-binding and must not be recorded as a calculation.
+    pub async fn process_quote(
+        &self,
+        tx: Transaction,
+        profile: &Profile,
+        rate_overrides: &HashMap<TaxType, f64>,
+    ) -> Result<Vec<TaxBreakdown>> {
+        info!("Processing non-binding quote ({}, {})", profile.fiscal_category, tx.jurisdiction);
+
+        self.run_calculators(&tx, profile, rate_overrides).await
+    }
+
+    async fn run_calculators(
+        &self,
+        tx: &Transaction,
+        profile: &Profile,
+        rate_overrides: &HashMap<TaxType, f64>,
+    ) -> Result<Vec<TaxBreakdown>> {
         // Resolve IVA Rate based on jurisdiction
-        let jurisdiction_rate = self.profile_resolver
-            .resolve_iva_rate(&tx.jurisdiction)
-            .await?;
+        let jurisdiction_rate = match rate_overrides.get(&TaxType::IVA) {
+            Some(rate) => *rate,
+            None => self.profile_resolver.resolve_iva_rate(&tx.jurisdiction).await?,
+        };
 
         let mut breakdowns = Vec::new();
 
         // Calculate IVA
-        let iva = self.iva_calculator.calculate(&tx, &profile, jurisdiction_rate);
+        let iva = self.iva_calculator.calculate(tx, profile, jurisdiction_rate)?;
         breakdowns.push(iva);
 
         Ok(breakdowns)
     }
+
+    /// Runs `process_calculation` bounded by `deadline`. When it expires the
+    /// outstanding cache/DB calls are cancelled and the error wraps
+    /// `deadline::DeadlineExceeded`.
+    pub async fn process_calculation_until(&self, tx: Transaction, deadline: Instant) -> Result<Vec<TaxBreakdown>> {
+        deadline::run_until(deadline, self.process_calculation(tx)).await?
+    }
+
+    /// `process_quote` bounded by `deadline`.
+    pub async fn process_quote_until(
+        &self,
+        tx: Transaction,
+        profile: &Profile,
+        rate_overrides: &HashMap<TaxType, f64>,
+        deadline: Instant,
+    ) -> Result<Vec<TaxBreakdown>> {
+        deadline::run_until(deadline, self.process_quote(tx, profile, rate_overrides)).await?
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::domain::models::{Profile, IvaRate};
-    use mockall::mock;
-    use async_trait::async_trait;
+    use crate::test_support::{MockCache, MockRepo, StalledCache};
     use chrono::Local;
-
-    mock! {
-        pub Repo {}
-        #[async_trait]
-        impl ProfileRepositoryTrait for Repo {
-            async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>>;
-            async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>>;
-        }
-    }
-
-    mock! {
-        pub Cache {}
-        #[async_trait]
-        impl ProfileCacheTrait for Cache {
-            async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>>;
-            async fn set(&self, profile: &Profile) -> Result<()>;
-            async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>>;
-            async fn set_iva_rate(&self, rate: &IvaRate) -> Result<()>;
-        }
-    }
+    use rust_decimal::Decimal;
 
     #[tokio::test]
     async fn test_process_calculation_success() {
@@ -96,7 +123,7 @@ mod tests {
         let orchestrator = Orchestrator::new(profile_resolver, IVACalculator);
 
         let tx = Transaction {
-            amount: 100.0,
+            amount: Decimal::from(100),
             product: "P".to_string(),
             jurisdiction: "J1".to_string(),
             client_id: "c1".to_string(),
@@ -105,7 +132,7 @@ mod tests {
 
         let res = orchestrator.process_calculation(tx).await.unwrap();
         assert_eq!(res.len(), 1);
-        assert_eq!(res[0].amount, 21.0);
+        assert_eq!(res[0].amount, Decimal::from(21));
     }
 
     #[tokio::test]
@@ -120,14 +147,59 @@ mod tests {
         let orchestrator = Orchestrator::new(profile_resolver, IVACalculator);
 
         let tx = Transaction {
-            amount: 100.0,
+            amount: Decimal::from(100),
             product: "P".to_string(),
             jurisdiction: "J1".to_string(),
             client_id: "unknown".to_string(),
             date: Local::now().date_naive(),
         };
 
-        let res = orchestrator.process_calculation(tx).await;
-        assert!(res.is_err());
+        let err = orchestrator.process_calculation(tx).await.unwrap_err();
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::NotFound);
+        assert_eq!(err.to_string(), "Profile not found");
+    }
+
+    #[tokio::test]
+    async fn test_process_quote_does_not_touch_profiles() {
+        // Any profile lookup or cache write would hit an unexpected mock call
+        let mock_db = MockRepo::new();
+        let mut mock_cache = MockCache::new();
+        mock_cache.expect_get_iva_rate().returning(|_| Ok(Some(IvaRate { jurisdiction: "J1".to_string(), rate: 0.21 })));
+
+        let orchestrator = Orchestrator::new(ProfileResolver::new(mock_db, mock_cache), IVACalculator);
+        let tx = Transaction {
+            amount: Decimal::from(100),
+            product: "P".to_string(),
+            jurisdiction: "J1".to_string(),
+            client_id: String::new(),
+            date: Local::now().date_naive(),
+        };
+        let profile = Profile::inline("RESPONSABLE_INSCRIPTO", &["J1".to_string()]);
+
+        let res = orchestrator.process_quote(tx.clone(), &profile, &HashMap::new()).await.unwrap();
+        assert_eq!(res[0].amount, Decimal::from(21));
+
+        let overrides = HashMap::from([(TaxType::IVA, 0.105)]);
+        let res = orchestrator.process_quote(tx, &profile, &overrides).await.unwrap();
+        assert_eq!(res[0].rate, 0.105);
+        assert_eq!(res[0].amount, Decimal::new(105, 1));
+    }
+
+    #[tokio::test]
+    async fn test_process_calculation_deadline_exceeded() {
+        let profile_resolver = ProfileResolver::new(MockRepo::new(), StalledCache);
+        let orchestrator = Orchestrator::new(profile_resolver, IVACalculator);
+
+        let tx = Transaction {
+            amount: Decimal::from(100),
+            product: "P".to_string(),
+            jurisdiction: "J1".to_string(),
+            client_id: "c1".to_string(),
+            date: Local::now().date_naive(),
+        };
+
+        let deadline = Instant::now() + std::time::Duration::from_millis(10);
+        let err = orchestrator.process_calculation_until(tx, deadline).await.unwrap_err();
+        assert!(err.is::<deadline::DeadlineExceeded>());
     }
 }
@@ -0,0 +1,166 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
+/// Callers tracked at once. Past it the oldest bucket is evicted, so random
+/// keys from a misbehaving caller cannot grow the map without bound.
+const MAX_TRACKED_KEYS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitConfig {
+    /// Sustained requests per second allowed per key.
+    pub requests_per_second: f64,
+    /// Requests a key may issue at once after being idle.
+    pub burst: u32,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token-bucket rate limiter keyed by caller: the `clientId` of a
+/// calculation, or the consumer or peer of a quote. Shared by every RPC
+/// worker.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    max_keys: usize,
+    buckets: Mutex<Buckets>,
+}
+
+/// Buckets by key, with the keys in the order they were first seen.
+#[derive(Default)]
+struct Buckets {
+    map: HashMap<String, Bucket>,
+    order: VecDeque<String>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            max_keys: MAX_TRACKED_KEYS,
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    /// Takes one token for `key`. Returns `false` if the key is over its limit.
+    pub fn try_acquire(&self, key: &str) -> bool {
+        self.try_acquire_at(key, Instant::now())
+    }
+
+    /// Whether `key` is out of tokens, without taking one. Used where only
+    /// some outcomes are charged, such as failed logins.
+    pub fn is_limited(&self, key: &str) -> bool {
+        self.is_limited_at(key, Instant::now())
+    }
+
+    fn is_limited_at(&self, key: &str, now: Instant) -> bool {
+        let buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        buckets.map.get(key).is_some_and(|bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * self.config.requests_per_second < 1.0
+        })
+    }
+
+    fn try_acquire_at(&self, key: &str, now: Instant) -> bool {
+        let burst = f64::from(self.config.burst);
+        let rate = self.config.requests_per_second;
+        let mut guard = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let buckets = &mut *guard;
+
+        if !buckets.map.contains_key(key) {
+            // Evicting the oldest key at worst hands it a full bucket again
+            while buckets.map.len() >= self.max_keys {
+                let Some(oldest) = buckets.order.pop_front() else { break };
+                buckets.map.remove(&oldest);
+            }
+            buckets.order.push_back(key.to_string());
+        }
+        let bucket = buckets.map.entry(key.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limiter(requests_per_second: f64, burst: u32) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig { requests_per_second, burst })
+    }
+
+    #[test]
+    fn test_burst_then_rejected() {
+        let limiter = limiter(1.0, 3);
+        let now = Instant::now();
+
+        assert!(limiter.try_acquire_at("c1", now));
+        assert!(limiter.try_acquire_at("c1", now));
+        assert!(limiter.try_acquire_at("c1", now));
+        assert!(!limiter.try_acquire_at("c1", now));
+    }
+
+    #[test]
+    fn test_tokens_refill_over_time() {
+        let limiter = limiter(10.0, 1);
+        let now = Instant::now();
+
+        assert!(limiter.try_acquire_at("c1", now));
+        assert!(!limiter.try_acquire_at("c1", now + Duration::from_millis(50)));
+        assert!(limiter.try_acquire_at("c1", now + Duration::from_millis(150)));
+    }
+
+    #[test]
+    fn test_keys_are_independent() {
+        let limiter = limiter(1.0, 1);
+        let now = Instant::now();
+
+        assert!(limiter.try_acquire_at("c1", now));
+        assert!(!limiter.try_acquire_at("c1", now));
+        assert!(limiter.try_acquire_at("c2", now));
+    }
+
+    #[test]
+    fn test_tracked_keys_never_exceed_the_cap() {
+        let mut limiter = limiter(0.001, 1);
+        limiter.max_keys = 3;
+        let now = Instant::now();
+
+        for i in 0..10 {
+            assert!(limiter.try_acquire_at(&format!("c{}", i), now));
+            let buckets = limiter.buckets.lock().unwrap();
+            assert!(buckets.map.len() <= 3);
+            assert_eq!(buckets.order.len(), buckets.map.len());
+        }
+        // The newest keys are still limited; the oldest were evicted
+        assert!(!limiter.try_acquire_at("c9", now));
+        assert!(limiter.try_acquire_at("c0", now));
+    }
+
+    #[test]
+    fn test_is_limited_does_not_take_tokens() {
+        let limiter = limiter(10.0, 1);
+        let now = Instant::now();
+
+        assert!(!limiter.is_limited_at("c1", now));
+        assert!(!limiter.is_limited_at("c1", now));
+        assert!(limiter.try_acquire_at("c1", now));
+        assert!(limiter.is_limited_at("c1", now));
+        assert!(!limiter.is_limited_at("c1", now + Duration::from_millis(150)));
+    }
+}
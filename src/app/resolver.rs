@@ -1,7 +1,22 @@
-use crate::domain::models::Profile;
-use crate::domain::traits::{ProfileRepositoryTrait, ProfileCacheTrait};
+use crate::app::degradation::{self, Degradation};
+use crate::app::errors::ErrorCode;
+use crate::app::retry::Classifier;
+use crate::app::single_flight::SingleFlight;
+use crate::domain::models::{IvaRate, Profile};
+use crate::domain::traits::{CacheLookup, Generation, ProfileRepositoryTrait, ProfileCacheTrait, StaleEntry};
 use anyhow::Result;
-use tracing::{debug, info};
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{debug, info, warn};
+
+/// Hardcoded safety fallback when not even a DEFAULT IVA rate exists.
+const FALLBACK_IVA_RATE: f64 = 0.21;
+
+/// A loaded value and how the load was degraded (a failed cache write, a
+/// stale copy). Kept with the value so every caller sharing a load is
+/// flagged.
+type Loaded<T> = (T, Degradation);
 
 #[derive(Clone)]
 pub struct ProfileResolver<R, C> 
@@ -11,6 +26,14 @@ where
 {
     db: R,
     cache: C,
+    // Concurrent cache misses for the same key share one DB load; clones
+    // share the loads in flight
+    profile_loads: Arc<SingleFlight<Loaded<Option<Profile>>>>,
+    iva_rate_loads: Arc<SingleFlight<Loaded<f64>>>,
+    // Cache failures are not fatal (RT-003): the request falls through to
+    // the DB and is flagged as degraded
+    cache_errors: Arc<AtomicU64>,
+    is_transient: Classifier,
 }
 
 impl<R, C> ProfileResolver<R, C>
@@ -19,56 +42,187 @@ where
     C: ProfileCacheTrait,
 {
     pub fn new(db: R, cache: C) -> Self {
-        Self { db, cache }
+        Self {
+            db,
+            cache,
+            profile_loads: Arc::new(SingleFlight::new()),
+            iva_rate_loads: Arc::new(SingleFlight::new()),
+            cache_errors: Arc::default(),
+            is_transient: |_| false,
+        }
+    }
+
+    /// DB errors, besides an open circuit and timeouts, that may be answered
+    /// with a stale copy (e.g. `db::is_transient`). Any other error, such as
+    /// a broken query, is returned as is.
+    pub fn with_transient_errors(mut self, is_transient: Classifier) -> Self {
+        self.is_transient = is_transient;
+        self
+    }
+
+    /// Cache reads and writes that failed and were skipped.
+    pub fn cache_errors(&self) -> u64 {
+        self.cache_errors.load(Ordering::Relaxed)
+    }
+
+    fn cache_failed(&self, operation: &str, key: &str, err: anyhow::Error) {
+        warn!("Cache {} failed for {}, continuing without it: {:#}", operation, key, err);
+        self.cache_errors.fetch_add(1, Ordering::Relaxed);
+        degradation::mark();
+    }
+
+    /// Whether the DB is unreachable rather than answering with an error.
+    fn db_unavailable(&self, err: &anyhow::Error) -> bool {
+        matches!(ErrorCode::from_error(err), ErrorCode::Unavailable | ErrorCode::Timeout) || (self.is_transient)(err)
+    }
+
+    /// RT-003: when the DB fails, serves the last copy cached for `key`,
+    /// else returns the DB error.
+    fn stale_or<T>(&self, key: &str, err: anyhow::Error, stale: Result<Option<StaleEntry<T>>>) -> Result<T> {
+        match stale {
+            Ok(Some(entry)) => {
+                let age = entry.age();
+                warn!("DB failed for {}, serving data cached {:?} ago: {:#}", key, age, err);
+                degradation::mark_stale(age);
+                Ok(entry.value)
+            }
+            Ok(None) => Err(err),
+            Err(e) => {
+                self.cache_failed("stale read", key, e);
+                Err(err)
+            }
+        }
+    }
+
+    /// Runs a load in its own degradation scope, so how it was degraded
+    /// travels with the value to every caller sharing it.
+    async fn tracked<T>(load: impl Future<Output = Result<T>>) -> Result<Loaded<T>> {
+        let (outcome, degradation) = degradation::track(load).await;
+        match outcome {
+            Ok(value) => Ok((value, degradation)),
+            Err(e) => {
+                // Callers sharing a failed load only get the error
+                degradation::merge(degradation);
+                Err(e)
+            }
+        }
+    }
+
+    fn flag<T>((value, degradation): Loaded<T>) -> T {
+        degradation::merge(degradation);
+        value
     }
 
     pub async fn resolve(&self, client_id: &str) -> Result<Option<Profile>> {
-        // Try L2 Cache (Redis)
-        if let Some(profile) = self.cache.get_by_id(client_id).await? {
-            debug!("Cache hit for client_id: {}", client_id);
-            return Ok(Some(profile));
+        // Try the cache, which may know the profile does not exist
+        match self.cache.lookup_profile(client_id).await {
+            Ok(CacheLookup::Hit(profile)) => {
+                debug!("Cache hit for client_id: {}", client_id);
+                return Ok(Some(profile));
+            }
+            Ok(CacheLookup::NotFound) => {
+                debug!("Cached not found for client_id: {}", client_id);
+                return Ok(None);
+            }
+            Ok(CacheLookup::Miss) => {}
+            Err(e) => self.cache_failed("read", client_id, e),
         }
 
         info!("Cache miss for client_id: {}. Fetching from DB...", client_id);
-        
-        // Try DB
-        if let Some(profile) = self.db.get_by_id(client_id).await? {
-            // Populate Cache
-            self.cache.set(&profile).await?;
-            debug!("Populated cache for client_id: {}", client_id);
-            return Ok(Some(profile));
+        self.profile_loads.run(client_id, || Self::tracked(self.load_profile(client_id))).await.map(Self::flag)
+    }
+
+    async fn load_profile(&self, client_id: &str) -> Result<Option<Profile>> {
+        match self.fetch_profile(client_id).await {
+            Ok(profile) => Ok(profile),
+            Err(e) if !self.db_unavailable(&e) => Err(e),
+            Err(e) => {
+                let stale = self.cache.get_stale_profile(client_id).await;
+                self.stale_or(client_id, e, stale).map(Some)
+            }
+        }
+    }
+
+    /// Generation taken before a DB load, so an admin write or invalidation
+    /// landing during the load keeps what it read out of the cache. `None`
+    /// if it cannot be read: the load is then not cached at all.
+    async fn generation(&self, key: &str, generation: impl Future<Output = Result<Generation>>) -> Option<Generation> {
+        match generation.await {
+            Ok(generation) => Some(generation),
+            Err(e) => {
+                self.cache_failed("generation read", key, e);
+                None
+            }
         }
+    }
 
-        Ok(None)
+    async fn fetch_profile(&self, client_id: &str) -> Result<Option<Profile>> {
+        let generation = self.generation(client_id, self.cache.profile_generation(client_id)).await;
+        // Try DB
+        let profile = self.db.get_by_id(client_id).await?;
+
+        // Populate Cache, including a "not found"
+        if let Some(generation) = generation {
+            match self.cache.fill_profile(client_id, profile.as_ref(), generation).await {
+                Ok(()) => debug!("Populated cache for client_id: {}", client_id),
+                Err(e) => self.cache_failed("write", client_id, e),
+            }
+        }
+        Ok(profile)
     }
 
     pub async fn resolve_iva_rate(&self, jurisdiction: &str) -> Result<f64> {
         // Try Cache
-        if let Some(rate_info) = self.cache.get_iva_rate(jurisdiction).await? {
-            debug!("Cache hit for IVA rate in jurisdiction: {}", jurisdiction);
-            return Ok(rate_info.rate);
+        match self.cache.lookup_iva_rate(jurisdiction).await {
+            Ok(CacheLookup::Hit(rate_info)) => {
+                debug!("Cache hit for IVA rate in jurisdiction: {}", jurisdiction);
+                return Ok(rate_info.rate);
+            }
+            Ok(CacheLookup::NotFound) => return Ok(FALLBACK_IVA_RATE),
+            Ok(CacheLookup::Miss) => {}
+            Err(e) => self.cache_failed("read", jurisdiction, e),
         }
 
         info!("Cache miss for IVA rate in jurisdiction: {}. Fetching from DB...", jurisdiction);
+        self.iva_rate_loads.run(jurisdiction, || Self::tracked(self.load_iva_rate(jurisdiction))).await.map(Self::flag)
+    }
 
-        // Try DB for specific jurisdiction
-        if let Some(rate_info) = self.db.get_iva_rate(jurisdiction).await? {
-            self.cache.set_iva_rate(&rate_info).await?;
-            return Ok(rate_info.rate);
+    async fn load_iva_rate(&self, jurisdiction: &str) -> Result<f64> {
+        match self.fetch_iva_rate(jurisdiction).await {
+            Ok(rate) => Ok(rate),
+            Err(e) if !self.db_unavailable(&e) => Err(e),
+            Err(e) => {
+                let stale = self.cache.get_stale_iva_rate(jurisdiction).await;
+                self.stale_or(jurisdiction, e, stale).map(|rate_info| rate_info.rate)
+            }
         }
+    }
 
-        // Fallback to DEFAULT
-        if let Some(rate_info) = self.db.get_iva_rate("DEFAULT").await? {
-             // Cache the specific jurisdiction with the default rate to avoid constant misses
-            let specific_rate = crate::domain::models::IvaRate {
-                jurisdiction: jurisdiction.to_string(),
-                rate: rate_info.rate,
-            };
-            self.cache.set_iva_rate(&specific_rate).await?;
-            return Ok(rate_info.rate);
+    async fn fetch_iva_rate(&self, jurisdiction: &str) -> Result<f64> {
+        let generation = self.generation(jurisdiction, self.cache.iva_rate_generation(jurisdiction)).await;
+        let rate_info = self.load_iva_rate_row(jurisdiction).await?;
+        if let Some(generation) = generation {
+            match self.cache.fill_iva_rate(jurisdiction, rate_info.as_ref(), generation).await {
+                Ok(()) => debug!("Populated cache for IVA rate in jurisdiction: {}", jurisdiction),
+                Err(e) => self.cache_failed("write", jurisdiction, e),
+            }
+        }
+        // `None`: neither the jurisdiction nor DEFAULT exist
+        Ok(rate_info.map_or(FALLBACK_IVA_RATE, |rate_info| rate_info.rate))
+    }
+
+    async fn load_iva_rate_row(&self, jurisdiction: &str) -> Result<Option<IvaRate>> {
+        // Try DB for specific jurisdiction
+        if let Some(rate_info) = self.db.get_iva_rate(jurisdiction).await? {
+            return Ok(Some(rate_info));
         }
 
-        Ok(0.21) // Hardcoded safety fallback
+        // Fallback to DEFAULT, cached under the specific jurisdiction to
+        // avoid constant misses
+        Ok(self.db.get_iva_rate("DEFAULT").await?.map(|rate_info| IvaRate {
+            jurisdiction: jurisdiction.to_string(),
+            rate: rate_info.rate,
+        }))
     }
 }
 
@@ -76,28 +230,9 @@ where
 mod tests {
     use super::*;
     use crate::domain::models::{Profile, IvaRate};
-    use mockall::mock;
-    use async_trait::async_trait;
-
-    mock! {
-        pub Repo {}
-        #[async_trait]
-        impl ProfileRepositoryTrait for Repo {
-            async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>>;
-            async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>>;
-        }
-    }
-
-    mock! {
-        pub Cache {}
-        #[async_trait]
-        impl ProfileCacheTrait for Cache {
-            async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>>;
-            async fn set(&self, profile: &Profile) -> Result<()>;
-            async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>>;
-            async fn set_iva_rate(&self, rate: &IvaRate) -> Result<()>;
-        }
-    }
+    use crate::app::circuit_breaker::CircuitOpen;
+    use crate::test_support::{MockCache, MockNegativeCache, MockRepo, SlowRepo};
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_resolve_profile_cache_hit() {
@@ -228,4 +363,282 @@ mod tests {
 
         assert!(res.is_none());
     }
+
+    #[tokio::test]
+    async fn test_missing_profile_is_cached_as_not_found() {
+        let mut mock_db = MockRepo::new();
+        let mut mock_cache = MockNegativeCache::new();
+
+        mock_cache.expect_lookup_profile().returning(|_| Ok(CacheLookup::Miss));
+        mock_db.expect_get_by_id().times(1).returning(|_| Ok(None));
+        mock_cache.expect_set_profile_not_found().withf(|id| id == "ghost").times(1).returning(|_| Ok(()));
+
+        let resolver = ProfileResolver::new(mock_db, mock_cache);
+        assert!(resolver.resolve("ghost").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cached_not_found_skips_db() {
+        let mut mock_db = MockRepo::new();
+        let mut mock_cache = MockNegativeCache::new();
+
+        mock_cache.expect_lookup_profile().returning(|_| Ok(CacheLookup::NotFound));
+        mock_cache.expect_lookup_iva_rate().returning(|_| Ok(CacheLookup::NotFound));
+        mock_db.expect_get_by_id().times(0);
+        mock_db.expect_get_iva_rate().times(0);
+
+        let resolver = ProfileResolver::new(mock_db, mock_cache);
+        assert!(resolver.resolve("ghost").await.unwrap().is_none());
+        assert_eq!(resolver.resolve_iva_rate("NOWHERE").await.unwrap(), FALLBACK_IVA_RATE);
+    }
+
+    #[tokio::test]
+    async fn test_missing_iva_rate_is_cached_as_not_found() {
+        let mut mock_db = MockRepo::new();
+        let mut mock_cache = MockNegativeCache::new();
+
+        mock_cache.expect_lookup_iva_rate().returning(|_| Ok(CacheLookup::Miss));
+        mock_db.expect_get_iva_rate().times(2).returning(|_| Ok(None));
+        mock_cache.expect_set_iva_rate_not_found().withf(|j| j == "NOWHERE").times(1).returning(|_| Ok(()));
+
+        let resolver = ProfileResolver::new(mock_db, mock_cache);
+        assert_eq!(resolver.resolve_iva_rate("NOWHERE").await.unwrap(), FALLBACK_IVA_RATE);
+    }
+
+    #[tokio::test]
+    async fn test_cache_failures_fall_through_to_db() {
+        let mut mock_db = MockRepo::new();
+        let mut mock_cache = MockCache::new();
+
+        mock_cache.expect_get_by_id().returning(|_| Err(anyhow::anyhow!("redis down")));
+        mock_cache.expect_set().times(1).returning(|_| Err(anyhow::anyhow!("redis down")));
+        mock_cache.expect_get_iva_rate().returning(|_| Err(anyhow::anyhow!("redis down")));
+        mock_cache.expect_set_iva_rate().times(1).returning(|_| Err(anyhow::anyhow!("redis down")));
+        mock_db.expect_get_by_id().returning(|id| {
+            Ok(Some(Profile { client_id: id.to_string(), fiscal_category: "RI".to_string(), config: serde_json::json!({}) }))
+        });
+        mock_db.expect_get_iva_rate().returning(|j| Ok(Some(IvaRate { jurisdiction: j.to_string(), rate: 0.105 })));
+
+        let resolver = ProfileResolver::new(mock_db, mock_cache);
+        let (profile, degradation) = degradation::track(resolver.resolve("c1")).await;
+        assert!(profile.unwrap().is_some());
+        assert!(degradation.cache_failed);
+        let (rate, degradation) = degradation::track(resolver.resolve_iva_rate("TDF")).await;
+        assert_eq!(rate.unwrap(), 0.105);
+        assert!(degradation.cache_failed);
+        assert_eq!(resolver.cache_errors(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_db_failures_are_still_fatal() {
+        let mut mock_db = MockRepo::new();
+        let mut mock_cache = MockCache::new();
+
+        mock_cache.expect_get_by_id().returning(|_| Ok(None));
+        mock_db.expect_get_by_id().returning(|_| Err(anyhow::anyhow!("postgres down")));
+
+        let resolver = ProfileResolver::new(mock_db, mock_cache);
+        let (profile, degradation) = degradation::track(resolver.resolve("c1")).await;
+        assert_eq!(profile.unwrap_err().to_string(), "postgres down");
+        assert_eq!(degradation, Degradation::default());
+    }
+
+    #[tokio::test]
+    async fn test_db_failures_serve_stale_copies() {
+        let mut mock_db = MockRepo::new();
+        let mut mock_cache = MockNegativeCache::new();
+
+        mock_cache.expect_lookup_profile().returning(|_| Ok(CacheLookup::Miss));
+        mock_cache.expect_lookup_iva_rate().returning(|_| Ok(CacheLookup::Miss));
+        mock_db.expect_get_by_id().returning(|_| Err(CircuitOpen { name: "postgres" }.into()));
+        mock_db.expect_get_iva_rate().returning(|_| Err(anyhow::anyhow!("connection reset")));
+        mock_cache.expect_get_stale_profile().returning(|id| {
+            Ok(Some(StaleEntry {
+                value: Profile { client_id: id.to_string(), fiscal_category: "RI".to_string(), config: serde_json::json!({}) },
+                stored_at: chrono::Utc::now() - chrono::Duration::minutes(10),
+            }))
+        });
+        mock_cache.expect_get_stale_iva_rate().returning(|j| {
+            Ok(Some(StaleEntry {
+                value: IvaRate { jurisdiction: j.to_string(), rate: 0.105 },
+                stored_at: chrono::Utc::now() - chrono::Duration::minutes(30),
+            }))
+        });
+        mock_cache.expect_set().times(0);
+        mock_cache.expect_set_iva_rate().times(0);
+
+        let resolver = ProfileResolver::new(mock_db, mock_cache)
+            .with_transient_errors(|e| e.to_string() == "connection reset");
+        let (res, degradation) = degradation::track(async {
+            let profile = resolver.resolve("c1").await?;
+            let rate = resolver.resolve_iva_rate("TDF").await?;
+            anyhow::Ok((profile, rate))
+        })
+        .await;
+
+        let (profile, rate) = res.unwrap();
+        assert_eq!(profile.unwrap().client_id, "c1");
+        assert_eq!(rate, 0.105);
+        assert!(!degradation.cache_failed);
+        let age = degradation.stale_age.unwrap();
+        assert!(age >= Duration::from_secs(30 * 60) && age < Duration::from_secs(31 * 60));
+    }
+
+    #[tokio::test]
+    async fn test_db_errors_other_than_outages_skip_stale_copies() {
+        let mut mock_db = MockRepo::new();
+        let mut mock_cache = MockNegativeCache::new();
+
+        mock_cache.expect_lookup_profile().returning(|_| Ok(CacheLookup::Miss));
+        mock_db.expect_get_by_id().returning(|_| Err(anyhow::anyhow!("column \"config\" does not exist")));
+        mock_cache.expect_get_stale_profile().times(0);
+
+        let resolver = ProfileResolver::new(mock_db, mock_cache);
+        let (profile, degradation) = degradation::track(resolver.resolve("c1")).await;
+        assert_eq!(profile.unwrap_err().to_string(), "column \"config\" does not exist");
+        assert_eq!(degradation, Degradation::default());
+    }
+
+    #[tokio::test]
+    async fn test_db_failure_without_stale_copy_is_fatal() {
+        let mut mock_db = MockRepo::new();
+        let mut mock_cache = MockNegativeCache::new();
+
+        mock_cache.expect_lookup_profile().returning(|_| Err(anyhow::anyhow!("redis down")));
+        mock_db.expect_get_by_id().returning(|_| Err(CircuitOpen { name: "postgres" }.into()));
+        mock_cache.expect_get_stale_profile().times(1).returning(|_| Err(anyhow::anyhow!("redis down")));
+
+        let resolver = ProfileResolver::new(mock_db, mock_cache);
+        let (profile, degradation) = degradation::track(resolver.resolve("c1")).await;
+        assert_eq!(profile.unwrap_err().to_string(), "circuit breaker for postgres is open");
+        assert!(degradation.cache_failed);
+        assert_eq!(resolver.cache_errors(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_share_one_db_load() {
+        let mut mock_cache = MockCache::new();
+        mock_cache.expect_get_by_id().returning(|_| Ok(None));
+        mock_cache.expect_set().times(1).returning(|_| Ok(()));
+        mock_cache.expect_get_iva_rate().returning(|_| Ok(None));
+        mock_cache.expect_set_iva_rate().times(2).returning(|_| Ok(()));
+
+        let resolver = ProfileResolver::new(SlowRepo::new(std::time::Duration::from_millis(20)), mock_cache);
+        let profiles = futures_util::future::join_all((0..5).map(|_| resolver.resolve("c1"))).await;
+        assert!(profiles.iter().all(|r| r.as_ref().unwrap().is_some()));
+        assert_eq!(resolver.db.loads(), 1);
+
+        // Different keys still load separately
+        let rates = futures_util::future::join_all(
+            ["J1", "J1", "J2", "J2"].into_iter().map(|j| resolver.resolve_iva_rate(j)),
+        )
+        .await;
+        assert!(rates.iter().all(|r| *r.as_ref().unwrap() == 0.21));
+        assert_eq!(resolver.db.loads(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_callers_sharing_a_load_are_flagged_when_its_cache_write_fails() {
+        let mut mock_cache = MockCache::new();
+        mock_cache.expect_get_by_id().returning(|_| Ok(None));
+        mock_cache.expect_set().times(1).returning(|_| Err(anyhow::anyhow!("redis down")));
+
+        let resolver = ProfileResolver::new(SlowRepo::new(Duration::from_millis(20)), mock_cache);
+        let outcomes =
+            futures_util::future::join_all((0..3).map(|_| degradation::track(resolver.resolve("c1")))).await;
+
+        assert_eq!(resolver.db.loads(), 1);
+        for (profile, degradation) in outcomes {
+            assert!(profile.unwrap().is_some());
+            assert!(degradation.cache_failed);
+        }
+    }
+
+    /// Cache that, like Redis, drops fills whose generation is older than
+    /// the last invalidation.
+    #[derive(Default)]
+    struct VersionedCache {
+        invalidations: AtomicU64,
+        profiles: std::sync::Mutex<std::collections::HashMap<String, Profile>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ProfileCacheTrait for VersionedCache {
+        async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
+            Ok(self.profiles.lock().unwrap().get(client_id).cloned())
+        }
+
+        async fn set(&self, profile: &Profile) -> Result<()> {
+            self.profiles.lock().unwrap().insert(profile.client_id.clone(), profile.clone());
+            Ok(())
+        }
+
+        async fn get_iva_rate(&self, _jurisdiction: &str) -> Result<Option<IvaRate>> {
+            Ok(None)
+        }
+
+        async fn set_iva_rate(&self, _rate: &IvaRate) -> Result<()> {
+            Ok(())
+        }
+
+        async fn profile_generation(&self, _client_id: &str) -> Result<Generation> {
+            Ok(Generation { local: None, shared: Some(self.invalidations.load(Ordering::SeqCst)) })
+        }
+
+        async fn fill_profile(&self, _client_id: &str, profile: Option<&Profile>, generation: Generation) -> Result<()> {
+            match profile {
+                Some(profile) if generation.shared == Some(self.invalidations.load(Ordering::SeqCst)) => self.set(profile).await,
+                _ => Ok(()),
+            }
+        }
+
+        async fn invalidate_profile(&self, client_id: &str) -> Result<()> {
+            self.invalidations.fetch_add(1, Ordering::SeqCst);
+            self.profiles.lock().unwrap().remove(client_id);
+            Ok(())
+        }
+
+        async fn invalidate_iva_rate(&self, _jurisdiction: &str) -> Result<()> {
+            Ok(())
+        }
+
+        async fn invalidate_all_profiles(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn invalidate_all_iva_rates(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_invalidation_during_a_db_load_keeps_it_out_of_the_cache() {
+        let resolver = ProfileResolver::new(SlowRepo::new(Duration::from_millis(20)), VersionedCache::default());
+        let invalidate = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            resolver.cache.invalidate_profile("c1").await.unwrap();
+        };
+
+        let (profile, ()) = tokio::join!(resolver.resolve("c1"), invalidate);
+        assert!(profile.unwrap().is_some());
+        assert!(resolver.cache.profiles.lock().unwrap().is_empty());
+
+        // A load started after the invalidation is cached
+        assert!(resolver.resolve("c1").await.unwrap().is_some());
+        assert_eq!(resolver.db.loads(), 2);
+        assert!(resolver.cache.profiles.lock().unwrap().contains_key("c1"));
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_is_still_unavailable_through_the_resolver() {
+        let mut mock_db = MockRepo::new();
+        mock_db.expect_get_by_id().returning(|_| Err(CircuitOpen { name: "postgres" }.into()));
+        let mut mock_cache = MockCache::new();
+        mock_cache.expect_get_by_id().returning(|_| Ok(None));
+
+        let resolver = ProfileResolver::new(mock_db, mock_cache);
+        let err = resolver.resolve("c1").await.unwrap_err();
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::Unavailable);
+        assert_eq!(err.to_string(), "circuit breaker for postgres is open");
+    }
 }
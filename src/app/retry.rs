@@ -0,0 +1,294 @@
+use crate::app::deadline;
+use crate::app::timeout::DependencyTimeout;
+use crate::domain::models::{IvaRate, Profile};
+use crate::domain::traits::{CacheLookup, Generation, ProfileCacheTrait, ProfileRepositoryTrait, StaleEntry};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::debug;
+
+/// Tells transient errors (timeouts, dropped connections) from permanent ones.
+/// `DependencyTimeout` is always transient.
+pub type Classifier = fn(&anyhow::Error) -> bool;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Wait before each retry; its length is the number of retries.
+    pub delays: Vec<Duration>,
+}
+
+impl Default for RetryPolicy {
+    /// 3 attempts as per RT-003: the call and 2 retries, after 2 and 3 ms.
+    fn default() -> Self {
+        Self { delays: [2, 3].into_iter().map(Duration::from_millis).collect() }
+    }
+}
+
+impl RetryPolicy {
+    /// Reads `RETRY_DELAYS_MS`, a comma-separated list of waits (default
+    /// `2,3`); an empty value disables retries.
+    pub fn from_env() -> Result<Self> {
+        let Ok(value) = env::var("RETRY_DELAYS_MS") else {
+            return Ok(Self::default());
+        };
+        let delays = value
+            .split(',')
+            .map(str::trim)
+            .filter(|delay| !delay.is_empty())
+            .map(|delay| delay.parse().map(Duration::from_millis))
+            .collect::<Result<_, _>>()
+            .context("Invalid value for RETRY_DELAYS_MS")?;
+        Ok(Self { delays })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RetryStats {
+    /// Attempts made after a transient error.
+    pub retries: u64,
+    /// Calls that succeeded after retrying.
+    pub recovered: u64,
+    /// Calls that still failed with a transient error when out of retries or
+    /// of time before the request deadline.
+    pub exhausted: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    retries: AtomicU64,
+    recovered: AtomicU64,
+    exhausted: AtomicU64,
+}
+
+/// Retries calls to the wrapped repository or cache that fail with a
+/// transient error, as long as the current request deadline leaves time for
+/// the wait. Clones share their counters.
+#[derive(Clone)]
+pub struct Retrying<T> {
+    inner: T,
+    policy: Arc<RetryPolicy>,
+    is_transient: Classifier,
+    counters: Arc<Counters>,
+}
+
+impl<T> Retrying<T> {
+    pub fn new(inner: T, policy: RetryPolicy, is_transient: Classifier) -> Self {
+        Self { inner, policy: Arc::new(policy), is_transient, counters: Arc::default() }
+    }
+
+    pub fn stats(&self) -> RetryStats {
+        RetryStats {
+            retries: self.counters.retries.load(Ordering::Relaxed),
+            recovered: self.counters.recovered.load(Ordering::Relaxed),
+            exhausted: self.counters.exhausted.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn run<V, F, Fut>(&self, call: F) -> Result<V>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<V>>,
+    {
+        let mut delays = self.policy.delays.iter();
+        let mut retried = false;
+        loop {
+            let err = match call().await {
+                Ok(value) => {
+                    if retried {
+                        self.counters.recovered.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return Ok(value);
+                }
+                Err(e) => e,
+            };
+            if !err.is::<DependencyTimeout>() && !(self.is_transient)(&err) {
+                return Err(err);
+            }
+
+            // Retrying is pointless if the deadline expires while waiting
+            let delay = delays.next().filter(|delay| deadline::remaining().is_none_or(|left| left > **delay));
+            let Some(delay) = delay else {
+                self.counters.exhausted.fetch_add(1, Ordering::Relaxed);
+                return Err(err);
+            };
+            debug!("Retrying in {:?} after transient error: {:#}", delay, err);
+            tokio::time::sleep(*delay).await;
+            self.counters.retries.fetch_add(1, Ordering::Relaxed);
+            retried = true;
+        }
+    }
+}
+
+#[async_trait]
+impl<R: ProfileRepositoryTrait> ProfileRepositoryTrait for Retrying<R> {
+    async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
+        self.run(|| self.inner.get_by_id(client_id)).await
+    }
+
+    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>> {
+        self.run(|| self.inner.get_iva_rate(jurisdiction)).await
+    }
+}
+
+// Every cache operation is idempotent, including invalidations (a retried
+// DEL may publish the invalidation twice)
+#[async_trait]
+impl<C: ProfileCacheTrait> ProfileCacheTrait for Retrying<C> {
+    async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
+        self.run(|| self.inner.get_by_id(client_id)).await
+    }
+
+    async fn set(&self, profile: &Profile) -> Result<()> {
+        self.run(|| self.inner.set(profile)).await
+    }
+
+    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>> {
+        self.run(|| self.inner.get_iva_rate(jurisdiction)).await
+    }
+
+    async fn set_iva_rate(&self, rate: &IvaRate) -> Result<()> {
+        self.run(|| self.inner.set_iva_rate(rate)).await
+    }
+
+    async fn lookup_profile(&self, client_id: &str) -> Result<CacheLookup<Profile>> {
+        self.run(|| self.inner.lookup_profile(client_id)).await
+    }
+
+    async fn set_profile_not_found(&self, client_id: &str) -> Result<()> {
+        self.run(|| self.inner.set_profile_not_found(client_id)).await
+    }
+
+    async fn lookup_iva_rate(&self, jurisdiction: &str) -> Result<CacheLookup<IvaRate>> {
+        self.run(|| self.inner.lookup_iva_rate(jurisdiction)).await
+    }
+
+    async fn set_iva_rate_not_found(&self, jurisdiction: &str) -> Result<()> {
+        self.run(|| self.inner.set_iva_rate_not_found(jurisdiction)).await
+    }
+
+    async fn get_stale_profile(&self, client_id: &str) -> Result<Option<StaleEntry<Profile>>> {
+        self.run(|| self.inner.get_stale_profile(client_id)).await
+    }
+
+    async fn get_stale_iva_rate(&self, jurisdiction: &str) -> Result<Option<StaleEntry<IvaRate>>> {
+        self.run(|| self.inner.get_stale_iva_rate(jurisdiction)).await
+    }
+
+    async fn profile_generation(&self, client_id: &str) -> Result<Generation> {
+        self.run(|| self.inner.profile_generation(client_id)).await
+    }
+
+    async fn fill_profile(&self, client_id: &str, profile: Option<&Profile>, generation: Generation) -> Result<()> {
+        self.run(|| self.inner.fill_profile(client_id, profile, generation)).await
+    }
+
+    async fn iva_rate_generation(&self, jurisdiction: &str) -> Result<Generation> {
+        self.run(|| self.inner.iva_rate_generation(jurisdiction)).await
+    }
+
+    async fn fill_iva_rate(&self, jurisdiction: &str, rate: Option<&IvaRate>, generation: Generation) -> Result<()> {
+        self.run(|| self.inner.fill_iva_rate(jurisdiction, rate, generation)).await
+    }
+
+    async fn invalidate_profile(&self, client_id: &str) -> Result<()> {
+        self.run(|| self.inner.invalidate_profile(client_id)).await
+    }
+
+    async fn invalidate_iva_rate(&self, jurisdiction: &str) -> Result<()> {
+        self.run(|| self.inner.invalidate_iva_rate(jurisdiction)).await
+    }
+
+    async fn invalidate_all_profiles(&self) -> Result<()> {
+        self.run(|| self.inner.invalidate_all_profiles()).await
+    }
+
+    async fn invalidate_all_iva_rates(&self) -> Result<()> {
+        self.run(|| self.inner.invalidate_all_iva_rates()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockRepo;
+    use tokio::time::Instant;
+
+    fn is_timeout(err: &anyhow::Error) -> bool {
+        err.to_string() == "timeout"
+    }
+
+    fn retrying(repo: MockRepo) -> Retrying<MockRepo> {
+        Retrying::new(repo, RetryPolicy::default(), is_timeout)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retries_transient_errors_with_backoff() {
+        let mut repo = MockRepo::new();
+        let mut seq = mockall::Sequence::new();
+        repo.expect_get_iva_rate().times(2).in_sequence(&mut seq).returning(|_| Err(anyhow::anyhow!("timeout")));
+        repo.expect_get_iva_rate()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|j| Ok(Some(IvaRate { jurisdiction: j.to_string(), rate: 0.21 })));
+        let repo = retrying(repo);
+
+        let started = Instant::now();
+        assert!(repo.get_iva_rate("J1").await.unwrap().is_some());
+        assert_eq!(started.elapsed(), Duration::from_millis(5));
+        assert_eq!(repo.stats(), RetryStats { retries: 2, recovered: 1, exhausted: 0 });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_gives_up_after_three_attempts() {
+        let mut repo = MockRepo::new();
+        repo.expect_get_by_id().times(3).returning(|_| Err(anyhow::anyhow!("timeout")));
+        let repo = retrying(repo);
+
+        let started = Instant::now();
+        assert_eq!(repo.get_by_id("c1").await.unwrap_err().to_string(), "timeout");
+        assert_eq!(started.elapsed(), Duration::from_millis(5));
+        assert_eq!(repo.stats(), RetryStats { retries: 2, recovered: 0, exhausted: 1 });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dependency_timeouts_are_retried() {
+        let mut repo = MockRepo::new();
+        let mut seq = mockall::Sequence::new();
+        repo.expect_get_by_id().times(1).in_sequence(&mut seq).returning(|_| {
+            Err(DependencyTimeout { name: "postgres", timeout: Duration::from_millis(5) }.into())
+        });
+        repo.expect_get_by_id().times(1).in_sequence(&mut seq).returning(|_| Ok(None));
+        let repo = retrying(repo);
+
+        assert!(repo.get_by_id("c1").await.unwrap().is_none());
+        assert_eq!(repo.stats(), RetryStats { retries: 1, recovered: 1, exhausted: 0 });
+    }
+
+    #[tokio::test]
+    async fn test_permanent_errors_are_not_retried() {
+        let mut repo = MockRepo::new();
+        repo.expect_get_by_id().times(1).returning(|_| Err(anyhow::anyhow!("relation does not exist")));
+        let repo = retrying(repo);
+
+        assert!(repo.get_by_id("c1").await.is_err());
+        assert_eq!(repo.stats(), RetryStats::default());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stops_retrying_near_the_deadline() {
+        let mut repo = MockRepo::new();
+        repo.expect_get_by_id().times(2).returning(|_| Err(anyhow::anyhow!("timeout")));
+        let repo = retrying(repo);
+
+        // Room for the 2 ms wait, not for the 3 ms one after it
+        let deadline = Instant::now() + Duration::from_millis(4);
+        let res = deadline::run_until(deadline, repo.get_by_id("c1")).await.unwrap();
+
+        assert!(res.is_err());
+        assert_eq!(repo.stats(), RetryStats { retries: 1, recovered: 0, exhausted: 1 });
+    }
+}
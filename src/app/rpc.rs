@@ -1,17 +1,59 @@
 use anyhow::Result;
-use tracing::error;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use tokio::sync::{broadcast, OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::task_tracker::TaskTrackerToken;
+use tracing::{debug, error, warn};
 
-use crate::domain::models::Transaction;
+use crate::domain::models::{IvaRate, Profile, TaxBreakdown, TaxType, Transaction};
+use crate::app::admin::AdminService;
+use crate::app::auth::{Consumer, KeySet};
+use crate::app::changes::{Change, ChangeAction, ChangeFilter, ChangeKind, ChangeNotifier};
+use crate::app::deadline::TimeoutBounds;
+use crate::app::degradation::{self, Degradation};
+use crate::app::errors::{ErrorCode, ServiceError};
+use crate::app::health::{HealthService, HealthStatus};
 use crate::app::orchestrator::Orchestrator;
-use crate::schema_capnp::tax_engine;
-use crate::domain::traits::{ProfileRepositoryTrait, ProfileCacheTrait};
+use crate::app::rate_limit::RateLimiter;
+use crate::app::shutdown::Shutdown;
+use crate::app::tls::ClientIdentity;
+use crate::schema_capnp::{
+    ApiErrorCode, calculation_result, gateway, health, iva_rate_data, profile_data, quote_request, rate_listener, subscription,
+    subscription_filter, tax_admin, tax_engine,
+};
+use crate::domain::traits::{ProfileAdminRepositoryTrait, ProfileRepositoryTrait, ProfileCacheTrait};
+
+/// Highest `TaxEngine` API version served (`calculateV2`).
+///
+/// Breaking changes get a new `calculateVN` method and bump this; older
+/// methods keep their schema and answers, adapting their requests onto the
+/// current calculation path, until `MIN_API_VERSION` moves past them.
+pub const API_VERSION: u16 = 2;
+/// Oldest API version still served (`calculate`).
+pub const MIN_API_VERSION: u16 = 1;
 
 pub struct TaxEngineImpl<R, C>
 where
     R: ProfileRepositoryTrait + Clone + Send + Sync + 'static,
     C: ProfileCacheTrait + Clone + Send + Sync + 'static,
 {
-    pub orchestrator: Orchestrator<R, C>,
+    pub orchestrator: Arc<Orchestrator<R, C>>,
+    shutdown: Shutdown,
+    in_flight_limit: Option<Arc<Semaphore>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    request_timeout: Option<Duration>,
+    timeout_bounds: TimeoutBounds,
+    health: Option<Arc<HealthService>>,
+    changes: Option<Arc<ChangeNotifier>>,
+    client_identity: Option<ClientIdentity>,
+    consumer: Option<Arc<Consumer>>,
+    peer: Option<String>,
 }
 
 impl<R, C> TaxEngineImpl<R, C>
@@ -19,11 +61,413 @@ where
     R: ProfileRepositoryTrait + Clone + Send + Sync + 'static,
     C: ProfileCacheTrait + Clone + Send + Sync + 'static,
 {
-    pub fn new(orchestrator: Orchestrator<R, C>) -> Self {
-        Self { orchestrator }
+    pub fn new(orchestrator: impl Into<Arc<Orchestrator<R, C>>>) -> Self {
+        Self {
+            orchestrator: orchestrator.into(),
+            shutdown: Shutdown::new(),
+            in_flight_limit: None,
+            rate_limiter: None,
+            request_timeout: None,
+            timeout_bounds: TimeoutBounds::default(),
+            health: None,
+            changes: None,
+            client_identity: None,
+            consumer: None,
+            peer: None,
+        }
+    }
+
+    /// Stops admitting calls once `shutdown` is triggered and tracks the
+    /// in-flight ones so the server can drain them.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Caps the calls this capability executes concurrently. The server
+    /// creates one `TaxEngineImpl` per connection, so this is a per-connection
+    /// limit.
+    pub fn with_max_in_flight(self, max_in_flight: usize) -> Self {
+        self.with_in_flight_limit(Arc::new(Semaphore::new(max_in_flight)))
+    }
+
+    /// Like `with_max_in_flight`, sharing the limit with other capabilities
+    /// (e.g. every `TaxEngine` obtained through one connection's `Gateway`).
+    pub fn with_in_flight_limit(mut self, limit: Arc<Semaphore>) -> Self {
+        self.in_flight_limit = Some(limit);
+        self
+    }
+
+    /// Rejects calls whose `clientId` is over its rate limit.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Deadline applied to calls that do not set `timeoutMs`.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Range `timeoutMs` is clamped to.
+    pub fn with_timeout_bounds(mut self, bounds: TimeoutBounds) -> Self {
+        self.timeout_bounds = bounds;
+        self
+    }
+
+    /// Enables the `health` method.
+    pub fn with_health(mut self, health: Arc<HealthService>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Enables the `subscribe` method.
+    pub fn with_changes(mut self, changes: Arc<ChangeNotifier>) -> Self {
+        self.changes = Some(changes);
+        self
+    }
+
+    /// Identity from the client certificate verified during the TLS handshake.
+    pub fn with_client_identity(mut self, identity: ClientIdentity) -> Self {
+        self.client_identity = Some(identity);
+        self
+    }
+
+    pub fn client_identity(&self) -> Option<&ClientIdentity> {
+        self.client_identity.as_ref()
+    }
+
+    /// Restricts calls to the clients `consumer` may access.
+    pub fn with_consumer(mut self, consumer: Arc<Consumer>) -> Self {
+        self.consumer = Some(consumer);
+        self
+    }
+
+    /// Address of the connection's peer, which rate limits the quotes of
+    /// callers with neither a consumer nor a TLS identity.
+    pub fn with_peer(mut self, peer: impl Into<String>) -> Self {
+        self.peer = Some(peer.into());
+        self
+    }
+
+    /// Caller whose quotes share a rate limit: the consumer, else the TLS
+    /// identity, else the peer address.
+    fn quote_caller(&self) -> String {
+        match (&self.consumer, &self.client_identity, &self.peer) {
+            (Some(consumer), _, _) => format!("consumer {}", consumer.name),
+            (None, Some(identity), _) => format!("identity {}", identity.name()),
+            (None, None, Some(peer)) => format!("peer {}", peer),
+            (None, None, None) => "-".to_string(),
+        }
+    }
+
+    /// Name used in logs: the authenticated consumer, else the TLS identity.
+    fn peer_name(&self) -> String {
+        match (&self.consumer, &self.client_identity) {
+            (Some(consumer), _) => consumer.name.clone(),
+            (None, Some(identity)) => identity.name().to_string(),
+            (None, None) => "-".to_string(),
+        }
+    }
+
+    /// `calculate` reports calls rejected before calculating as exceptions,
+    /// with the code as a prefix of the description; failed calculations
+    /// keep their original message (see `calculate`). Only a shutdown fails
+    /// as disconnected, so clients reconnect to another instance.
+    fn v1_error(&self, e: &anyhow::Error) -> capnp::Error {
+        if self.shutdown.is_triggered() {
+            // Kept without a code, as before versioning
+            return capnp::Error::disconnected(e.to_string());
+        }
+        ErrorCode::from_error(e).to_capnp(e)
+    }
+
+    /// Admits a calculation: fails with `UNAVAILABLE` during shutdown and
+    /// `OVERLOADED` over the in-flight limit. The call counts as in flight
+    /// until the returned guards are dropped.
+    fn admit(&self) -> Result<(TaskTrackerToken, Option<OwnedSemaphorePermit>)> {
+        let Some(in_flight) = self.shutdown.track() else {
+            return Err(ServiceError::new(ErrorCode::Unavailable, "Server is shutting down").into());
+        };
+        let permit = match &self.in_flight_limit {
+            Some(limit) => match limit.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    return Err(ServiceError::new(
+                        ErrorCode::Overloaded,
+                        "too many in-flight calls on this connection",
+                    )
+                    .into());
+                }
+            },
+            None => None,
+        };
+        Ok((in_flight, permit))
+    }
+
+    /// The calculation shared by every API version. Calls rejected before
+    /// calculating fail with the `ErrorCode` and message returned to the
+    /// client; a failed calculation is the inner `Err`, as raised by the
+    /// orchestrator (see `calculation_error`).
+    async fn process(&self, input: CalculationInput, received_at: Instant) -> Result<Result<Vec<TaxBreakdown>>> {
+        let peer = self.peer_name();
+        let client_id = input.client_id;
+        if let Some(consumer) = &self.consumer
+            && !consumer.can_access(&client_id)
+        {
+            warn!("Consumer {} is not allowed to calculate for client {}", consumer.name, client_id);
+            return Err(ServiceError::new(
+                ErrorCode::PermissionDenied,
+                format!("not allowed to calculate for client {}", client_id),
+            )
+            .into());
+        }
+        if let Some(limiter) = &self.rate_limiter
+            && !limiter.try_acquire(&client_id)
+        {
+            warn!("Rate limit exceeded for client: {} (peer {})", client_id, peer);
+            return Err(ServiceError::new(
+                ErrorCode::Overloaded,
+                format!("rate limit exceeded for client {}", client_id),
+            )
+            .into());
+        }
+        let deadline = self.deadline(input.timeout_ms, received_at);
+        let tx = Transaction {
+            amount: input.amount,
+            product: input.product,
+            jurisdiction: input.jurisdiction,
+            client_id,
+            date: parse_date(&input.date)?,
+        };
+
+        let outcome = match deadline {
+            Some(deadline) => self.orchestrator.process_calculation_until(tx, deadline).await,
+            None => self.orchestrator.process_calculation(tx).await,
+        };
+        Ok(outcome.inspect_err(|e| error!("Calculation error ({}) for peer {}: {:?}", ErrorCode::from_error(e), peer, e)))
+    }
+
+    /// Runs a quote over `input.profile`. Quotes read no client data, so
+    /// they are not scoped to the consumer's clients; they are rate limited
+    /// per caller (see `quote_caller`) instead of per client.
+    async fn process_quote(&self, input: QuoteInput, received_at: Instant) -> Result<Vec<TaxBreakdown>> {
+        let peer = self.peer_name();
+        if input.profile.fiscal_category.is_empty() {
+            return Err(ServiceError::new(ErrorCode::InvalidRequest, "fiscalCategory is required").into());
+        }
+        if let Some(limiter) = &self.rate_limiter {
+            // Prefixed so callers never share a bucket with a clientId
+            let caller = self.quote_caller();
+            if !limiter.try_acquire(&format!("quote:{}", caller)) {
+                warn!("Quote rate limit exceeded for {}", caller);
+                return Err(ServiceError::new(ErrorCode::Overloaded, "rate limit exceeded for quotes").into());
+            }
+        }
+        let deadline = self.deadline(input.timeout_ms, received_at);
+        let tx = Transaction {
+            amount: input.amount,
+            product: input.product,
+            jurisdiction: input.jurisdiction,
+            client_id: String::new(),
+            date: parse_date(&input.date)?,
+        };
+
+        let outcome = match deadline {
+            Some(deadline) => {
+                self.orchestrator.process_quote_until(tx, &input.profile, &input.rate_overrides, deadline).await
+            }
+            None => self.orchestrator.process_quote(tx, &input.profile, &input.rate_overrides).await,
+        };
+        outcome.map_err(|e| {
+            let code = ErrorCode::from_error(&e);
+            error!("Quote error ({}) for peer {}: {:?}", code, peer, e);
+            ServiceError::new(code, format!("Quote failed: {}", e)).into()
+        })
+    }
+
+    /// `timeout_ms` from the request within `timeout_bounds`, else the
+    /// configured request timeout.
+    fn deadline(&self, timeout_ms: u32, received_at: Instant) -> Option<Instant> {
+        let timeout = match timeout_ms {
+            0 => self.request_timeout?,
+            ms => self.timeout_bounds.clamp(Duration::from_millis(ms.into())),
+        };
+        Some(received_at + timeout)
+    }
+
+    fn features(&self) -> Vec<&'static str> {
+        let mut features = Vec::new();
+        if self.health.is_some() {
+            features.push("health");
+        }
+        if self.changes.is_some() {
+            features.push("subscribe");
+        }
+        features
+    }
+}
+
+/// Request fields common to `calculate` and `calculateV2`, before validation.
+struct CalculationInput {
+    client_id: String,
+    amount: Decimal,
+    jurisdiction: String,
+    product: String,
+    date: String,
+    timeout_ms: u32,
+}
+
+/// Fields of a `quote` request, after decimal and tax type parsing.
+struct QuoteInput {
+    amount: Decimal,
+    jurisdiction: String,
+    product: String,
+    date: String,
+    timeout_ms: u32,
+    profile: Profile,
+    rate_overrides: HashMap<TaxType, f64>,
+}
+
+fn parse_date(date: &str) -> Result<chrono::NaiveDate> {
+    match date {
+        "" => Ok(chrono::Local::now().date_naive()),
+        date => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+            ServiceError::new(ErrorCode::InvalidRequest, format!("invalid date {}, expected YYYY-MM-DD", date)).into()
+        }),
+    }
+}
+
+fn read_quote(request: quote_request::Reader) -> Result<QuoteInput> {
+    let profile = request.get_profile()?;
+    let mut rate_overrides = HashMap::new();
+    for rate_override in request.get_rate_overrides()?.iter() {
+        let tax_type = parse_tax_type(rate_override.get_tax_type()?.to_str()?)?;
+        let rate = parse_decimal(rate_override.get_rate()?.to_str()?)?;
+        if !(Decimal::ZERO..=Decimal::ONE).contains(&rate) {
+            return Err(ServiceError::new(
+                ErrorCode::InvalidRequest,
+                format!("rate for {} must be between 0 and 1", tax_type.name()),
+            )
+            .into());
+        }
+        // Rates are kept as f64 like the ones stored in iva_rates
+        rate_overrides.insert(tax_type, rate.to_f64().unwrap_or_default());
+    }
+    let mut inscriptions = Vec::new();
+    for jurisdiction in profile.get_inscriptions()?.iter() {
+        inscriptions.push(jurisdiction?.to_string()?);
+    }
+    // An exemption is quoted as a zero rate, over any override
+    for name in profile.get_exemptions()?.iter() {
+        rate_overrides.insert(parse_tax_type(name?.to_str()?)?, 0.0);
+    }
+
+    Ok(QuoteInput {
+        amount: parse_decimal(request.get_amount()?.to_str()?)?,
+        jurisdiction: request.get_jurisdiction()?.to_string()?,
+        product: request.get_product()?.to_string()?,
+        date: request.get_date()?.to_string()?,
+        timeout_ms: request.get_timeout_ms(),
+        profile: Profile::inline(profile.get_fiscal_category()?.to_str()?, &inscriptions),
+        rate_overrides,
+    })
+}
+
+fn parse_tax_type(name: &str) -> Result<TaxType> {
+    TaxType::parse(name)
+        .ok_or_else(|| ServiceError::new(ErrorCode::InvalidRequest, format!("unknown tax type {:?}", name)).into())
+}
+
+/// Writes a `calculateV2` or `quote` outcome: the breakdown with decimal
+/// amounts, or the error as data.
+fn write_result(
+    result: calculation_result::Builder,
+    outcome: Result<Vec<TaxBreakdown>>,
+    non_binding: bool,
+    degradation: Degradation,
+) {
+    match outcome {
+        Ok(breakdowns) => {
+            let mut ok = result.init_ok();
+            ok.set_total_amount(format_amount(breakdowns.iter().map(|b| b.amount).sum()));
+            ok.set_non_binding(non_binding);
+            ok.set_degraded(degradation.cache_failed);
+            if let Some(age) = degradation.stale_age {
+                ok.set_stale(true);
+                ok.set_stale_age_ms(age.as_millis() as u64);
+            }
+            let mut list = ok.init_breakdown(breakdowns.len() as u32);
+            for (i, b) in breakdowns.iter().enumerate() {
+                let mut line = list.reborrow().get(i as u32);
+                line.set_tax_type(b.tax_type.name());
+                line.set_base(format_amount(b.base));
+                line.set_rate(format_rate(b.rate));
+                line.set_amount(format_amount(b.amount));
+            }
+        }
+        Err(e) => {
+            let mut error = result.init_error();
+            error.set_code(api_error_code(ErrorCode::from_error(&e)));
+            error.set_message(e.to_string());
+        }
+    }
+}
+
+fn api_error_code(code: ErrorCode) -> ApiErrorCode {
+    match code {
+        ErrorCode::InvalidRequest => ApiErrorCode::InvalidRequest,
+        ErrorCode::Unauthenticated => ApiErrorCode::Unauthenticated,
+        ErrorCode::PermissionDenied => ApiErrorCode::PermissionDenied,
+        ErrorCode::NotFound => ApiErrorCode::NotFound,
+        ErrorCode::AlreadyExists => ApiErrorCode::AlreadyExists,
+        ErrorCode::Overloaded => ApiErrorCode::Overloaded,
+        ErrorCode::Timeout => ApiErrorCode::Timeout,
+        ErrorCode::Unavailable => ApiErrorCode::Unavailable,
+        ErrorCode::Internal => ApiErrorCode::Internal,
     }
 }
 
+/// Parses a plain decimal such as `-1234.56` without going through `f64`;
+/// exponents and separators are rejected.
+fn parse_decimal(text: &str) -> Result<Decimal> {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let (int, frac) = digits.split_once('.').unwrap_or((digits, "0"));
+    let valid = !int.is_empty()
+        && !frac.is_empty()
+        && int.bytes().all(|b| b.is_ascii_digit())
+        && frac.bytes().all(|b| b.is_ascii_digit());
+    match text.parse::<Decimal>() {
+        Ok(value) if valid => Ok(value),
+        _ => Err(ServiceError::new(ErrorCode::InvalidRequest, format!("invalid decimal amount {:?}", text)).into()),
+    }
+}
+
+/// What `calculateV2` reports for a calculation the orchestrator failed.
+fn calculation_error(e: anyhow::Error) -> anyhow::Error {
+    ServiceError::new(ErrorCode::from_error(&e), format!("Calculation failed: {}", e)).into()
+}
+
+/// Converts a `Float64` amount of the JSON API.
+pub(crate) fn decimal_from_f64(value: f64) -> Result<Decimal> {
+    Decimal::try_from(value)
+        .map_err(|_| ServiceError::new(ErrorCode::InvalidRequest, format!("invalid amount {}", value)).into())
+}
+
+/// Rounds to cents, half away from zero.
+fn format_amount(value: Decimal) -> String {
+    let rounded = value.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero);
+    // -0.001 rounds to a negative zero
+    let rounded = if rounded.is_zero() { Decimal::ZERO } else { rounded };
+    format!("{:.2}", rounded)
+}
+
+fn format_rate(value: f64) -> String {
+    let text = format!("{:.6}", value + 0.0);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
 impl<R, C> tax_engine::Server for TaxEngineImpl<R, C>
 where
     R: ProfileRepositoryTrait + Clone + Send + Sync + 'static,
@@ -34,46 +478,975 @@ where
         params: tax_engine::CalculateParams,
         mut results: tax_engine::CalculateResults,
     ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
-        let orchestrator = self.orchestrator.clone();
-        
-        capnp::capability::Promise::from_future(async move {
-            let request = params.get()?;
-            let tx_req = request.get_tx()?;
+        let received_at = Instant::now();
+        let admission = match self.admit() {
+            Ok(admission) => admission,
+            Err(e) => return capnp::capability::Promise::err(self.v1_error(&e)),
+        };
 
-            let client_id = tx_req.get_client_id()?.to_string()?;
+        capnp::capability::Promise::from_future(async move {
+            let _admission = admission;
+            let tx_req = params.get()?.get_tx()?;
+            // Amounts are answered in f64 from the request, as before
+            // versioning; the decimal only drives the pipeline, so amounts
+            // it cannot hold (e.g. 1e300) still get an answer
             let amount = tx_req.get_amount();
-            let jurisdiction = tx_req.get_jurisdiction()?.to_string()?;
-            let product = tx_req.get_product()?.to_string()?;
-
-            let tx = Transaction {
-                amount,
-                product,
-                jurisdiction,
-                client_id,
-                date: chrono::Local::now().date_naive(),
+            let input = CalculationInput {
+                client_id: tx_req.get_client_id()?.to_string()?,
+                amount: Decimal::try_from(amount).unwrap_or_default(),
+                jurisdiction: tx_req.get_jurisdiction()?.to_string()?,
+                product: tx_req.get_product()?.to_string()?,
+                date: tx_req.get_date()?.to_string()?,
+                timeout_ms: tx_req.get_timeout_ms(),
             };
 
-            match orchestrator.process_calculation(tx).await {
-                Ok(breakdowns) => {
+            match self.process(input, received_at).await {
+                Ok(Ok(breakdowns)) => {
                     let mut response = results.get().init_response();
-                    let total: f64 = breakdowns.iter().map(|b| b.amount).sum();
+                    let total: f64 = breakdowns.iter().map(|b| amount * b.rate).sum();
                     response.set_total_amount(total);
 
                     let mut list = response.init_breakdown(breakdowns.len() as u32);
                     for (i, b) in breakdowns.iter().enumerate() {
                         let mut detail = list.reborrow().get(i as u32);
                         detail.set_tax_type(format!("{:?}", b.tax_type));
-                        detail.set_base(b.base);
+                        detail.set_base(amount);
                         detail.set_rate(b.rate);
-                        detail.set_amount(b.amount);
+                        detail.set_amount(amount * b.rate);
                     }
                     Ok(())
                 }
-                Err(e) => {
-                    error!("Calculation error: {:?}", e);
+                Ok(Err(e)) if !self.shutdown.is_triggered() => {
                     Err(capnp::Error::failed(format!("Calculation failed: {}", e)))
                 }
+                Ok(Err(e)) | Err(e) => Err(self.v1_error(&e)),
+            }
+        })
+    }
+
+    fn health(
+        self: capnp::capability::Rc<Self>,
+        _params: tax_engine::HealthParams,
+        mut results: tax_engine::HealthResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let Some(service) = self.health.clone() else {
+            return capnp::capability::Promise::err(capnp::Error::unimplemented(
+                "Health checks are not configured".to_string(),
+            ));
+        };
+        results.get().set_health(capnp_rpc::new_client(HealthImpl { service }));
+        capnp::capability::Promise::ok(())
+    }
+
+    fn subscribe(
+        self: capnp::capability::Rc<Self>,
+        params: tax_engine::SubscribeParams,
+        mut results: tax_engine::SubscribeResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let Some(notifier) = self.changes.clone() else {
+            return capnp::capability::Promise::err(capnp::Error::unimplemented(
+                "Change notifications are not configured".to_string(),
+            ));
+        };
+        if self.shutdown.is_triggered() {
+            return capnp::capability::Promise::err(capnp::Error::disconnected(
+                "Server is shutting down".to_string(),
+            ));
+        }
+
+        let consumer = self.consumer.clone();
+
+        capnp::capability::Promise::from_future(async move {
+            let request = params.get()?;
+            let listener = request.get_listener()?;
+            let mut filter = read_filter(request.get_filter()?)?;
+            if let Some(consumer) = &consumer {
+                restrict_filter(&mut filter, consumer)?;
+            }
+
+            // Subscribe before returning so no change committed after this
+            // call is missed
+            let changes = notifier.subscribe();
+            let cancelled = CancellationToken::new();
+            tokio::task::spawn_local(forward_changes(changes, filter, listener, cancelled.clone()));
+
+            results.get().set_subscription(capnp_rpc::new_client(SubscriptionImpl {
+                cancelled,
+            }));
+            Ok(())
+        })
+    }
+
+    fn get_api_version(
+        self: capnp::capability::Rc<Self>,
+        _params: tax_engine::GetApiVersionParams,
+        mut results: tax_engine::GetApiVersionResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let mut version = results.get().init_version();
+        version.set_current(API_VERSION);
+        version.set_minimum(MIN_API_VERSION);
+        let features = self.features();
+        let mut list = version.init_features(features.len() as u32);
+        for (i, feature) in features.into_iter().enumerate() {
+            list.set(i as u32, feature);
+        }
+        capnp::capability::Promise::ok(())
+    }
+
+    fn calculate_v2(
+        self: capnp::capability::Rc<Self>,
+        params: tax_engine::CalculateV2Params,
+        mut results: tax_engine::CalculateV2Results,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let received_at = Instant::now();
+        let admission = self.admit();
+
+        capnp::capability::Promise::from_future(async move {
+            let request = params.get()?.get_request()?;
+            let (outcome, degradation) = match (admission, parse_decimal(request.get_amount()?.to_str()?)) {
+                (Err(e), _) | (_, Err(e)) => (Err(e), Degradation::default()),
+                (Ok(admission), Ok(amount)) => {
+                    let _admission = admission;
+                    let input = CalculationInput {
+                        client_id: request.get_client_id()?.to_string()?,
+                        amount,
+                        jurisdiction: request.get_jurisdiction()?.to_string()?,
+                        product: request.get_product()?.to_string()?,
+                        date: request.get_date()?.to_string()?,
+                        timeout_ms: request.get_timeout_ms(),
+                    };
+                    degradation::track(async { self.process(input, received_at).await?.map_err(calculation_error) })
+                        .await
+                }
+            };
+
+            write_result(results.get().init_result(), outcome, false, degradation);
+            Ok(())
+        })
+    }
+
+    fn quote(
+        self: capnp::capability::Rc<Self>,
+        params: tax_engine::QuoteParams,
+        mut results: tax_engine::QuoteResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let received_at = Instant::now();
+        let admission = self.admit();
+
+        capnp::capability::Promise::from_future(async move {
+            let (outcome, degradation) = match (admission, read_quote(params.get()?.get_request()?)) {
+                (Err(e), _) | (_, Err(e)) => (Err(e), Degradation::default()),
+                (Ok(admission), Ok(input)) => {
+                    let _admission = admission;
+                    degradation::track(self.process_quote(input, received_at)).await
+                }
+            };
+
+            write_result(results.get().init_result(), outcome, true, degradation);
+            Ok(())
+        })
+    }
+}
+
+/// Held by the client; when it is dropped (or the connection closes) the
+/// forwarding task stops.
+struct SubscriptionImpl {
+    cancelled: CancellationToken,
+}
+
+impl Drop for SubscriptionImpl {
+    fn drop(&mut self) {
+        self.cancelled.cancel();
+    }
+}
+
+impl subscription::Server for SubscriptionImpl {
+    fn cancel(
+        self: capnp::capability::Rc<Self>,
+        _params: subscription::CancelParams,
+        _results: subscription::CancelResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        self.cancelled.cancel();
+        capnp::capability::Promise::ok(())
+    }
+}
+
+fn read_filter(filter: subscription_filter::Reader) -> Result<ChangeFilter, capnp::Error> {
+    let mut jurisdictions = Vec::new();
+    for jurisdiction in filter.get_jurisdictions()?.iter() {
+        jurisdictions.push(jurisdiction?.to_string()?);
+    }
+    let mut client_ids = Vec::new();
+    for client_id in filter.get_client_ids()?.iter() {
+        client_ids.push(client_id?.to_string()?);
+    }
+    Ok(ChangeFilter { jurisdictions, client_ids })
+}
+
+/// Limits profile notifications to the clients `consumer` may access.
+fn restrict_filter(filter: &mut ChangeFilter, consumer: &Consumer) -> Result<(), capnp::Error> {
+    let Some(allowed) = &consumer.client_ids else {
+        return Ok(());
+    };
+    if filter.client_ids.is_empty() {
+        filter.client_ids = allowed.iter().cloned().collect();
+    } else if let Some(client_id) = filter.client_ids.iter().find(|id| !allowed.contains(*id)) {
+        return Err(ErrorCode::PermissionDenied
+            .to_capnp(format!("not allowed to subscribe to client {}", client_id)));
+    }
+    Ok(())
+}
+
+/// Delivers matching changes to `listener` one at a time, until the
+/// subscription is dropped or a listener call fails.
+async fn forward_changes(
+    mut changes: broadcast::Receiver<Change>,
+    filter: ChangeFilter,
+    listener: rate_listener::Client,
+    cancelled: CancellationToken,
+) {
+    use crate::schema_capnp::{RateChangeAction, RateChangeKind};
+
+    loop {
+        let received = tokio::select! {
+            _ = cancelled.cancelled() => return,
+            received = changes.recv() => received,
+        };
+        let (kind, key, action) = match received {
+            Ok(change) if !filter.matches(&change) => continue,
+            Ok(change) => {
+                let kind = match change.kind {
+                    ChangeKind::IvaRate => RateChangeKind::IvaRate,
+                    ChangeKind::Profile => RateChangeKind::Profile,
+                    ChangeKind::Resync => RateChangeKind::Resync,
+                };
+                let action = match change.action {
+                    ChangeAction::Created => RateChangeAction::Created,
+                    ChangeAction::Updated => RateChangeAction::Updated,
+                    ChangeAction::Deleted => RateChangeAction::Deleted,
+                };
+                (kind, change.key, action)
+            }
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Subscriber fell behind by {} changes, asking it to resync", missed);
+                (RateChangeKind::Resync, String::new(), RateChangeAction::Updated)
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        let mut request = listener.on_change_request();
+        {
+            let mut change = request.get().init_change();
+            change.set_kind(kind);
+            change.set_key(&key);
+            change.set_action(action);
+        }
+        let delivered = tokio::select! {
+            _ = cancelled.cancelled() => return,
+            delivered = request.send().promise => delivered,
+        };
+        if let Err(e) = delivered {
+            debug!("Cancelling subscription, listener call failed: {}", e);
+            return;
+        }
+    }
+}
+
+/// Bootstrap capability when a key set is configured. Each successful
+/// `login` gets a `TaxEngine` built by `new_engine` for that consumer.
+pub struct GatewayImpl<F>
+where
+    F: Fn(Arc<Consumer>) -> tax_engine::Client + 'static,
+{
+    keys: Arc<KeySet>,
+    new_engine: F,
+    peer: Option<String>,
+    login_limiter: Option<Arc<RateLimiter>>,
+}
+
+impl<F> GatewayImpl<F>
+where
+    F: Fn(Arc<Consumer>) -> tax_engine::Client + 'static,
+{
+    pub fn new(keys: Arc<KeySet>, new_engine: F) -> Self {
+        Self { keys, new_engine, peer: None, login_limiter: None }
+    }
+
+    /// Remote address of the connection, used to key failed logins.
+    pub fn with_peer(mut self, peer: impl Into<String>) -> Self {
+        self.peer = Some(peer.into());
+        self
+    }
+
+    /// Charges every failed login to the peer; once it is out of tokens
+    /// `login` fails with `OVERLOADED` without checking the token, so
+    /// guessing cannot continue from a new connection either.
+    pub fn with_login_limiter(mut self, login_limiter: Arc<RateLimiter>) -> Self {
+        self.login_limiter = Some(login_limiter);
+        self
+    }
+}
+
+impl<F> gateway::Server for GatewayImpl<F>
+where
+    F: Fn(Arc<Consumer>) -> tax_engine::Client + 'static,
+{
+    fn login(
+        self: capnp::capability::Rc<Self>,
+        params: gateway::LoginParams,
+        mut results: gateway::LoginResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let token = match params.get().and_then(|p| p.get_token()).and_then(|t| Ok(t.to_str()?.to_string())) {
+            Ok(token) => token,
+            Err(e) => return capnp::capability::Promise::err(e),
+        };
+        let peer = self.peer.as_deref().unwrap_or("-");
+        if let Some(limiter) = &self.login_limiter
+            && limiter.is_limited(peer)
+        {
+            warn!("Rejected login from {}: too many failed attempts", peer);
+            return capnp::capability::Promise::err(
+                ErrorCode::Overloaded.to_capnp("too many failed login attempts"),
+            );
+        }
+        let Some(consumer) = self.keys.authenticate(&token) else {
+            warn!("Rejected login with unknown token from {}", peer);
+            if let Some(limiter) = &self.login_limiter {
+                limiter.try_acquire(peer);
+            }
+            return capnp::capability::Promise::err(ErrorCode::Unauthenticated.to_capnp("invalid token"));
+        };
+
+        debug!("Consumer {} logged in", consumer.name);
+        results.get().set_engine((self.new_engine)(consumer));
+        capnp::capability::Promise::ok(())
+    }
+}
+
+pub struct HealthImpl {
+    service: Arc<HealthService>,
+}
+
+impl HealthImpl {
+    pub fn new(service: Arc<HealthService>) -> Self {
+        Self { service }
+    }
+}
+
+fn to_capnp_status(status: HealthStatus) -> crate::schema_capnp::HealthStatus {
+    match status {
+        HealthStatus::Serving => crate::schema_capnp::HealthStatus::Serving,
+        HealthStatus::Degraded => crate::schema_capnp::HealthStatus::Degraded,
+        HealthStatus::NotServing => crate::schema_capnp::HealthStatus::NotServing,
+    }
+}
+
+impl health::Server for HealthImpl {
+    fn check(
+        self: capnp::capability::Rc<Self>,
+        _params: health::CheckParams,
+        mut results: health::CheckResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let service = self.service.clone();
+
+        capnp::capability::Promise::from_future(async move {
+            let report = service.check().await;
+
+            let mut out = results.get().init_report();
+            out.set_status(to_capnp_status(report.status));
+
+            let mut list = out.init_dependencies(report.dependencies.len() as u32);
+            for (i, dependency) in report.dependencies.iter().enumerate() {
+                let mut entry = list.reborrow().get(i as u32);
+                entry.set_name(dependency.name);
+                entry.set_status(to_capnp_status(dependency.status));
+                entry.set_critical(dependency.critical);
+                entry.set_latency_ms(dependency.latency_ms);
+                if let Some(error) = &dependency.error {
+                    entry.set_error(error);
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+pub struct TaxAdminImpl<A, C>
+where
+    A: ProfileAdminRepositoryTrait + 'static,
+    C: ProfileCacheTrait + 'static,
+{
+    service: Arc<AdminService<A, C>>,
+    /// Who the connection authenticated as; recorded in the audit log.
+    actor: Rc<str>,
+}
+
+impl<A, C> TaxAdminImpl<A, C>
+where
+    A: ProfileAdminRepositoryTrait + 'static,
+    C: ProfileCacheTrait + 'static,
+{
+    pub fn new(service: Arc<AdminService<A, C>>, actor: impl Into<Rc<str>>) -> Self {
+        Self { service, actor: actor.into() }
+    }
+}
+
+fn admin_error(operation: &str, e: anyhow::Error) -> capnp::Error {
+    let code = ErrorCode::from_error(&e);
+    if code == ErrorCode::Internal {
+        error!("Admin {} failed: {:?}", operation, e);
+    } else {
+        warn!("Admin {} rejected ({}): {}", operation, code, e);
+    }
+    code.to_capnp(format!("{} failed: {}", operation, e))
+}
+
+fn read_profile(data: profile_data::Reader) -> Result<Profile, capnp::Error> {
+    let config = data.get_config()?.to_str()?;
+    let config = if config.is_empty() {
+        serde_json::json!({})
+    } else {
+        serde_json::from_str(config)
+            .map_err(|e| ErrorCode::InvalidRequest.to_capnp(format!("config is not valid JSON: {}", e)))?
+    };
+    Ok(Profile {
+        client_id: data.get_client_id()?.to_string()?,
+        fiscal_category: data.get_fiscal_category()?.to_string()?,
+        config,
+    })
+}
+
+fn read_iva_rate(data: iva_rate_data::Reader) -> Result<IvaRate, capnp::Error> {
+    Ok(IvaRate {
+        jurisdiction: data.get_jurisdiction()?.to_string()?,
+        rate: data.get_rate(),
+    })
+}
+
+impl<A, C> tax_admin::Server for TaxAdminImpl<A, C>
+where
+    A: ProfileAdminRepositoryTrait + 'static,
+    C: ProfileCacheTrait + 'static,
+{
+    fn list_profiles(
+        self: capnp::capability::Rc<Self>,
+        _params: tax_admin::ListProfilesParams,
+        mut results: tax_admin::ListProfilesResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let service = self.service.clone();
+
+        capnp::capability::Promise::from_future(async move {
+            let profiles = service.list_profiles().await.map_err(|e| admin_error("listProfiles", e))?;
+
+            let mut list = results.get().init_profiles(profiles.len() as u32);
+            for (i, profile) in profiles.iter().enumerate() {
+                let mut entry = list.reborrow().get(i as u32);
+                entry.set_client_id(&profile.client_id);
+                entry.set_fiscal_category(&profile.fiscal_category);
+                entry.set_config(profile.config.to_string());
+            }
+            Ok(())
+        })
+    }
+
+    fn create_profile(
+        self: capnp::capability::Rc<Self>,
+        params: tax_admin::CreateProfileParams,
+        _results: tax_admin::CreateProfileResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let service = self.service.clone();
+        let actor = self.actor.clone();
+
+        capnp::capability::Promise::from_future(async move {
+            let request = params.get()?;
+            let profile = read_profile(request.get_profile()?)?;
+            service.create_profile(&profile, &actor).await.map_err(|e| admin_error("createProfile", e))
+        })
+    }
+
+    fn update_profile(
+        self: capnp::capability::Rc<Self>,
+        params: tax_admin::UpdateProfileParams,
+        _results: tax_admin::UpdateProfileResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let service = self.service.clone();
+        let actor = self.actor.clone();
+
+        capnp::capability::Promise::from_future(async move {
+            let request = params.get()?;
+            let profile = read_profile(request.get_profile()?)?;
+            service.update_profile(&profile, &actor).await.map_err(|e| admin_error("updateProfile", e))
+        })
+    }
+
+    fn delete_profile(
+        self: capnp::capability::Rc<Self>,
+        params: tax_admin::DeleteProfileParams,
+        _results: tax_admin::DeleteProfileResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let service = self.service.clone();
+        let actor = self.actor.clone();
+
+        capnp::capability::Promise::from_future(async move {
+            let request = params.get()?;
+            let client_id = request.get_client_id()?.to_str()?;
+            service.delete_profile(client_id, &actor).await.map_err(|e| admin_error("deleteProfile", e))
+        })
+    }
+
+    fn list_iva_rates(
+        self: capnp::capability::Rc<Self>,
+        _params: tax_admin::ListIvaRatesParams,
+        mut results: tax_admin::ListIvaRatesResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let service = self.service.clone();
+
+        capnp::capability::Promise::from_future(async move {
+            let rates = service.list_iva_rates().await.map_err(|e| admin_error("listIvaRates", e))?;
+
+            let mut list = results.get().init_rates(rates.len() as u32);
+            for (i, rate) in rates.iter().enumerate() {
+                let mut entry = list.reborrow().get(i as u32);
+                entry.set_jurisdiction(&rate.jurisdiction);
+                entry.set_rate(rate.rate);
             }
+            Ok(())
+        })
+    }
+
+    fn create_iva_rate(
+        self: capnp::capability::Rc<Self>,
+        params: tax_admin::CreateIvaRateParams,
+        _results: tax_admin::CreateIvaRateResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let service = self.service.clone();
+        let actor = self.actor.clone();
+
+        capnp::capability::Promise::from_future(async move {
+            let request = params.get()?;
+            let rate = read_iva_rate(request.get_rate()?)?;
+            service.create_iva_rate(&rate, &actor).await.map_err(|e| admin_error("createIvaRate", e))
+        })
+    }
+
+    fn update_iva_rate(
+        self: capnp::capability::Rc<Self>,
+        params: tax_admin::UpdateIvaRateParams,
+        _results: tax_admin::UpdateIvaRateResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let service = self.service.clone();
+        let actor = self.actor.clone();
+
+        capnp::capability::Promise::from_future(async move {
+            let request = params.get()?;
+            let rate = read_iva_rate(request.get_rate()?)?;
+            service.update_iva_rate(&rate, &actor).await.map_err(|e| admin_error("updateIvaRate", e))
+        })
+    }
+
+    fn delete_iva_rate(
+        self: capnp::capability::Rc<Self>,
+        params: tax_admin::DeleteIvaRateParams,
+        _results: tax_admin::DeleteIvaRateResults,
+    ) -> impl futures_util::Future<Output = Result<(), capnp::Error>> + 'static {
+        let service = self.service.clone();
+        let actor = self.actor.clone();
+
+        capnp::capability::Promise::from_future(async move {
+            let request = params.get()?;
+            let jurisdiction = request.get_jurisdiction()?.to_str()?;
+            service.delete_iva_rate(jurisdiction, &actor).await.map_err(|e| admin_error("deleteIvaRate", e))
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::circuit_breaker::CircuitOpen;
+    use crate::app::resolver::ProfileResolver;
+    use crate::domain::calculators::IVACalculator;
+    use crate::test_support::{DownCache, FixedStore};
+
+    fn engine() -> TaxEngineImpl<FixedStore, FixedStore> {
+        TaxEngineImpl::new(Orchestrator::new(ProfileResolver::new(FixedStore, FixedStore), IVACalculator))
+    }
+
+    /// (taxType, base, rate, amount) lines and total, as returned by `calculate`.
+    type V1Answer = (f64, Vec<(String, f64, f64, f64)>);
+
+    async fn calculate_v1(engine: &tax_engine::Client, client_id: &str, amount: f64, jurisdiction: &str, date: &str)
+        -> Result<V1Answer, capnp::Error> {
+        let mut request = engine.calculate_request();
+        {
+            let mut tx = request.get().init_tx();
+            tx.set_client_id(client_id);
+            tx.set_amount(amount);
+            tx.set_jurisdiction(jurisdiction);
+            tx.set_product("PROD");
+            tx.set_date(date);
+        }
+        let response = request.send().promise.await?;
+        let response = response.get()?.get_response()?;
+        let mut lines = Vec::new();
+        for detail in response.get_breakdown()?.iter() {
+            lines.push((detail.get_tax_type()?.to_string()?, detail.get_base(), detail.get_rate(), detail.get_amount()));
+        }
+        Ok((response.get_total_amount(), lines))
+    }
+
+    /// `Ok((total, lines))` or `Err((code, message))`, as returned by `calculateV2`.
+    type V2Answer = Result<(String, Vec<(String, String, String, String)>), (ApiErrorCode, String)>;
+
+    async fn calculate_v2(engine: &tax_engine::Client, client_id: &str, amount: &str, jurisdiction: &str, date: &str)
+        -> V2Answer {
+        use crate::schema_capnp::calculation_result::Which;
+
+        let mut request = engine.calculate_v2_request();
+        {
+            let mut tx = request.get().init_request();
+            tx.set_client_id(client_id);
+            tx.set_amount(amount);
+            tx.set_jurisdiction(jurisdiction);
+            tx.set_product("PROD");
+            tx.set_date(date);
+        }
+        let response = request.send().promise.await.unwrap();
+        match response.get().unwrap().get_result().unwrap().which().unwrap() {
+            Which::Ok(ok) => {
+                let ok = ok.unwrap();
+                let lines = ok
+                    .get_breakdown()
+                    .unwrap()
+                    .iter()
+                    .map(|line| {
+                        let text = |t: capnp::text::Reader| t.to_string().unwrap();
+                        (
+                            text(line.get_tax_type().unwrap()),
+                            text(line.get_base().unwrap()),
+                            text(line.get_rate().unwrap()),
+                            text(line.get_amount().unwrap()),
+                        )
+                    })
+                    .collect();
+                Ok((ok.get_total_amount().unwrap().to_string().unwrap(), lines))
+            }
+            Which::Error(error) => {
+                let error = error.unwrap();
+                Err((error.get_code().unwrap(), error.get_message().unwrap().to_string().unwrap()))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_calculate_v1_answers_are_unchanged() {
+        let engine: tax_engine::Client = capnp_rpc::new_client(engine());
+
+        let answer = calculate_v1(&engine, "c1", 100.0, "BUENOS_AIRES", "").await.unwrap();
+        assert_eq!(answer, (21.0, vec![("IVA".to_string(), 100.0, 0.21, 21.0)]));
+
+        let answer = calculate_v1(&engine, "mono", 100.0, "BUENOS_AIRES", "").await.unwrap();
+        assert_eq!(answer, (0.0, vec![("IVA".to_string(), 100.0, 0.0, 0.0)]));
+
+        // As before versioning: the same kind and message, without a code
+        let err = calculate_v1(&engine, "missing", 100.0, "BUENOS_AIRES", "").await.unwrap_err();
+        assert_eq!(err.kind, capnp::ErrorKind::Failed);
+        assert_eq!(err.extra, "Calculation failed: Profile not found");
+
+        let err = calculate_v1(&engine, "c1", 100.0, "BUENOS_AIRES", "31/01/2026").await.unwrap_err();
+        assert!(err.extra.contains("INVALID_REQUEST: invalid date 31/01/2026"), "{}", err.extra);
+    }
+
+    #[tokio::test]
+    async fn test_calculate_v1_matches_baseline_f64_answers() {
+        let engine: tax_engine::Client = capnp_rpc::new_client(engine());
+
+        // (amount, jurisdiction, IVA amount) as answered before versioning,
+        // i.e. computed as `amount * rate` in f64
+        let golden: [(f64, &str, f64); 7] = [
+            (2.675, "BUENOS_AIRES", 0.56175),
+            (0.1 + 0.2, "TDF", 0.0315),
+            (123456789.99, "BUENOS_AIRES", 25925925.897899996),
+            (9007199254740993.0, "TDF", 945755921747804.1),
+            (1e20, "BUENOS_AIRES", 2.1e19),
+            (1e300, "BUENOS_AIRES", 2.1e299),
+            (-7.77, "TDF", -0.81585),
+        ];
+        for (amount, jurisdiction, expected) in golden {
+            let (total, lines) = calculate_v1(&engine, "c1", amount, jurisdiction, "").await.unwrap();
+            assert_eq!(total.to_bits(), expected.to_bits(), "amount {}", amount);
+            assert_eq!(lines[0].1.to_bits(), amount.to_bits(), "amount {}", amount);
+            assert_eq!(lines[0].3.to_bits(), expected.to_bits(), "amount {}", amount);
+        }
+    }
+
+    /// Repository behind an open circuit breaker.
+    #[derive(Clone)]
+    struct OpenCircuitRepo;
+
+    #[async_trait::async_trait]
+    impl ProfileRepositoryTrait for OpenCircuitRepo {
+        async fn get_by_id(&self, _client_id: &str) -> Result<Option<Profile>> {
+            Err(CircuitOpen { name: "postgres" }.into())
+        }
+        async fn get_iva_rate(&self, _jurisdiction: &str) -> Result<Option<IvaRate>> {
+            Err(CircuitOpen { name: "postgres" }.into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_calculate_v1_disconnects_only_on_shutdown() {
+        let shutdown = Shutdown::new();
+        let engine: tax_engine::Client = capnp_rpc::new_client(
+            TaxEngineImpl::new(Orchestrator::new(ProfileResolver::new(OpenCircuitRepo, FixedStore), IVACalculator))
+                .with_shutdown(shutdown.clone()),
+        );
+
+        let err = calculate_v1(&engine, "c1", 100.0, "BUENOS_AIRES", "").await.unwrap_err();
+        assert_eq!(err.kind, capnp::ErrorKind::Failed);
+        assert_eq!(err.extra, "Calculation failed: circuit breaker for postgres is open");
+
+        shutdown.trigger();
+        let err = calculate_v1(&engine, "c1", 100.0, "BUENOS_AIRES", "").await.unwrap_err();
+        assert_eq!(err.kind, capnp::ErrorKind::Disconnected);
+    }
+
+    #[tokio::test]
+    async fn test_calculate_v1_and_v2_agree() {
+        let engine: tax_engine::Client = capnp_rpc::new_client(engine());
+
+        for (client_id, amount, jurisdiction) in
+            [("c1", "100", "BUENOS_AIRES"), ("c1", "1234.56", "TDF"), ("mono", "99.99", "TDF")]
+        {
+            let (v1_total, v1_lines) =
+                calculate_v1(&engine, client_id, amount.parse().unwrap(), jurisdiction, "2026-01-31").await.unwrap();
+            let (v2_total, v2_lines) =
+                calculate_v2(&engine, client_id, amount, jurisdiction, "2026-01-31").await.unwrap();
+
+            assert_eq!(v2_total, format_amount(Decimal::try_from(v1_total).unwrap()));
+            assert_eq!(v1_lines.len(), v2_lines.len());
+            for (v1, v2) in v1_lines.iter().zip(&v2_lines) {
+                assert_eq!(v2.0, v1.0);
+                assert_eq!(v2.1, format_amount(Decimal::try_from(v1.1).unwrap()));
+                assert_eq!(v2.2.parse::<f64>().unwrap(), v1.2);
+                assert_eq!(v2.3, format_amount(Decimal::try_from(v1.3).unwrap()));
+            }
+        }
+
+        let (total, lines) = calculate_v2(&engine, "c1", "1234.56", "TDF", "").await.unwrap();
+        assert_eq!(total, "129.63");
+        assert_eq!(lines[0].2, "0.105");
+    }
+
+    #[tokio::test]
+    async fn test_calculate_v2_keeps_decimal_amounts() {
+        let engine: tax_engine::Client = capnp_rpc::new_client(engine());
+
+        // 2^53 + 1 has no f64 representation
+        let (total, lines) = calculate_v2(&engine, "c1", "9007199254740993.10", "TDF", "").await.unwrap();
+        assert_eq!(lines[0].1, "9007199254740993.10");
+        assert_eq!(lines[0].3, "945755921747804.28");
+        assert_eq!(total, "945755921747804.28");
+
+        // 2.675 is 2.67499... as an f64
+        let (_, lines) = calculate_v2(&engine, "mono", "2.675", "TDF", "").await.unwrap();
+        assert_eq!(lines[0].1, "2.68");
+        assert_eq!(lines[0].3, "0.00");
+    }
+
+    #[test]
+    fn test_format_amount_rounds_half_away_from_zero() {
+        assert_eq!(format_amount("0.125".parse().unwrap()), "0.13");
+        assert_eq!(format_amount("-0.125".parse().unwrap()), "-0.13");
+        assert_eq!(format_amount("-0.001".parse().unwrap()), "0.00");
+        assert_eq!(format_amount(Decimal::from(21)), "21.00");
+    }
+
+    #[tokio::test]
+    async fn test_calculate_v2_returns_errors_as_data() {
+        let engine: tax_engine::Client = capnp_rpc::new_client(engine());
+
+        let (code, message) = calculate_v2(&engine, "missing", "100", "TDF", "").await.unwrap_err();
+        assert_eq!(code, ApiErrorCode::NotFound);
+        assert_eq!(message, "Calculation failed: Profile not found");
+
+        for amount in ["1e3", "12,50", "", "abc", "1."] {
+            let (code, _) = calculate_v2(&engine, "c1", amount, "TDF", "").await.unwrap_err();
+            assert_eq!(code, ApiErrorCode::InvalidRequest, "amount {:?}", amount);
+        }
+
+        let (code, _) = calculate_v2(&engine, "c1", "100", "TDF", "2026-13-01").await.unwrap_err();
+        assert_eq!(code, ApiErrorCode::InvalidRequest);
+    }
+
+    #[tokio::test]
+    async fn test_quote_is_non_binding_and_validated() {
+        use crate::schema_capnp::calculation_result::Which;
+
+        let engine: tax_engine::Client = capnp_rpc::new_client(engine());
+        let quote = |fiscal_category: &str, exemptions: &[&str], overrides: &[(&str, &str)]| {
+            let mut request = engine.quote_request();
+            {
+                let mut quote = request.get().init_request();
+                quote.set_amount("200");
+                quote.set_jurisdiction("BUENOS_AIRES");
+                quote.set_product("PROD");
+                let mut profile = quote.reborrow().init_profile();
+                profile.set_fiscal_category(fiscal_category);
+                profile.reborrow().init_inscriptions(1).set(0, "BUENOS_AIRES");
+                let mut list = profile.init_exemptions(exemptions.len() as u32);
+                for (i, exemption) in exemptions.iter().enumerate() {
+                    list.set(i as u32, *exemption);
+                }
+                let mut list = quote.init_rate_overrides(overrides.len() as u32);
+                for (i, (tax_type, rate)) in overrides.iter().enumerate() {
+                    let mut rate_override = list.reborrow().get(i as u32);
+                    rate_override.set_tax_type(*tax_type);
+                    rate_override.set_rate(*rate);
+                }
+            }
+            request.send().promise
+        };
+        let outcome = |response: capnp::capability::Response<tax_engine::quote_results::Owned>| {
+            match response.get().unwrap().get_result().unwrap().which().unwrap() {
+                Which::Ok(ok) => {
+                    let ok = ok.unwrap();
+                    assert!(ok.get_non_binding());
+                    Ok(ok.get_total_amount().unwrap().to_string().unwrap())
+                }
+                Which::Error(error) => Err(error.unwrap().get_code().unwrap()),
+            }
+        };
+
+        let response = quote("RESPONSABLE_INSCRIPTO", &[], &[]).await.unwrap();
+        assert_eq!(outcome(response), Ok("42.00".to_string()));
+        let response = quote("RESPONSABLE_INSCRIPTO", &[], &[("IVA", "0.105")]).await.unwrap();
+        assert_eq!(outcome(response), Ok("21.00".to_string()));
+        let response = quote("RESPONSABLE_INSCRIPTO", &["IVA"], &[]).await.unwrap();
+        assert_eq!(outcome(response), Ok("0.00".to_string()));
+
+        let response = quote("", &[], &[]).await.unwrap();
+        assert_eq!(outcome(response), Err(ApiErrorCode::InvalidRequest));
+        let response = quote("RESPONSABLE_INSCRIPTO", &["VAT"], &[]).await.unwrap();
+        assert_eq!(outcome(response), Err(ApiErrorCode::InvalidRequest));
+        let response = quote("RESPONSABLE_INSCRIPTO", &[], &[("IVA", "21")]).await.unwrap();
+        assert_eq!(outcome(response), Err(ApiErrorCode::InvalidRequest));
+
+        // calculateV2 results stay binding
+        let mut request = engine.calculate_v2_request();
+        {
+            let mut tx = request.get().init_request();
+            tx.set_client_id("c1");
+            tx.set_amount("1");
+        }
+        let response = request.send().promise.await.unwrap();
+        match response.get().unwrap().get_result().unwrap().which().unwrap() {
+            Which::Ok(ok) => assert!(!ok.unwrap().get_non_binding()),
+            Which::Error(_) => panic!("calculateV2 failed"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_logins_are_rate_limited_per_peer() {
+        use crate::app::rate_limit::{RateLimitConfig, RateLimiter};
+        use sha2::{Digest, Sha256};
+
+        let hash = Sha256::digest(b"secret-token").iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        let keys = Arc::new(
+            KeySet::from_json(&format!(
+                r#"[{{"consumer": "billing", "token_sha256": "{}", "client_ids": ["*"]}}]"#,
+                hash
+            ))
+            .unwrap(),
+        );
+        let limiter = Arc::new(RateLimiter::new(RateLimitConfig { requests_per_second: 0.001, burst: 2 }));
+        let gateway_for = |peer: &str| -> gateway::Client {
+            let engine = |_| capnp_rpc::new_client(engine());
+            capnp_rpc::new_client(GatewayImpl::new(keys.clone(), engine).with_peer(peer).with_login_limiter(limiter.clone()))
+        };
+        let login = |gateway: gateway::Client, token: &'static str| async move {
+            let mut request = gateway.login_request();
+            request.get().set_token(token);
+            request.send().promise.await.err().map(|e| e.to_string())
+        };
+
+        let first = gateway_for("10.0.0.1");
+        assert!(login(first.clone(), "wrong-token").await.unwrap().contains("UNAUTHENTICATED"));
+        assert!(login(first.clone(), "wrong-token").await.unwrap().contains("UNAUTHENTICATED"));
+        // Out of attempts: even the right token is refused, on any connection
+        assert!(login(first, "secret-token").await.unwrap().contains("OVERLOADED"));
+        assert!(login(gateway_for("10.0.0.1"), "secret-token").await.unwrap().contains("OVERLOADED"));
+        assert_eq!(login(gateway_for("10.0.0.2"), "secret-token").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_quotes_are_rate_limited_per_peer() {
+        use crate::app::rate_limit::{RateLimitConfig, RateLimiter};
+        use crate::schema_capnp::calculation_result::Which;
+
+        let limiter = Arc::new(RateLimiter::new(RateLimitConfig { requests_per_second: 0.001, burst: 1 }));
+        let engine_for = |peer: &str| -> tax_engine::Client {
+            capnp_rpc::new_client(engine().with_rate_limiter(limiter.clone()).with_peer(peer))
+        };
+        let quote = |engine: tax_engine::Client| async move {
+            let mut request = engine.quote_request();
+            {
+                let mut quote = request.get().init_request();
+                quote.set_amount("100");
+                quote.reborrow().init_profile().set_fiscal_category("MONOTRIBUTO");
+            }
+            let response = request.send().promise.await.unwrap();
+            match response.get().unwrap().get_result().unwrap().which().unwrap() {
+                Which::Ok(_) => None,
+                Which::Error(error) => Some(error.unwrap().get_code().unwrap()),
+            }
+        };
+
+        let first = engine_for("10.0.0.1");
+        assert_eq!(quote(first.clone()).await, None);
+        assert_eq!(quote(first).await, Some(ApiErrorCode::Overloaded));
+        assert_eq!(quote(engine_for("10.0.0.2")).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_cache_failure_is_reported_as_degraded() {
+        use crate::schema_capnp::calculation_result::Which;
+
+        let degraded = |engine: tax_engine::Client| async move {
+            let mut request = engine.calculate_v2_request();
+            {
+                let mut tx = request.get().init_request();
+                tx.set_client_id("c1");
+                tx.set_amount("100");
+                tx.set_jurisdiction("TDF");
+            }
+            let response = request.send().promise.await.unwrap();
+            match response.get().unwrap().get_result().unwrap().which().unwrap() {
+                Which::Ok(ok) => {
+                    let ok = ok.unwrap();
+                    assert_eq!(ok.get_total_amount().unwrap().to_str().unwrap(), "10.50");
+                    ok.get_degraded()
+                }
+                Which::Error(_) => panic!("calculateV2 failed"),
+            }
+        };
+
+        let down = TaxEngineImpl::new(Orchestrator::new(ProfileResolver::new(FixedStore, DownCache), IVACalculator));
+        assert!(degraded(capnp_rpc::new_client(down)).await);
+        assert!(!degraded(capnp_rpc::new_client(engine())).await);
+    }
+
+    #[tokio::test]
+    async fn test_get_api_version_lists_enabled_features() {
+        let engine: tax_engine::Client =
+            capnp_rpc::new_client(engine().with_health(Arc::new(HealthService::new())));
+
+        let response = engine.get_api_version_request().send().promise.await.unwrap();
+        let version = response.get().unwrap().get_version().unwrap();
+        assert_eq!(version.get_current(), API_VERSION);
+        assert_eq!(version.get_minimum(), MIN_API_VERSION);
+        let features: Vec<String> =
+            version.get_features().unwrap().iter().map(|f| f.unwrap().to_string().unwrap()).collect();
+        assert_eq!(features, vec!["health".to_string()]);
+    }
+}
@@ -0,0 +1,999 @@
+use anyhow::{Context, Result};
+use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
+use futures_util::{AsyncReadExt, FutureExt};
+use std::env;
+use std::net::{SocketAddr, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, error, info, warn};
+
+use crate::app::admin::AdminService;
+use crate::app::auth::{Consumer, KeySet};
+use crate::app::changes::ChangeNotifier;
+use crate::app::deadline::TimeoutBounds;
+use crate::app::errors::ErrorCode;
+use crate::app::health::HealthService;
+use crate::app::orchestrator::Orchestrator;
+use crate::app::rate_limit::{RateLimitConfig, RateLimiter};
+use crate::app::rpc::{GatewayImpl, TaxAdminImpl, TaxEngineImpl};
+use crate::app::shutdown::Shutdown;
+use crate::app::tls::{self, ClientIdentity, TlsConfig};
+use crate::domain::traits::{ProfileAdminRepositoryTrait, ProfileRepositoryTrait, ProfileCacheTrait};
+use crate::schema_capnp::{gateway, tax_admin, tax_engine};
+
+/// How long a rejected connection is kept open so the client can read the
+/// `OVERLOADED` error from its bootstrap capability.
+const REJECTED_CONNECTION_GRACE: Duration = Duration::from_secs(1);
+
+/// Rejected connections kept open for `REJECTED_CONNECTION_GRACE` at once;
+/// beyond this they are closed right away, so a flood cannot pile up tasks.
+const MAX_REJECTED_CONNECTIONS: usize = 64;
+
+/// Pause after a failed accept. Errors such as running out of file
+/// descriptors last until some connection closes; retrying at once would
+/// only spin.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Logs a failed accept on `listener` and waits `ACCEPT_ERROR_BACKOFF`. A
+/// failed accept is never fatal: ending the loop would take the listener,
+/// and with it the instance, down over an error that clears on its own.
+pub(crate) async fn accept_failed(listener: &str, err: &std::io::Error) {
+    warn!("Failed to accept {} connection, retrying in {:?}: {}", listener, ACCEPT_ERROR_BACKOFF, err);
+    tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+}
+
+#[derive(Debug, Clone)]
+pub struct UnixSocketConfig {
+    pub path: PathBuf,
+    /// File mode applied to the socket, e.g. `0o660`.
+    pub mode: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// TCP listener; `None` when only the Unix socket is served.
+    pub addr: Option<SocketAddr>,
+    pub unix_socket: Option<UnixSocketConfig>,
+    pub http_addr: SocketAddr,
+    /// Listener for the HTTP/JSON API; `None` leaves it disabled.
+    pub api_addr: Option<SocketAddr>,
+    pub admin_addr: SocketAddr,
+    pub workers: usize,
+    pub shutdown_timeout: Duration,
+    pub max_connections: usize,
+    pub max_in_flight_per_connection: usize,
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Failed `Gateway.login` attempts allowed per peer IP.
+    pub login_failure_limit: RateLimitConfig,
+    pub request_timeout: Duration,
+    /// Range the `timeoutMs` sent by clients is clamped to.
+    pub timeout_bounds: TimeoutBounds,
+    pub tls: Option<TlsConfig>,
+    pub gateway_keys_path: Option<PathBuf>,
+}
+
+impl ServerConfig {
+    /// Reads the server settings from the environment:
+    ///
+    /// - `RPC_ADDR` (default `0.0.0.0:50051`; empty disables TCP)
+    /// - `RPC_UNIX_SOCKET`: path of a Unix socket serving the same interface,
+    ///   alongside TCP or alone; `RPC_UNIX_SOCKET_MODE` sets its permissions
+    ///   (octal, default `660`)
+    /// - `HTTP_ADDR` for the `/livez` and `/readyz` probes (default `0.0.0.0:8080`)
+    /// - `API_ADDR` for `POST /v1/calculate` (disabled unless set); it uses
+    ///   the RPC TLS settings and `RPC_MAX_CONNECTIONS`
+    /// - `ADMIN_RPC_ADDR` for the `TaxAdmin` interface (default `127.0.0.1:50052`,
+    ///   only reachable from the host)
+    /// - `RPC_WORKERS` (default: one worker per CPU core)
+    /// - `SHUTDOWN_TIMEOUT_MS` (default 10 seconds)
+    /// - `RPC_MAX_CONNECTIONS` (default 1024)
+    /// - `RPC_MAX_IN_FLIGHT_PER_CONNECTION` (default 1024)
+    /// - `RATE_LIMIT_RPS` / `RATE_LIMIT_BURST` (per `clientId`; disabled unless
+    ///   `RATE_LIMIT_RPS` is set, burst defaults to one second worth of requests)
+    /// - `REQUEST_TIMEOUT_MS` (default 100 ms; used when the request does not
+    ///   carry its own `timeoutMs`). RT-003's 5 ms is the latency target, not
+    ///   the deadline: a cache miss over a real network does not fit in it
+    /// - `TLS_CERT_PATH` / `TLS_KEY_PATH` (PEM; TLS is disabled unless both are set)
+    /// - `TLS_CLIENT_CA_PATH` (PEM bundle; when set, clients must present a
+    ///   certificate signed by one of these CAs)
+    /// - `GATEWAY_KEYS_PATH` (JSON key set; when set, the bootstrap capability
+    ///   is a `Gateway` and calls require `login`)
+    pub fn from_env() -> Result<Self> {
+        let addr_str = env::var("RPC_ADDR").unwrap_or_else(|_| "0.0.0.0:50051".to_string());
+        let addr = match addr_str.trim() {
+            "" => None,
+            addr_str => Some(addr_str.to_socket_addrs()?.next().context("Invalid address")?),
+        };
+
+        let unix_socket = match env::var("RPC_UNIX_SOCKET") {
+            Ok(path) if !path.is_empty() => {
+                anyhow::ensure!(cfg!(unix), "RPC_UNIX_SOCKET is only supported on Unix platforms");
+                let mode = env::var("RPC_UNIX_SOCKET_MODE").unwrap_or_else(|_| "660".to_string());
+                let mode = u32::from_str_radix(&mode, 8).context("RPC_UNIX_SOCKET_MODE must be an octal mode")?;
+                Some(UnixSocketConfig { path: PathBuf::from(path), mode })
+            }
+            _ => None,
+        };
+        anyhow::ensure!(
+            addr.is_some() || unix_socket.is_some(),
+            "Either RPC_ADDR or RPC_UNIX_SOCKET must be set"
+        );
+
+        let http_addr_str = env::var("HTTP_ADDR").unwrap_or_else(|_| "0.0.0.0:8080".to_string());
+        let http_addr = http_addr_str.to_socket_addrs()?.next().context("Invalid HTTP_ADDR")?;
+
+        let api_addr = match env::var("API_ADDR") {
+            Ok(addr_str) if !addr_str.trim().is_empty() => {
+                Some(addr_str.trim().to_socket_addrs()?.next().context("Invalid API_ADDR")?)
+            }
+            _ => None,
+        };
+
+        let admin_addr_str = env::var("ADMIN_RPC_ADDR").unwrap_or_else(|_| "127.0.0.1:50052".to_string());
+        let admin_addr = admin_addr_str.to_socket_addrs()?.next().context("Invalid ADMIN_RPC_ADDR")?;
+
+        let workers = env_or("RPC_WORKERS", num_cpus::get())?;
+        anyhow::ensure!(workers > 0, "RPC_WORKERS must be greater than zero");
+
+        let shutdown_timeout = Duration::from_millis(env_or("SHUTDOWN_TIMEOUT_MS", 10_000)?);
+
+        let max_connections = env_or("RPC_MAX_CONNECTIONS", 1024)?;
+        anyhow::ensure!(max_connections > 0, "RPC_MAX_CONNECTIONS must be greater than zero");
+
+        let max_in_flight_per_connection = env_or("RPC_MAX_IN_FLIGHT_PER_CONNECTION", 1024)?;
+        anyhow::ensure!(
+            max_in_flight_per_connection > 0,
+            "RPC_MAX_IN_FLIGHT_PER_CONNECTION must be greater than zero"
+        );
+
+        let rate_limit = match env::var("RATE_LIMIT_RPS") {
+            Ok(value) => {
+                let requests_per_second: f64 =
+                    value.parse().context("RATE_LIMIT_RPS must be a number")?;
+                anyhow::ensure!(requests_per_second > 0.0, "RATE_LIMIT_RPS must be greater than zero");
+                let burst = env_or("RATE_LIMIT_BURST", requests_per_second.ceil() as u32)?;
+                anyhow::ensure!(burst > 0, "RATE_LIMIT_BURST must be greater than zero");
+                Some(RateLimitConfig { requests_per_second, burst })
+            }
+            Err(_) => None,
+        };
+
+        let login_failure_limit = RateLimitConfig {
+            requests_per_second: env_or("LOGIN_FAILURE_RPS", 0.1)?,
+            burst: env_or("LOGIN_FAILURE_BURST", 5)?,
+        };
+        anyhow::ensure!(
+            login_failure_limit.requests_per_second > 0.0,
+            "LOGIN_FAILURE_RPS must be greater than zero"
+        );
+        anyhow::ensure!(login_failure_limit.burst > 0, "LOGIN_FAILURE_BURST must be greater than zero");
+
+        let request_timeout = Duration::from_millis(env_or("REQUEST_TIMEOUT_MS", 100)?);
+        anyhow::ensure!(!request_timeout.is_zero(), "REQUEST_TIMEOUT_MS must be greater than zero");
+
+        let default_bounds = TimeoutBounds::default();
+        let timeout_bounds = TimeoutBounds {
+            min: Duration::from_millis(env_or("REQUEST_TIMEOUT_MIN_MS", default_bounds.min.as_millis() as u64)?),
+            max: Duration::from_millis(env_or("REQUEST_TIMEOUT_MAX_MS", default_bounds.max.as_millis() as u64)?),
+        };
+        anyhow::ensure!(
+            timeout_bounds.min <= timeout_bounds.max,
+            "REQUEST_TIMEOUT_MIN_MS must not be greater than REQUEST_TIMEOUT_MAX_MS"
+        );
+
+        let tls = match (env::var("TLS_CERT_PATH"), env::var("TLS_KEY_PATH")) {
+            (Ok(cert_path), Ok(key_path)) => Some(TlsConfig {
+                cert_path: PathBuf::from(cert_path),
+                key_path: PathBuf::from(key_path),
+                client_ca_path: env::var("TLS_CLIENT_CA_PATH").ok().map(PathBuf::from),
+            }),
+            (Err(_), Err(_)) => {
+                anyhow::ensure!(
+                    env::var("TLS_CLIENT_CA_PATH").is_err(),
+                    "TLS_CLIENT_CA_PATH requires TLS_CERT_PATH and TLS_KEY_PATH"
+                );
+                None
+            }
+            _ => anyhow::bail!("TLS_CERT_PATH and TLS_KEY_PATH must be set together"),
+        };
+
+        let gateway_keys_path = env::var("GATEWAY_KEYS_PATH").ok().map(PathBuf::from);
+
+        Ok(Self {
+            addr,
+            unix_socket,
+            http_addr,
+            api_addr,
+            admin_addr,
+            workers,
+            shutdown_timeout,
+            max_connections,
+            max_in_flight_per_connection,
+            rate_limit,
+            login_failure_limit,
+            request_timeout,
+            timeout_bounds,
+            tls,
+            gateway_keys_path,
+        })
+    }
+
+    /// The limiter for `RATE_LIMIT_RPS`; build it once and share it between
+    /// the RPC and HTTP servers so a client has a single budget.
+    pub fn rate_limiter(&self) -> Option<Arc<RateLimiter>> {
+        self.rate_limit.map(|config| Arc::new(RateLimiter::new(config)))
+    }
+
+    /// Loads `GATEWAY_KEYS_PATH`, if set.
+    pub fn load_keys(&self) -> Result<Option<Arc<KeySet>>> {
+        let Some(path) = &self.gateway_keys_path else {
+            return Ok(None);
+        };
+        let keys = KeySet::load(path)?;
+        info!("Gateway enabled with {} keys", keys.len());
+        Ok(Some(Arc::new(keys)))
+    }
+}
+
+fn env_or<T>(name: &str, default: T) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match env::var(name) {
+        Ok(value) => value.parse().with_context(|| format!("Invalid value for {}", name)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// An accepted socket, detached from the accept loop's runtime.
+enum Stream {
+    Tcp(std::net::TcpStream),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixStream),
+}
+
+/// A connection handed from the accept loop to a worker.
+enum Incoming {
+    /// Served normally; the permit is released when the connection closes.
+    Accepted(Stream, OwnedSemaphorePermit),
+    /// Over `max_connections`: every call fails with `OVERLOADED` until the
+    /// grace period ends and the permit is released.
+    Rejected(Stream, OwnedSemaphorePermit),
+}
+
+/// The permit a connection holds until it is closed.
+enum Slot {
+    /// One of `max_connections`.
+    Connection(OwnedSemaphorePermit),
+    /// One of `MAX_REJECTED_CONNECTIONS`.
+    Rejection(OwnedSemaphorePermit),
+}
+
+/// Per-worker state needed to build a `TaxEngine` for each connection.
+#[derive(Clone)]
+struct WorkerContext<R, C>
+where
+    R: ProfileRepositoryTrait + Clone + Send + Sync + 'static,
+    C: ProfileCacheTrait + Clone + Send + Sync + 'static,
+{
+    orchestrator: Arc<Orchestrator<R, C>>,
+    shutdown: Shutdown,
+    max_in_flight: usize,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    login_limiter: Arc<RateLimiter>,
+    request_timeout: Duration,
+    timeout_bounds: TimeoutBounds,
+    health: Option<Arc<HealthService>>,
+    changes: Option<Arc<ChangeNotifier>>,
+    tls: Option<TlsAcceptor>,
+    keys: Option<Arc<KeySet>>,
+}
+
+impl<R, C> WorkerContext<R, C>
+where
+    R: ProfileRepositoryTrait + Clone + Send + Sync + 'static,
+    C: ProfileCacheTrait + Clone + Send + Sync + 'static,
+{
+    /// Bootstrap capability for a new connection: a `Gateway` when a key set
+    /// is configured, otherwise an unrestricted `TaxEngine`.
+    fn new_bootstrap(&self, client_identity: Option<ClientIdentity>, peer: String) -> capnp::capability::Client {
+        // One limit per connection, however many times the client logs in
+        let in_flight = Arc::new(Semaphore::new(self.max_in_flight));
+        match &self.keys {
+            None => self.new_engine(client_identity, None, in_flight, peer).client,
+            Some(keys) => {
+                let context = self.clone();
+                let login_peer = peer.clone();
+                let gateway = GatewayImpl::new(keys.clone(), move |consumer| {
+                    context.new_engine(client_identity.clone(), Some(consumer), in_flight.clone(), peer.clone())
+                })
+                .with_peer(login_peer)
+                .with_login_limiter(self.login_limiter.clone());
+                let gateway: gateway::Client = capnp_rpc::new_client(gateway);
+                gateway.client
+            }
+        }
+    }
+
+    fn new_engine(
+        &self,
+        client_identity: Option<ClientIdentity>,
+        consumer: Option<Arc<Consumer>>,
+        in_flight: Arc<Semaphore>,
+        peer: String,
+    ) -> tax_engine::Client {
+        let mut engine = TaxEngineImpl::new(self.orchestrator.clone())
+            .with_shutdown(self.shutdown.clone())
+            .with_in_flight_limit(in_flight)
+            .with_request_timeout(self.request_timeout)
+            .with_timeout_bounds(self.timeout_bounds)
+            .with_peer(peer);
+        if let Some(rate_limiter) = &self.rate_limiter {
+            engine = engine.with_rate_limiter(rate_limiter.clone());
+        }
+        if let Some(health) = &self.health {
+            engine = engine.with_health(health.clone());
+        }
+        if let Some(changes) = &self.changes {
+            engine = engine.with_changes(changes.clone());
+        }
+        if let Some(identity) = client_identity {
+            engine = engine.with_client_identity(identity);
+        }
+        if let Some(consumer) = consumer {
+            engine = engine.with_consumer(consumer);
+        }
+        capnp_rpc::new_client(engine)
+    }
+}
+
+/// Cap'n Proto RPC server.
+///
+/// The accept loop runs on the caller's runtime and hands every accepted
+/// connection to one of N worker threads (round-robin). Each worker owns a
+/// current-thread runtime, a `LocalSet` and its own `TaxEngine` client, since
+/// capnp-rpc objects are `!Send`. The orchestrator and the rate limiter are
+/// shared between workers; every connection gets its own `TaxEngine` so the
+/// in-flight limit applies per connection.
+///
+/// Connections beyond `max_connections` are still accepted briefly, but their
+/// bootstrap capability fails every call with `OVERLOADED`. At most
+/// `MAX_REJECTED_CONNECTIONS` are kept open that way; the rest are closed.
+///
+/// With TLS configured, workers run the handshake before serving a connection
+/// and pass the verified client certificate identity to its `TaxEngine`.
+///
+/// With a key set configured, the bootstrap capability is a `Gateway`; only
+/// `login` with a known token yields a `TaxEngine`.
+///
+/// The Unix socket, when configured, shares the workers and limits with TCP.
+/// It never uses TLS: access is controlled by the socket file permissions. The
+/// file is removed when the server stops.
+///
+/// When `shutdown` is triggered the listener is closed, new calls are refused
+/// and workers keep their connections open until in-flight calls finish or
+/// `shutdown_timeout` elapses.
+pub struct RpcServer<R, C>
+where
+    R: ProfileRepositoryTrait + Clone + Send + Sync + 'static,
+    C: ProfileCacheTrait + Clone + Send + Sync + 'static,
+{
+    config: ServerConfig,
+    orchestrator: Arc<Orchestrator<R, C>>,
+    health: Option<Arc<HealthService>>,
+    changes: Option<Arc<ChangeNotifier>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    keys: Option<Arc<KeySet>>,
+}
+
+impl<R, C> RpcServer<R, C>
+where
+    R: ProfileRepositoryTrait + Clone + Send + Sync + 'static,
+    C: ProfileCacheTrait + Clone + Send + Sync + 'static,
+{
+    pub fn new(config: ServerConfig, orchestrator: Arc<Orchestrator<R, C>>) -> Self {
+        Self { config, orchestrator, health: None, changes: None, rate_limiter: None, keys: None }
+    }
+
+    /// Exposes `health` through `TaxEngine.health`.
+    pub fn with_health(mut self, health: Arc<HealthService>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Lets clients `subscribe` to the changes published on `changes`.
+    pub fn with_changes(mut self, changes: Arc<ChangeNotifier>) -> Self {
+        self.changes = Some(changes);
+        self
+    }
+
+    /// Uses `rate_limiter` instead of building one from the configuration.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Uses `keys` instead of loading `GATEWAY_KEYS_PATH`.
+    pub fn with_keys(mut self, keys: Arc<KeySet>) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    pub async fn run(self, shutdown: Shutdown) -> Result<()> {
+        let tls = match &self.config.tls {
+            Some(config) => Some(config.acceptor().context("Invalid TLS configuration")?),
+            None => None,
+        };
+        let keys = match self.keys.clone() {
+            Some(keys) => Some(keys),
+            None => self.config.load_keys()?,
+        };
+        if keys.is_none() {
+            warn!("GATEWAY_KEYS_PATH is not set: any peer gets an unrestricted TaxEngine");
+        }
+        let tcp_listener = match &self.config.addr {
+            Some(addr) => {
+                let listener = TcpListener::bind(addr).await?;
+                info!(
+                    "RPC Server listening on {} with {} workers ({})",
+                    addr,
+                    self.config.workers,
+                    match &self.config.tls {
+                        Some(config) if config.client_ca_path.is_some() => "mutual TLS",
+                        Some(_) => "TLS",
+                        None => "plaintext",
+                    }
+                );
+                Some(listener)
+            }
+            None => None,
+        };
+        let unix_listener = match &self.config.unix_socket {
+            Some(config) => {
+                let socket = UnixSocket::bind(config)?;
+                info!(
+                    "RPC Server listening on unix:{} (mode {:o}) with {} workers",
+                    config.path.display(),
+                    config.mode,
+                    self.config.workers
+                );
+                Some(socket)
+            }
+            None => None,
+        };
+
+        let context = WorkerContext {
+            orchestrator: self.orchestrator.clone(),
+            shutdown: shutdown.clone(),
+            max_in_flight: self.config.max_in_flight_per_connection,
+            rate_limiter: self.rate_limiter.clone().or_else(|| self.config.rate_limiter()),
+            login_limiter: Arc::new(RateLimiter::new(self.config.login_failure_limit)),
+            request_timeout: self.config.request_timeout,
+            timeout_bounds: self.config.timeout_bounds,
+            health: self.health.clone(),
+            changes: self.changes.clone(),
+            tls,
+            keys,
+        };
+        let connections = Arc::new(Semaphore::new(self.config.max_connections));
+        let rejections = Arc::new(Semaphore::new(MAX_REJECTED_CONNECTIONS));
+
+        let mut workers = Vec::with_capacity(self.config.workers);
+        let mut handles = Vec::with_capacity(self.config.workers);
+        for id in 0..self.config.workers {
+            let (sender, handle) = spawn_worker(id, context.clone(), self.config.shutdown_timeout)?;
+            workers.push(sender);
+            handles.push(handle);
+        }
+
+        let mut next = 0usize;
+        loop {
+            let (stream, peer) = tokio::select! {
+                biased;
+                _ = shutdown.triggered() => break,
+                accepted = accept_tcp(tcp_listener.as_ref()) => match accepted {
+                    Ok((stream, peer)) => match stream.set_nodelay(true).and_then(|()| stream.into_std()) {
+                        Ok(stream) => (Stream::Tcp(stream), peer.to_string()),
+                        Err(e) => {
+                            warn!("Dropping connection from {}: {}", peer, e);
+                            continue;
+                        }
+                    },
+                    Err(e) => {
+                        accept_failed("RPC", &e).await;
+                        continue;
+                    }
+                },
+                accepted = accept_unix(unix_listener.as_ref()) => match accepted {
+                    Ok(stream) => (stream, "unix socket".to_string()),
+                    Err(e) => {
+                        accept_failed("Unix socket", &e).await;
+                        continue;
+                    }
+                },
+            };
+
+            let incoming = match connections.clone().try_acquire_owned() {
+                Ok(permit) => Incoming::Accepted(stream, permit),
+                Err(_) => {
+                    warn!(
+                        "Rejecting connection from {}: limit of {} connections reached",
+                        peer, self.config.max_connections
+                    );
+                    match rejections.clone().try_acquire_owned() {
+                        Ok(permit) => Incoming::Rejected(stream, permit),
+                        // Too many rejections in their grace period: close it
+                        Err(_) => continue,
+                    }
+                }
+            };
+
+            let worker = &workers[next % workers.len()];
+            next = next.wrapping_add(1);
+
+            debug!("Dispatching connection from {} to worker", peer);
+            if worker.send(incoming).is_err() {
+                anyhow::bail!("RPC worker stopped unexpectedly");
+            }
+        }
+
+        // Also removes the socket file
+        drop(tcp_listener);
+        drop(unix_listener);
+        info!(
+            "Stopped accepting connections, draining {} in-flight calls (timeout {:?})",
+            shutdown.in_flight(),
+            self.config.shutdown_timeout
+        );
+
+        // Closing the channels lets each worker finish its drain and exit
+        drop(workers);
+        for handle in handles {
+            tokio::task::spawn_blocking(move || handle.join())
+                .await?
+                .map_err(|_| anyhow::anyhow!("RPC worker panicked"))?;
+        }
+
+        info!("RPC Server stopped");
+        Ok(())
+    }
+}
+
+async fn accept_tcp(listener: Option<&TcpListener>) -> std::io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(unix)]
+async fn accept_unix(socket: Option<&UnixSocket>) -> std::io::Result<Stream> {
+    match socket {
+        Some(socket) => {
+            let (stream, _) = socket.listener.accept().await?;
+            Ok(Stream::Unix(stream.into_std()?))
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(not(unix))]
+async fn accept_unix(_socket: Option<&UnixSocket>) -> std::io::Result<Stream> {
+    std::future::pending().await
+}
+
+/// A bound Unix socket; the socket file is removed when it is dropped.
+#[cfg(unix)]
+struct UnixSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixSocket {
+    fn bind(config: &UnixSocketConfig) -> Result<Self> {
+        let path = &config.path;
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            anyhow::ensure!(
+                metadata.file_type().is_socket(),
+                "{} exists and is not a socket",
+                path.display()
+            );
+            // A running server still accepts connections; a leftover file from
+            // a crashed one does not
+            anyhow::ensure!(
+                std::os::unix::net::UnixStream::connect(path).is_err(),
+                "{} is in use by another process",
+                path.display()
+            );
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+        }
+
+        // Bound inside a directory only we can enter and moved into place once
+        // its mode is set, so the socket is never reachable with looser permissions
+        let file_name = path.file_name().context("RPC_UNIX_SOCKET must name a file")?;
+        let private_dir = path.with_file_name(format!(
+            ".{}.{}.tmp",
+            file_name.to_string_lossy(),
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&private_dir);
+        std::fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&private_dir)
+            .with_context(|| format!("Failed to create {}", private_dir.display()))?;
+        let staged = private_dir.join(file_name);
+        let listener = UnixListener::bind(&staged)
+            .with_context(|| format!("Failed to bind Unix socket {}", path.display()))
+            .and_then(|listener| {
+                std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(config.mode))
+                    .with_context(|| format!("Failed to set permissions on {}", path.display()))?;
+                std::fs::rename(&staged, path)
+                    .with_context(|| format!("Failed to move Unix socket to {}", path.display()))?;
+                Ok(listener)
+            });
+        if let Err(e) = std::fs::remove_dir_all(&private_dir) {
+            warn!("Failed to remove {}: {}", private_dir.display(), e);
+        }
+        Ok(Self { listener: listener?, path: path.clone() })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!("Failed to remove Unix socket {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Never constructed: `ServerConfig::from_env` rejects `RPC_UNIX_SOCKET` here.
+#[cfg(not(unix))]
+struct UnixSocket;
+
+#[cfg(not(unix))]
+impl UnixSocket {
+    fn bind(_config: &UnixSocketConfig) -> Result<Self> {
+        anyhow::bail!("Unix sockets are not supported on this platform")
+    }
+}
+
+fn spawn_worker<R, C>(
+    id: usize,
+    context: WorkerContext<R, C>,
+    shutdown_timeout: Duration,
+) -> Result<(mpsc::UnboundedSender<Incoming>, std::thread::JoinHandle<()>)>
+where
+    R: ProfileRepositoryTrait + Clone + Send + Sync + 'static,
+    C: ProfileCacheTrait + Clone + Send + Sync + 'static,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel::<Incoming>();
+
+    let handle = std::thread::Builder::new()
+        .name(format!("rpc-worker-{}", id))
+        .spawn(move || {
+            let rt = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(rt) => rt,
+                Err(e) => {
+                    error!("Failed to create runtime for RPC worker {}: {}", id, e);
+                    return;
+                }
+            };
+
+            let local = tokio::task::LocalSet::new();
+            local.block_on(&rt, async move {
+                debug!("RPC worker {} started", id);
+                let shutdown = context.shutdown.clone();
+
+                while let Some(incoming) = rx.recv().await {
+                    let (stream, slot) = match incoming {
+                        Incoming::Accepted(stream, permit) => (stream, Slot::Connection(permit)),
+                        Incoming::Rejected(stream, permit) => (stream, Slot::Rejection(permit)),
+                    };
+                    let result = match stream {
+                        Stream::Tcp(stream) => {
+                            TcpStream::from_std(stream).map(|stream| serve_tcp(stream, &context, slot))
+                        }
+                        #[cfg(unix)]
+                        Stream::Unix(stream) => UnixStream::from_std(stream)
+                            .map(|stream| dispatch_connection(stream, None, UNIX_PEER.to_string(), &context, slot)),
+                    };
+                    if let Err(e) = result {
+                        warn!("RPC worker {} failed to register connection: {}", id, e);
+                    }
+                }
+
+                // Connections stay open while their pending calls complete
+                if !shutdown.drain(shutdown_timeout).await {
+                    warn!(
+                        "RPC worker {} abandoning {} in-flight calls after {:?}",
+                        id,
+                        shutdown.in_flight(),
+                        shutdown_timeout
+                    );
+                }
+                debug!("RPC worker {} stopped", id);
+            });
+        })
+        .context("Failed to spawn RPC worker thread")?;
+
+    Ok((tx, handle))
+}
+
+/// Serves the `TaxAdmin` interface on its own listener, separate from the
+/// public RPC port so it can be bound to a private address.
+///
+/// Admin traffic is low, so every connection is handled by a single thread.
+/// When `shutdown` is triggered the listener is closed and open admin
+/// connections are dropped; a write in progress is rolled back.
+///
+/// Writes are audited under the connection's identity: the client
+/// certificate name with mutual TLS, otherwise the peer address.
+pub struct AdminServer<A, C>
+where
+    A: ProfileAdminRepositoryTrait + 'static,
+    C: ProfileCacheTrait + 'static,
+{
+    addr: SocketAddr,
+    service: Arc<AdminService<A, C>>,
+    tls: Option<TlsAcceptor>,
+}
+
+impl<A, C> AdminServer<A, C>
+where
+    A: ProfileAdminRepositoryTrait + 'static,
+    C: ProfileCacheTrait + 'static,
+{
+    pub fn new(addr: SocketAddr, service: Arc<AdminService<A, C>>) -> Self {
+        Self { addr, service, tls: None }
+    }
+
+    /// Runs the TLS handshake on every admin connection.
+    pub fn with_tls(mut self, acceptor: TlsAcceptor) -> Self {
+        self.tls = Some(acceptor);
+        self
+    }
+
+    pub async fn run(self, shutdown: Shutdown) -> Result<()> {
+        let listener = TcpListener::bind(&self.addr)
+            .await
+            .with_context(|| format!("Failed to bind admin listener on {}", self.addr))?
+            .into_std()?;
+        info!(
+            "Admin RPC Server listening on {} ({})",
+            self.addr,
+            if self.tls.is_some() { "TLS" } else { "plaintext, writes audited by peer address" }
+        );
+
+        let service = self.service;
+        let tls = self.tls;
+        let handle = std::thread::Builder::new()
+            .name("rpc-admin".to_string())
+            .spawn(move || -> Result<()> {
+                let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+                let local = tokio::task::LocalSet::new();
+                local.block_on(&rt, async move {
+                    let listener = TcpListener::from_std(listener)?;
+                    loop {
+                        let (stream, peer) = tokio::select! {
+                            biased;
+                            _ = shutdown.triggered() => return Ok(()),
+                            accepted = listener.accept() => match accepted {
+                                Ok(accepted) => accepted,
+                                Err(e) => {
+                                    accept_failed("admin", &e).await;
+                                    continue;
+                                }
+                            },
+                        };
+                        debug!("Admin connection from {}", peer);
+                        if let Err(e) = stream.set_nodelay(true) {
+                            warn!("Dropping admin connection from {}: {}", peer, e);
+                            continue;
+                        }
+                        let service = service.clone();
+                        let Some(acceptor) = tls.clone() else {
+                            serve_admin(stream, service, admin_actor(None, peer));
+                            continue;
+                        };
+                        tokio::task::spawn_local(async move {
+                            match tls::accept(&acceptor, stream).await {
+                                Ok((stream, identity)) => serve_admin(stream, service, admin_actor(identity, peer)),
+                                Err(e) => warn!("Dropping admin connection from {}: {:#}", peer, e),
+                            }
+                        });
+                    }
+                })
+            })
+            .context("Failed to spawn admin RPC thread")?;
+
+        tokio::task::spawn_blocking(move || handle.join())
+            .await?
+            .map_err(|_| anyhow::anyhow!("Admin RPC thread panicked"))??;
+
+        info!("Admin RPC Server stopped");
+        Ok(())
+    }
+}
+
+/// Name written to the audit log for the writes made on an admin connection.
+fn admin_actor(identity: Option<ClientIdentity>, peer: SocketAddr) -> String {
+    match identity {
+        Some(identity) => identity.name().to_string(),
+        None => format!("unauthenticated {}", peer),
+    }
+}
+
+fn serve_admin<S, A, C>(stream: S, service: Arc<AdminService<A, C>>, actor: String)
+where
+    S: AsyncRead + AsyncWrite + Unpin + 'static,
+    A: ProfileAdminRepositoryTrait + 'static,
+    C: ProfileCacheTrait + 'static,
+{
+    debug!("Admin connection authenticated as {}", actor);
+    let client: tax_admin::Client = capnp_rpc::new_client(TaxAdminImpl::new(service, actor));
+    tokio::task::spawn_local(new_rpc_system(stream, client.client));
+}
+
+/// Runs the TLS handshake first when it is configured.
+fn serve_tcp<R, C>(stream: TcpStream, context: &WorkerContext<R, C>, slot: Slot)
+where
+    R: ProfileRepositoryTrait + Clone + Send + Sync + 'static,
+    C: ProfileCacheTrait + Clone + Send + Sync + 'static,
+{
+    let peer = stream.peer_addr().ok();
+    let Some(acceptor) = context.tls.clone() else {
+        dispatch_connection(stream, None, peer_key(peer), context, slot);
+        return;
+    };
+
+    let context = context.clone();
+    tokio::task::spawn_local(async move {
+        match tls::accept(&acceptor, stream).await {
+            Ok((stream, identity)) => {
+                if let Some(identity) = &identity {
+                    debug!("Client {:?} authenticated as {}", peer, identity);
+                }
+                dispatch_connection(stream, identity, peer_key(peer), &context, slot);
+            }
+            Err(e) => warn!("Dropping connection from {:?}: {:#}", peer, e),
+        }
+    });
+}
+
+/// Peer recorded for connections on the Unix socket, which share one.
+#[cfg(unix)]
+const UNIX_PEER: &str = "unix";
+
+/// The peer's IP without its port, so reconnecting from another port does
+/// not make a new peer.
+fn peer_key(addr: Option<SocketAddr>) -> String {
+    addr.map_or_else(|| "unknown".to_string(), |addr| addr.ip().to_string())
+}
+
+/// Serves `stream` with its own bootstrap capability, or rejects it when no connection
+/// permit was available.
+fn dispatch_connection<S, R, C>(
+    stream: S,
+    client_identity: Option<ClientIdentity>,
+    peer: String,
+    context: &WorkerContext<R, C>,
+    slot: Slot,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + 'static,
+    R: ProfileRepositoryTrait + Clone + Send + Sync + 'static,
+    C: ProfileCacheTrait + Clone + Send + Sync + 'static,
+{
+    match slot {
+        Slot::Connection(permit) => serve_connection(stream, context.new_bootstrap(client_identity, peer), permit),
+        Slot::Rejection(permit) => reject_connection(stream, permit),
+    }
+}
+
+fn new_rpc_system<S>(stream: S, bootstrap: capnp::capability::Client) -> RpcSystem<rpc_twoparty_capnp::Side>
+where
+    S: AsyncRead + AsyncWrite + Unpin + 'static,
+{
+    let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
+    let network = twoparty::VatNetwork::new(
+        reader,
+        writer,
+        rpc_twoparty_capnp::Side::Server,
+        Default::default(),
+    );
+
+    RpcSystem::new(Box::new(network), Some(bootstrap))
+}
+
+fn serve_connection<S>(stream: S, bootstrap: capnp::capability::Client, permit: OwnedSemaphorePermit)
+where
+    S: AsyncRead + AsyncWrite + Unpin + 'static,
+{
+    let rpc_system = new_rpc_system(stream, bootstrap);
+    tokio::task::spawn_local(rpc_system.map(move |_| drop(permit)));
+}
+
+fn reject_connection<S>(stream: S, permit: OwnedSemaphorePermit)
+where
+    S: AsyncRead + AsyncWrite + Unpin + 'static,
+{
+    let client: tax_engine::Client = capnp_rpc::new_future_client(async {
+        Err(ErrorCode::Overloaded.to_capnp("too many connections"))
+    });
+    let rpc_system = new_rpc_system(stream, client.client);
+    tokio::task::spawn_local(async move {
+        let _ = tokio::time::timeout(REJECTED_CONNECTION_GRACE, rpc_system).await;
+        drop(permit);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn socket_config(name: &str) -> UnixSocketConfig {
+        UnixSocketConfig {
+            path: env::temp_dir().join(format!("tax-engine-{}-{}.sock", name, std::process::id())),
+            mode: 0o600,
+        }
+    }
+
+    #[test]
+    fn test_admin_actor_comes_from_the_connection() {
+        let peer: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let identity = ClientIdentity {
+            common_name: Some("ops-console".to_string()),
+            dns_names: Vec::new(),
+            uris: Vec::new(),
+        };
+
+        assert_eq!(admin_actor(Some(identity), peer), "ops-console");
+        assert_eq!(admin_actor(None, peer), "unauthenticated 127.0.0.1:40000");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_permissions_and_cleanup() {
+        let config = socket_config("cleanup");
+
+        let socket = UnixSocket::bind(&config).unwrap();
+        let metadata = std::fs::metadata(&config.path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+        drop(socket);
+        assert!(!config.path.exists());
+        // The private directory the socket was bound in is gone as well
+        let staging = format!(".{}.", config.path.file_name().unwrap().to_string_lossy());
+        assert!(!std::fs::read_dir(env::temp_dir())
+            .unwrap()
+            .any(|entry| entry.unwrap().file_name().to_string_lossy().starts_with(&staging)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_replaces_stale_file_but_not_live_socket() {
+        let config = socket_config("stale");
+
+        // Left behind by a process that exited without cleaning up
+        drop(std::os::unix::net::UnixListener::bind(&config.path).unwrap());
+        let socket = UnixSocket::bind(&config).unwrap();
+
+        assert!(UnixSocket::bind(&config).is_err());
+        drop(socket);
+    }
+}
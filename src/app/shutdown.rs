@@ -0,0 +1,125 @@
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::{TaskTracker, task_tracker::TaskTrackerToken};
+use tracing::{error, info};
+
+/// Shutdown coordination shared by the accept loop, the RPC workers and every
+/// `TaxEngineImpl`. Once triggered no new calls are admitted and `drain` waits
+/// for the ones already in flight.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    token: CancellationToken,
+    in_flight: TaskTracker,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trigger(&self) {
+        self.in_flight.close();
+        self.token.cancel();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    pub async fn triggered(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Registers an in-flight call. Returns `None` once shutdown has started;
+    /// the call counts as pending until the returned token is dropped.
+    pub fn track(&self) -> Option<TaskTrackerToken> {
+        if self.is_triggered() {
+            return None;
+        }
+        Some(self.in_flight.token())
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Waits for in-flight calls to finish. Returns `false` if the timeout
+    /// elapsed first.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, self.in_flight.wait()).await.is_ok()
+    }
+}
+
+/// Resolves on SIGINT (Ctrl+C) or SIGTERM.
+pub async fn wait_for_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_track_rejected_after_trigger() {
+        let shutdown = Shutdown::new();
+        assert!(shutdown.track().is_some());
+
+        shutdown.trigger();
+
+        assert!(shutdown.is_triggered());
+        assert!(shutdown.track().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_in_flight_calls() {
+        let shutdown = Shutdown::new();
+        let call = shutdown.track().unwrap();
+        shutdown.trigger();
+
+        let finisher = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(call);
+        });
+
+        assert!(shutdown.drain(Duration::from_secs(1)).await);
+        assert_eq!(shutdown.in_flight(), 0);
+        finisher.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drain_times_out() {
+        let shutdown = Shutdown::new();
+        let _call = shutdown.track().unwrap();
+        shutdown.trigger();
+
+        assert!(!shutdown.drain(Duration::from_millis(20)).await);
+        assert_eq!(shutdown.in_flight(), 1);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::app::errors::{ErrorCode, ServiceError};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::OnceCell;
+
+type Flight<T> = Arc<OnceCell<Result<T, Arc<anyhow::Error>>>>;
+
+/// Deduplicates concurrent loads of the same key: while a load is running,
+/// later callers for that key wait for its result instead of starting their
+/// own. Nothing is kept once the load completes.
+///
+/// If the caller running the load is cancelled (e.g. its deadline expires),
+/// one of the waiting callers takes over and runs its own `load`.
+pub struct SingleFlight<T> {
+    flights: Mutex<HashMap<String, Flight<T>>>,
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self { flights: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `load` unless a load for `key` is already in flight, and returns
+    /// its result. Errors are shared as a `ServiceError` carrying the
+    /// original error's code and message, even for the caller that ran
+    /// `load`.
+    pub async fn run<F, Fut>(&self, key: &str, load: F) -> Result<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let guard = {
+            let mut flights = self.flights.lock().unwrap_or_else(PoisonError::into_inner);
+            let flight = flights.entry(key.to_string()).or_default().clone();
+            FlightGuard { flights: &self.flights, key, flight }
+        };
+
+        let outcome = guard.flight.get_or_init(|| async { load().await.map_err(Arc::new) }).await;
+        match outcome {
+            Ok(value) => Ok(value.clone()),
+            Err(e) => Err(ServiceError::new(ErrorCode::from_error(e), format!("{:#}", e)).into()),
+        }
+    }
+
+    #[cfg(test)]
+    fn in_flight(&self) -> usize {
+        self.flights.lock().unwrap().len()
+    }
+}
+
+/// Removes the flight from the map once it has a result, or when its last
+/// caller gives up on it.
+struct FlightGuard<'a, T> {
+    flights: &'a Mutex<HashMap<String, Flight<T>>>,
+    key: &'a str,
+    flight: Flight<T>,
+}
+
+impl<T> Drop for FlightGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut flights = self.flights.lock().unwrap_or_else(PoisonError::into_inner);
+        // Callers clone the flight while holding the lock, so a count of two
+        // (the map and this guard) means nobody else is waiting on it
+        if let Some(current) = flights.get(self.key)
+            && Arc::ptr_eq(current, &self.flight)
+            && (self.flight.initialized() || Arc::strong_count(&self.flight) == 2)
+        {
+            flights.remove(self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_callers_share_one_load() {
+        let flights = SingleFlight::new();
+        let loads = AtomicUsize::new(0);
+        let load = || async {
+            loads.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(42)
+        };
+
+        let results = futures_util::future::join_all((0..10).map(|_| flights.run("k", load))).await;
+
+        assert!(results.iter().all(|r| *r.as_ref().unwrap() == 42));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(flights.in_flight(), 0);
+
+        // Completed loads are not reused
+        assert_eq!(flights.run("k", || async { Ok(7) }).await.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_errors_reach_every_caller() {
+        let flights: SingleFlight<u32> = SingleFlight::new();
+        let load = || async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Err(anyhow::anyhow!("db down"))
+        };
+
+        let (first, second) = tokio::join!(flights.run("k", load), flights.run("k", load));
+
+        assert_eq!(first.unwrap_err().to_string(), "db down");
+        assert_eq!(second.unwrap_err().to_string(), "db down");
+    }
+
+    #[tokio::test]
+    async fn test_errors_keep_their_code() {
+        let flights: SingleFlight<u32> = SingleFlight::new();
+        let err = flights
+            .run("k", || async { Err(ServiceError::new(ErrorCode::Timeout, "slow").into()) })
+            .await
+            .unwrap_err();
+
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::Timeout);
+        assert_eq!(err.to_string(), "slow");
+    }
+
+    #[tokio::test]
+    async fn test_waiter_takes_over_when_leader_is_cancelled() {
+        let flights = SingleFlight::new();
+        let slow = || async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(1)
+        };
+        let fast = || async { Ok(2) };
+
+        let leader = tokio::time::timeout(Duration::from_millis(10), flights.run("k", slow));
+        let (leader, follower) = tokio::join!(leader, async {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            flights.run("k", fast).await
+        });
+
+        assert!(leader.is_err());
+        assert_eq!(follower.unwrap(), 2);
+        assert_eq!(flights.in_flight(), 0);
+
+        // Abandoned by every caller
+        let _ = tokio::time::timeout(Duration::from_millis(1), flights.run("k", slow)).await;
+        assert_eq!(flights.in_flight(), 0);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::domain::models::{IvaRate, Profile};
+use crate::domain::traits::{CacheLookup, Generation, ProfileCacheTrait, ProfileRepositoryTrait, StaleEntry};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::env;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DependencyTimeouts {
+    pub db: Duration,
+    pub cache: Duration,
+}
+
+impl Default for DependencyTimeouts {
+    /// DB 5 ms and external cache 2 ms as per RT-003.
+    fn default() -> Self {
+        Self { db: Duration::from_millis(5), cache: Duration::from_millis(2) }
+    }
+}
+
+impl DependencyTimeouts {
+    /// Reads `DB_TIMEOUT_MS` (default 5) and `CACHE_TIMEOUT_MS` (default 2);
+    /// `0` disables the timeout.
+    pub fn from_env() -> Result<Self> {
+        let mut timeouts = Self::default();
+        if let Ok(value) = env::var("DB_TIMEOUT_MS") {
+            timeouts.db = Duration::from_millis(value.parse().context("Invalid value for DB_TIMEOUT_MS")?);
+        }
+        if let Ok(value) = env::var("CACHE_TIMEOUT_MS") {
+            timeouts.cache = Duration::from_millis(value.parse().context("Invalid value for CACHE_TIMEOUT_MS")?);
+        }
+        Ok(timeouts)
+    }
+}
+
+/// A dependency call did not complete within its timeout. Unlike
+/// `DeadlineExceeded`, the request may still have time to retry it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DependencyTimeout {
+    pub name: &'static str,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for DependencyTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} did not respond within {:?}", self.name, self.timeout)
+    }
+}
+
+impl std::error::Error for DependencyTimeout {}
+
+/// Bounds every call to the wrapped repository or cache, failing with
+/// `DependencyTimeout` when it takes longer.
+#[derive(Clone)]
+pub struct TimeLimited<T> {
+    inner: T,
+    name: &'static str,
+    timeout: Duration,
+}
+
+impl<T> TimeLimited<T> {
+    /// A zero `timeout` leaves calls unbounded.
+    pub fn new(inner: T, name: &'static str, timeout: Duration) -> Self {
+        Self { inner, name, timeout }
+    }
+
+    async fn run<V>(&self, call: impl Future<Output = Result<V>>) -> Result<V> {
+        if self.timeout.is_zero() {
+            return call.await;
+        }
+        match tokio::time::timeout(self.timeout, call).await {
+            Ok(result) => result,
+            Err(_) => Err(DependencyTimeout { name: self.name, timeout: self.timeout }.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: ProfileRepositoryTrait> ProfileRepositoryTrait for TimeLimited<R> {
+    async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
+        self.run(self.inner.get_by_id(client_id)).await
+    }
+
+    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>> {
+        self.run(self.inner.get_iva_rate(jurisdiction)).await
+    }
+}
+
+#[async_trait]
+impl<C: ProfileCacheTrait> ProfileCacheTrait for TimeLimited<C> {
+    async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
+        self.run(self.inner.get_by_id(client_id)).await
+    }
+
+    async fn set(&self, profile: &Profile) -> Result<()> {
+        self.run(self.inner.set(profile)).await
+    }
+
+    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>> {
+        self.run(self.inner.get_iva_rate(jurisdiction)).await
+    }
+
+    async fn set_iva_rate(&self, rate: &IvaRate) -> Result<()> {
+        self.run(self.inner.set_iva_rate(rate)).await
+    }
+
+    async fn lookup_profile(&self, client_id: &str) -> Result<CacheLookup<Profile>> {
+        self.run(self.inner.lookup_profile(client_id)).await
+    }
+
+    async fn set_profile_not_found(&self, client_id: &str) -> Result<()> {
+        self.run(self.inner.set_profile_not_found(client_id)).await
+    }
+
+    async fn lookup_iva_rate(&self, jurisdiction: &str) -> Result<CacheLookup<IvaRate>> {
+        self.run(self.inner.lookup_iva_rate(jurisdiction)).await
+    }
+
+    async fn set_iva_rate_not_found(&self, jurisdiction: &str) -> Result<()> {
+        self.run(self.inner.set_iva_rate_not_found(jurisdiction)).await
+    }
+
+    async fn get_stale_profile(&self, client_id: &str) -> Result<Option<StaleEntry<Profile>>> {
+        self.run(self.inner.get_stale_profile(client_id)).await
+    }
+
+    async fn get_stale_iva_rate(&self, jurisdiction: &str) -> Result<Option<StaleEntry<IvaRate>>> {
+        self.run(self.inner.get_stale_iva_rate(jurisdiction)).await
+    }
+
+    async fn profile_generation(&self, client_id: &str) -> Result<Generation> {
+        self.run(self.inner.profile_generation(client_id)).await
+    }
+
+    async fn fill_profile(&self, client_id: &str, profile: Option<&Profile>, generation: Generation) -> Result<()> {
+        self.run(self.inner.fill_profile(client_id, profile, generation)).await
+    }
+
+    async fn iva_rate_generation(&self, jurisdiction: &str) -> Result<Generation> {
+        self.run(self.inner.iva_rate_generation(jurisdiction)).await
+    }
+
+    async fn fill_iva_rate(&self, jurisdiction: &str, rate: Option<&IvaRate>, generation: Generation) -> Result<()> {
+        self.run(self.inner.fill_iva_rate(jurisdiction, rate, generation)).await
+    }
+
+    async fn invalidate_profile(&self, client_id: &str) -> Result<()> {
+        self.run(self.inner.invalidate_profile(client_id)).await
+    }
+
+    async fn invalidate_iva_rate(&self, jurisdiction: &str) -> Result<()> {
+        self.run(self.inner.invalidate_iva_rate(jurisdiction)).await
+    }
+
+    async fn invalidate_all_profiles(&self) -> Result<()> {
+        self.run(self.inner.invalidate_all_profiles()).await
+    }
+
+    async fn invalidate_all_iva_rates(&self) -> Result<()> {
+        self.run(self.inner.invalidate_all_iva_rates()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::errors::ErrorCode;
+    use crate::test_support::SlowRepo;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_calls_fail_with_dependency_timeout() {
+        let repo = TimeLimited::new(SlowRepo::new(Duration::from_millis(10)), "postgres", Duration::from_millis(5));
+
+        let err = repo.get_iva_rate("J1").await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<DependencyTimeout>(),
+            Some(&DependencyTimeout { name: "postgres", timeout: Duration::from_millis(5) })
+        );
+        assert_eq!(err.to_string(), "postgres did not respond within 5ms");
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::Timeout);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fast_or_unbounded_calls_succeed() {
+        let fast = TimeLimited::new(SlowRepo::new(Duration::from_millis(1)), "postgres", Duration::from_millis(5));
+        assert!(fast.get_iva_rate("J1").await.unwrap().is_some());
+
+        let unbounded = TimeLimited::new(SlowRepo::new(Duration::from_secs(1)), "postgres", Duration::ZERO);
+        assert!(unbounded.get_iva_rate("J1").await.unwrap().is_some());
+    }
+}
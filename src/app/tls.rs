@@ -0,0 +1,238 @@
+use anyhow::{Context, Result};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::RootCertStore;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use x509_parser::extensions::GeneralName;
+
+/// Upper bound for a client to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// TLS settings for the RPC listener.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// When set, clients must present a certificate signed by one of these
+    /// CAs (mutual TLS).
+    pub client_ca_path: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// Builds the acceptor shared by every RPC worker. Fails if a file is
+    /// missing or does not contain a usable certificate or key.
+    pub fn acceptor(&self) -> Result<TlsAcceptor> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let certs = load_certs(&self.cert_path)?;
+        let key = load_key(&self.key_path)?;
+
+        let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let builder = match &self.client_ca_path {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(path)? {
+                    roots
+                        .add(cert)
+                        .with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                    .build()
+                    .context("Failed to build client certificate verifier")?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let config = builder
+            .with_single_cert(certs, key)
+            .context("TLS certificate and key do not match")?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// TLS settings for connecting to the RPC listener.
+#[derive(Debug, Clone)]
+pub struct TlsClientConfig {
+    /// CAs trusted to sign the server certificate.
+    pub ca_path: PathBuf,
+    /// Name the server certificate must be valid for.
+    pub server_name: String,
+    /// Certificate and key presented to servers that require mutual TLS.
+    pub client_cert: Option<(PathBuf, PathBuf)>,
+}
+
+impl TlsClientConfig {
+    pub fn connector(&self) -> Result<(TlsConnector, ServerName<'static>)> {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&self.ca_path)? {
+            roots
+                .add(cert)
+                .with_context(|| format!("Invalid CA certificate in {}", self.ca_path.display()))?;
+        }
+        let builder = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots);
+        let config = match &self.client_cert {
+            Some((cert_path, key_path)) => builder
+                .with_client_auth_cert(load_certs(cert_path)?, load_key(key_path)?)
+                .context("Client certificate and key do not match")?,
+            None => builder.with_no_client_auth(),
+        };
+        let server_name = ServerName::try_from(self.server_name.clone())
+            .with_context(|| format!("Invalid TLS server name {}", self.server_name))?;
+        Ok((TlsConnector::from(Arc::new(config)), server_name))
+    }
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let pem = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid PEM in {}", path.display()))?;
+    anyhow::ensure!(!certs.is_empty(), "No certificates found in {}", path.display());
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let pem = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    rustls_pemfile::private_key(&mut pem.as_slice())
+        .with_context(|| format!("Invalid PEM in {}", path.display()))?
+        .with_context(|| format!("No private key found in {}", path.display()))
+}
+
+/// Identity of a client authenticated with a certificate, taken from its
+/// subject and subject alternative names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    pub common_name: Option<String>,
+    pub dns_names: Vec<String>,
+    pub uris: Vec<String>,
+}
+
+impl ClientIdentity {
+    pub fn from_certificate(der: &[u8]) -> Result<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der)
+            .map_err(|e| anyhow::anyhow!("Invalid client certificate: {}", e))?;
+
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_string);
+
+        let mut dns_names = Vec::new();
+        let mut uris = Vec::new();
+        if let Some(san) = cert.subject_alternative_name()? {
+            for name in &san.value.general_names {
+                match name {
+                    GeneralName::DNSName(dns) => dns_names.push(dns.to_string()),
+                    GeneralName::URI(uri) => uris.push(uri.to_string()),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(Self { common_name, dns_names, uris })
+    }
+
+    /// Name used in logs: the common name, or the first SAN without one.
+    pub fn name(&self) -> &str {
+        self.common_name
+            .as_deref()
+            .or(self.uris.first().map(String::as_str))
+            .or(self.dns_names.first().map(String::as_str))
+            .unwrap_or("<anonymous>")
+    }
+}
+
+impl fmt::Display for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Runs the server side of the handshake. The identity is `None` when the
+/// listener does not request client certificates.
+pub async fn accept<S>(acceptor: &TlsAcceptor, stream: S) -> Result<(TlsStream<S>, Option<ClientIdentity>)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+        .await
+        .context("TLS handshake timed out")?
+        .context("TLS handshake failed")?;
+
+    let identity = match stream.get_ref().1.peer_certificates().and_then(|certs| certs.first()) {
+        Some(cert) => Some(ClientIdentity::from_certificate(cert)?),
+        None => None,
+    };
+    Ok((stream, identity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls").join(name)
+    }
+
+    fn server_config(mutual: bool) -> TlsConfig {
+        TlsConfig {
+            cert_path: fixture("server.pem"),
+            key_path: fixture("server.key"),
+            client_ca_path: mutual.then(|| fixture("ca.pem")),
+        }
+    }
+
+    fn client_config(with_client_cert: bool) -> TlsClientConfig {
+        TlsClientConfig {
+            ca_path: fixture("ca.pem"),
+            server_name: "localhost".to_string(),
+            client_cert: with_client_cert.then(|| (fixture("client.pem"), fixture("client.key"))),
+        }
+    }
+
+    async fn handshake(config: TlsConfig, with_client_cert: bool) -> Result<Option<ClientIdentity>> {
+        let acceptor = config.acceptor()?;
+        let (client, server) = tokio::io::duplex(16 * 1024);
+        let (connector, server_name) = client_config(with_client_cert).connector()?;
+
+        let connect = connector.connect(server_name, client);
+        let (accepted, _) = tokio::join!(accept(&acceptor, server), connect);
+        accepted.map(|(_, identity)| identity)
+    }
+
+    #[test]
+    fn test_client_identity_from_certificate() {
+        let cert = load_certs(&fixture("client.pem")).unwrap().remove(0);
+        let identity = ClientIdentity::from_certificate(&cert).unwrap();
+
+        assert_eq!(identity.common_name.as_deref(), Some("billing-service"));
+        assert_eq!(identity.dns_names, vec!["billing.internal".to_string()]);
+        assert_eq!(identity.uris, vec!["spiffe://tax/billing".to_string()]);
+        assert_eq!(identity.to_string(), "billing-service");
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls_exposes_client_identity() {
+        let identity = handshake(server_config(true), true).await.unwrap();
+        assert_eq!(identity.unwrap().name(), "billing-service");
+
+        let identity = handshake(server_config(false), false).await.unwrap();
+        assert!(identity.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls_rejects_client_without_certificate() {
+        assert!(handshake(server_config(true), false).await.is_err());
+    }
+}
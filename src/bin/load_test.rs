@@ -263,7 +263,7 @@ fn print_metrics(duration_secs: f64) {
     let l_5_10ms = LATENCY_5_10MS.load(Ordering::Relaxed);
     let over_10ms = LATENCY_OVER_10MS.load(Ordering::Relaxed);
     
-    let avg_latency = if success > 0 { total_latency / success } else { 0 };
+    let avg_latency = total_latency.checked_div(success).unwrap_or(0);
     let rps = if duration_secs > 0.0 { total as f64 / duration_secs } else { 0.0 };
     
     println!("\n╔════════════════════════════════════════════════════════════╗");
@@ -0,0 +1,480 @@
+//! Command-line client for ad-hoc calculations against a running server.
+//!
+//! ```bash
+//! tax-cli calculate --client client_1 --amount 100 --jurisdiction BUENOS_AIRES --product PROD
+//! tax-cli --format json calculate --client client_1 --amount 100 --jurisdiction TDF --product PROD --date 2026-01-31
+//! tax-cli batch --file transactions.csv
+//! ```
+//!
+//! Run `tax-cli --help` for every option.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use futures_util::StreamExt;
+use serde::Serialize;
+use std::env;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+use tax_manager::app::errors::ErrorCode;
+use tax_manager::app::tls::TlsClientConfig;
+use tax_manager::client::{ClientConfig, TaxEngineClient, TaxRequest, TaxResponse};
+
+const USAGE: &str = "\
+Usage: tax-cli [OPTIONS] <COMMAND>
+
+Commands:
+  calculate --client <ID> --amount <AMOUNT> --jurisdiction <J> --product <P> [--date <YYYY-MM-DD>]
+  batch --file <CSV>
+      CSV with a header row and the columns client_id, amount, jurisdiction,
+      product and, optionally, date
+
+Options:
+  --addr <ADDR>             host:port or unix:/path [env: RPC_ADDR, default: 127.0.0.1:50051]
+  --token <TOKEN>           Gateway token [env: TAX_ENGINE_TOKEN]
+  --format <table|json>     Output format [default: table]
+  --timeout-ms <MS>         Deadline for each calculation [default: 1000]
+  --concurrency <N>         Calculations in flight during a batch [default: 16]
+  --tls-ca <PEM>            Enables TLS, trusting these CAs
+  --tls-server-name <NAME>  Name in the server certificate [default: host of --addr]
+  --tls-cert <PEM>          Client certificate for mutual TLS (requires --tls-key)
+  --tls-key <PEM>           Client key for mutual TLS
+  -h, --help                Print this help";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Table,
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Calculate(TaxRequest),
+    Batch(PathBuf),
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    addr: String,
+    token: Option<String>,
+    format: Format,
+    timeout: Duration,
+    concurrency: usize,
+    tls_ca: Option<PathBuf>,
+    tls_server_name: Option<String>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    command: Command,
+}
+
+/// Splits `--name value` pairs; every option takes a value.
+fn take_options(args: &[String]) -> Result<Vec<(&str, &str)>> {
+    let mut options = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let name = arg
+            .strip_prefix("--")
+            .with_context(|| format!("unexpected argument {}", arg))?;
+        let value = iter.next().with_context(|| format!("--{} requires a value", name))?;
+        options.push((name, value.as_str()));
+    }
+    Ok(options)
+}
+
+/// Whether `-h` or `--help` appears where an option name is expected, so an
+/// option value such as `--client --help` is not mistaken for it.
+fn wants_help(args: &[String]) -> bool {
+    let mut at = 0;
+    while let Some(arg) = args.get(at) {
+        if arg == "-h" || arg == "--help" {
+            return true;
+        }
+        at += if arg.starts_with("--") { 2 } else { 1 };
+    }
+    false
+}
+
+fn parse_args(args: &[String]) -> Result<Args> {
+    // Global options come in pairs before the command
+    let mut command_at = 0;
+    while args.get(command_at).is_some_and(|arg| arg.starts_with("--")) {
+        command_at += 2;
+    }
+    let command = args.get(command_at).context("missing command: calculate or batch")?;
+
+    let mut parsed = Args {
+        addr: env::var("RPC_ADDR").unwrap_or_else(|_| "127.0.0.1:50051".to_string()),
+        token: env::var("TAX_ENGINE_TOKEN").ok(),
+        format: Format::Table,
+        timeout: Duration::from_secs(1),
+        concurrency: 16,
+        tls_ca: None,
+        tls_server_name: None,
+        tls_cert: None,
+        tls_key: None,
+        command: Command::Batch(PathBuf::new()),
+    };
+    for (name, value) in take_options(&args[..command_at])? {
+        match name {
+            "addr" => parsed.addr = value.to_string(),
+            "token" => parsed.token = Some(value.to_string()),
+            "format" => {
+                parsed.format = match value {
+                    "table" => Format::Table,
+                    "json" => Format::Json,
+                    _ => anyhow::bail!("--format must be table or json"),
+                }
+            }
+            "timeout-ms" => parsed.timeout = Duration::from_millis(value.parse().context("invalid --timeout-ms")?),
+            "concurrency" => parsed.concurrency = value.parse().context("invalid --concurrency")?,
+            "tls-ca" => parsed.tls_ca = Some(PathBuf::from(value)),
+            "tls-server-name" => parsed.tls_server_name = Some(value.to_string()),
+            "tls-cert" => parsed.tls_cert = Some(PathBuf::from(value)),
+            "tls-key" => parsed.tls_key = Some(PathBuf::from(value)),
+            _ => anyhow::bail!("unknown option --{}", name),
+        }
+    }
+    anyhow::ensure!(parsed.concurrency > 0, "--concurrency must be greater than zero");
+
+    let options = take_options(&args[command_at + 1..])?;
+    parsed.command = match command.as_str() {
+        "calculate" => {
+            let mut fields = TransactionFields::default();
+            for (name, value) in options {
+                fields.set(name, value)?;
+            }
+            Command::Calculate(fields.into_request()?)
+        }
+        "batch" => match options.as_slice() {
+            [("file", path)] => Command::Batch(PathBuf::from(path)),
+            _ => anyhow::bail!("batch takes exactly --file <CSV>"),
+        },
+        _ => anyhow::bail!("unknown command {}", command),
+    };
+    Ok(parsed)
+}
+
+/// Fields of a transaction, from command-line options or a CSV row.
+#[derive(Default)]
+struct TransactionFields {
+    client: Option<String>,
+    amount: Option<f64>,
+    jurisdiction: Option<String>,
+    product: Option<String>,
+    date: Option<NaiveDate>,
+}
+
+impl TransactionFields {
+    fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "client" | "client_id" => self.client = Some(value.to_string()),
+            "amount" => self.amount = Some(value.parse().with_context(|| format!("invalid amount {}", value))?),
+            "jurisdiction" => self.jurisdiction = Some(value.to_string()),
+            "product" => self.product = Some(value.to_string()),
+            "date" if value.is_empty() => self.date = None,
+            "date" => {
+                self.date = Some(
+                    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                        .with_context(|| format!("invalid date {}, expected YYYY-MM-DD", value))?,
+                )
+            }
+            _ => anyhow::bail!("unknown field {}", name),
+        }
+        Ok(())
+    }
+
+    fn into_request(self) -> Result<TaxRequest> {
+        let mut request = TaxRequest::new(
+            self.client.context("client is required")?,
+            self.amount.context("amount is required")?,
+            self.jurisdiction.context("jurisdiction is required")?,
+            self.product.context("product is required")?,
+        );
+        request.date = self.date;
+        Ok(request)
+    }
+}
+
+/// Reads a CSV without quoting: a header row naming the columns, then one
+/// transaction per row. Each request comes with its line number.
+fn read_batch(csv: &str) -> Result<Vec<(usize, TaxRequest)>> {
+    let mut lines = csv.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().context("empty file")?;
+    let columns: Vec<&str> = header.split(',').map(str::trim).collect();
+
+    let mut requests = Vec::new();
+    for (index, line) in lines {
+        let values: Vec<&str> = line.split(',').map(str::trim).collect();
+        anyhow::ensure!(
+            values.len() == columns.len(),
+            "line {}: expected {} columns, found {}",
+            index + 1,
+            columns.len(),
+            values.len()
+        );
+        let mut fields = TransactionFields::default();
+        for (column, value) in columns.iter().zip(values) {
+            fields.set(column, value).with_context(|| format!("line {}", index + 1))?;
+        }
+        requests.push((index + 1, fields.into_request().with_context(|| format!("line {}", index + 1))?));
+    }
+    Ok(requests)
+}
+
+/// The host part of `host:port` or `[ipv6]:port`, to verify the certificate
+/// against when `--tls-server-name` is not given.
+fn default_server_name(addr: &str) -> &str {
+    if let Some((host, _)) = addr.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+        return host;
+    }
+    addr.rsplit_once(':').map_or(addr, |(host, _)| host)
+}
+
+fn client_config(args: &Args) -> Result<ClientConfig> {
+    // A batch pipelines its calculations over a single connection
+    let mut config = ClientConfig::new(&args.addr).with_call_timeout(args.timeout);
+    if let Some(token) = &args.token {
+        config = config.with_token(token.clone());
+    }
+    if let Some(ca_path) = &args.tls_ca {
+        let server_name = match &args.tls_server_name {
+            Some(name) => name.clone(),
+            None => default_server_name(&args.addr).to_string(),
+        };
+        let client_cert = match (&args.tls_cert, &args.tls_key) {
+            (Some(cert), Some(key)) => Some((cert.clone(), key.clone())),
+            (None, None) => None,
+            _ => anyhow::bail!("--tls-cert and --tls-key must be used together"),
+        };
+        config = config.with_tls(TlsClientConfig { ca_path: ca_path.clone(), server_name, client_cert });
+    }
+    Ok(config)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchResult {
+    line: usize,
+    client_id: String,
+    amount: f64,
+    jurisdiction: String,
+    product: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date: Option<NaiveDate>,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Outcome {
+    Ok(TaxResponse),
+    Error { error: ErrorBody },
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+}
+
+impl From<anyhow::Error> for ErrorBody {
+    fn from(e: anyhow::Error) -> Self {
+        Self { code: ErrorCode::from_error(&e).as_str(), message: format!("{:#}", e) }
+    }
+}
+
+fn print_response(response: &TaxResponse) {
+    println!("{:<10} {:>14} {:>8} {:>14}", "TAX", "BASE", "RATE", "AMOUNT");
+    for line in &response.breakdown {
+        println!(
+            "{:<10} {:>14.2} {:>7.2}% {:>14.2}",
+            format!("{:?}", line.tax_type),
+            line.base,
+            line.rate * 100.0,
+            line.amount
+        );
+    }
+    println!("{:<10} {:>38.2}", "TOTAL", response.total_amount);
+}
+
+fn print_batch(results: &[BatchResult]) {
+    println!(
+        "{:<5} {:<16} {:>14} {:<16} {:<12} {:>14}  DETAIL",
+        "LINE", "CLIENT", "AMOUNT", "JURISDICTION", "PRODUCT", "TOTAL"
+    );
+    for result in results {
+        let (total, detail) = match &result.outcome {
+            Outcome::Ok(response) => (
+                format!("{:.2}", response.total_amount),
+                response
+                    .breakdown
+                    .iter()
+                    .map(|line| format!("{:?} {:.2}% = {:.2}", line.tax_type, line.rate * 100.0, line.amount))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            Outcome::Error { error } => ("-".to_string(), format!("{}: {}", error.code, error.message)),
+        };
+        println!(
+            "{:<5} {:<16} {:>14.2} {:<16} {:<12} {:>14}  {}",
+            result.line,
+            result.client_id,
+            result.amount,
+            result.jurisdiction,
+            result.product,
+            total,
+            detail
+        );
+    }
+}
+
+async fn run(args: Args) -> Result<bool> {
+    let client = TaxEngineClient::new(client_config(&args)?)?;
+
+    match args.command {
+        // Also sent as the deadline, so the server stops working on it too
+        Command::Calculate(request) => match client.calculate(request.with_timeout(args.timeout)).await {
+            Ok(response) => {
+                match args.format {
+                    Format::Table => print_response(&response),
+                    Format::Json => println!("{}", serde_json::to_string_pretty(&response)?),
+                }
+                Ok(true)
+            }
+            Err(e) => {
+                let error = ErrorBody::from(e);
+                match args.format {
+                    Format::Table => eprintln!("{}: {}", error.code, error.message),
+                    Format::Json => println!("{}", serde_json::to_string_pretty(&Outcome::Error { error })?),
+                }
+                Ok(false)
+            }
+        },
+        Command::Batch(path) => {
+            let csv = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let requests = read_batch(&csv).with_context(|| format!("Invalid batch file {}", path.display()))?;
+
+            // Results keep the order of the file
+            let results: Vec<BatchResult> = futures_util::stream::iter(requests)
+                .map(|(line, request)| {
+                    let client = client.clone();
+                    async move {
+                        let outcome = match client.calculate(request.clone().with_timeout(args.timeout)).await {
+                            Ok(response) => Outcome::Ok(response),
+                            Err(e) => Outcome::Error { error: e.into() },
+                        };
+                        BatchResult {
+                            line,
+                            client_id: request.client_id,
+                            amount: request.amount,
+                            jurisdiction: request.jurisdiction,
+                            product: request.product,
+                            date: request.date,
+                            outcome,
+                        }
+                    }
+                })
+                .buffered(args.concurrency)
+                .collect()
+                .await;
+
+            match args.format {
+                Format::Table => print_batch(&results),
+                Format::Json => println!("{}", serde_json::to_string_pretty(&results)?),
+            }
+            let failed = results.iter().filter(|r| matches!(r.outcome, Outcome::Error { .. })).count();
+            if failed > 0 {
+                eprintln!("{} of {} calculations failed", failed, results.len());
+            }
+            Ok(failed == 0)
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || wants_help(&args) {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {:#}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_parse_calculate() {
+        let parsed = parse_args(&args(
+            "--addr unix:/run/tax.sock --format json calculate --client c1 --amount 100.5 \
+             --jurisdiction TDF --product PROD --date 2026-01-31",
+        ))
+        .unwrap();
+
+        assert_eq!(parsed.addr, "unix:/run/tax.sock");
+        assert_eq!(parsed.format, Format::Json);
+        let expected = TaxRequest::new("c1", 100.5, "TDF", "PROD").with_date(NaiveDate::from_ymd_opt(2026, 1, 31).unwrap());
+        assert_eq!(parsed.command, Command::Calculate(expected));
+    }
+
+    #[test]
+    fn test_parse_rejects_incomplete_calculate() {
+        assert!(parse_args(&args("calculate --client c1 --amount 100")).is_err());
+        assert!(parse_args(&args("calculate --client c1 --amount ten --jurisdiction J --product P")).is_err());
+        assert!(parse_args(&args("--format xml batch --file t.csv")).is_err());
+    }
+
+    #[test]
+    fn test_help_is_not_taken_from_option_values() {
+        assert!(wants_help(&args("--help")));
+        assert!(wants_help(&args("--addr host:1 calculate -h")));
+        assert!(!wants_help(&args("calculate --client --help --amount 1")));
+        assert!(!wants_help(&args("--token -h batch --file t.csv")));
+    }
+
+    #[test]
+    fn test_default_server_name() {
+        assert_eq!(default_server_name("tax.internal:50051"), "tax.internal");
+        assert_eq!(default_server_name("[::1]:50051"), "::1");
+        assert_eq!(default_server_name("localhost"), "localhost");
+    }
+
+    #[test]
+    fn test_read_batch_by_header() {
+        let csv = "product,client_id,amount,jurisdiction,date\n\
+                   PROD,c1,100,TDF,\n\
+                   \n\
+                   PROD,c2,50.5,BUENOS_AIRES,2026-01-31\n";
+        let requests = read_batch(csv).unwrap();
+
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0], (2, TaxRequest::new("c1", 100.0, "TDF", "PROD")));
+        assert_eq!(requests[1].0, 4);
+        assert_eq!(requests[1].1.date, NaiveDate::from_ymd_opt(2026, 1, 31));
+
+        let err = read_batch("client_id,amount,jurisdiction,product\nc1,abc,TDF,PROD\n").unwrap_err();
+        assert!(format!("{:#}", err).contains("line 2"));
+    }
+}
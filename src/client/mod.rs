@@ -0,0 +1,568 @@
+use anyhow::{Context, Result};
+use capnp::capability::FromClientHook;
+use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
+use chrono::NaiveDate;
+use futures_util::{AsyncReadExt, FutureExt};
+use rustls::pki_types::ServerName;
+use serde::Serialize;
+use std::cell::Cell;
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+use tokio_rustls::TlsConnector;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tracing::{debug, warn};
+
+use crate::app::errors::{ErrorCode, ServiceError};
+use crate::app::tls::TlsClientConfig;
+use crate::domain::models::TaxType;
+use crate::schema_capnp::{gateway, tax_engine};
+
+/// Calls waiting for a connection before `calculate` waits for room.
+const QUEUE_CAPACITY: usize = 1024;
+/// Delay before retrying a failed connection, doubled on every failure.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(50);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Where the server listens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    /// `unix:/path/to/socket` for a Unix socket, `host:port` otherwise.
+    pub fn parse(addr: &str) -> Self {
+        match addr.strip_prefix("unix:") {
+            Some(path) => Endpoint::Unix(PathBuf::from(path)),
+            None => Endpoint::Tcp(addr.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(addr) => f.write_str(addr),
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    pub endpoint: Endpoint,
+    /// Connections in the pool, each served by its own thread.
+    pub connections: usize,
+    /// Upper bound for a call, including the wait for a connection.
+    pub call_timeout: Duration,
+    pub connect_timeout: Duration,
+    /// `Gateway.login` token, for servers configured with a key set.
+    pub token: Option<String>,
+    /// TLS for TCP endpoints.
+    pub tls: Option<TlsClientConfig>,
+}
+
+impl ClientConfig {
+    pub fn new(addr: &str) -> Self {
+        Self {
+            endpoint: Endpoint::parse(addr),
+            connections: 1,
+            call_timeout: Duration::from_secs(1),
+            connect_timeout: Duration::from_secs(1),
+            token: None,
+            tls: None,
+        }
+    }
+
+    pub fn with_connections(mut self, connections: usize) -> Self {
+        self.connections = connections;
+        self
+    }
+
+    pub fn with_call_timeout(mut self, timeout: Duration) -> Self {
+        self.call_timeout = timeout;
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsClientConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+}
+
+/// A calculation request, mirroring `Transaction`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaxRequest {
+    pub client_id: String,
+    pub amount: f64,
+    pub jurisdiction: String,
+    pub product: String,
+    /// `None` uses the server's current date.
+    pub date: Option<NaiveDate>,
+    /// Overrides `ClientConfig::call_timeout` and is sent as the server-side
+    /// deadline; without it the server applies its `REQUEST_TIMEOUT_MS`.
+    pub timeout: Option<Duration>,
+}
+
+impl TaxRequest {
+    pub fn new(client_id: impl Into<String>, amount: f64, jurisdiction: impl Into<String>, product: impl Into<String>) -> Self {
+        Self {
+            client_id: client_id.into(),
+            amount,
+            jurisdiction: jurisdiction.into(),
+            product: product.into(),
+            date: None,
+            timeout: None,
+        }
+    }
+
+    pub fn with_date(mut self, date: NaiveDate) -> Self {
+        self.date = Some(date);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxResponse {
+    pub total_amount: f64,
+    pub breakdown: Vec<TaxLine>,
+}
+
+/// One line of `TaxResponse`, as `calculate` returns it (`Float64`).
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaxLine {
+    pub tax_type: TaxType,
+    pub base: f64,
+    pub rate: f64,
+    pub amount: f64,
+}
+
+struct Call {
+    request: TaxRequest,
+    reply: oneshot::Sender<Result<TaxResponse>>,
+}
+
+/// Handle to a pool of `TaxEngine` connections.
+///
+/// Cap'n Proto clients are tied to the thread that created them, so each
+/// connection lives on its own thread with a `LocalSet`; the handle only holds
+/// channels to them and is cheap to clone and share across tasks. Calls are
+/// spread round-robin and pipelined on each connection.
+///
+/// Connections are opened on first use. A connection that fails is dropped
+/// and opened again by the next call, waiting `INITIAL_BACKOFF` (up to
+/// `MAX_BACKOFF`) between failed attempts; calls arriving meanwhile fail
+/// with `UNAVAILABLE` instead of waiting.
+///
+/// Errors carry the server's `ErrorCode`, so `ErrorCode::from_error` tells
+/// e.g. `OVERLOADED` (retry later) apart from `INVALID_REQUEST`.
+#[derive(Clone)]
+pub struct TaxEngineClient {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    workers: Vec<mpsc::Sender<Call>>,
+    next: AtomicUsize,
+    call_timeout: Duration,
+}
+
+impl TaxEngineClient {
+    /// Starts the connection threads. Fails only on an invalid TLS
+    /// configuration or if a thread cannot be spawned; the server does not
+    /// need to be up yet.
+    pub fn new(config: ClientConfig) -> Result<Self> {
+        anyhow::ensure!(config.connections > 0, "at least one connection is required");
+        let tls = match &config.tls {
+            Some(tls) => Some(tls.connector().context("Invalid TLS configuration")?),
+            None => None,
+        };
+
+        let mut workers = Vec::with_capacity(config.connections);
+        for id in 0..config.connections {
+            let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+            let (config, tls) = (config.clone(), tls.clone());
+            std::thread::Builder::new()
+                .name(format!("tax-client-{}", id))
+                .spawn(move || {
+                    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                        Ok(runtime) => runtime,
+                        Err(e) => {
+                            warn!("Tax engine client connection {} could not start: {}", id, e);
+                            return;
+                        }
+                    };
+                    let local = tokio::task::LocalSet::new();
+                    local.block_on(&runtime, Worker::new(&config, tls).run(receiver));
+                })
+                .context("Failed to spawn client connection thread")?;
+            workers.push(sender);
+        }
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                workers,
+                next: AtomicUsize::new(0),
+                call_timeout: config.call_timeout,
+            }),
+        })
+    }
+
+    pub async fn calculate(&self, request: TaxRequest) -> Result<TaxResponse> {
+        let timeout = request.timeout.unwrap_or(self.inner.call_timeout);
+        let index = self.inner.next.fetch_add(1, Ordering::Relaxed) % self.inner.workers.len();
+        let worker = &self.inner.workers[index];
+
+        let call = async {
+            let (reply, response) = oneshot::channel();
+            worker
+                .send(Call { request, reply })
+                .await
+                .map_err(|_| service_error(ErrorCode::Unavailable, "client connection thread stopped"))?;
+            response
+                .await
+                .map_err(|_| service_error(ErrorCode::Unavailable, "client connection thread stopped"))?
+        };
+        // Dropping the call on timeout drops its reply channel, which cancels
+        // the request on the connection thread
+        match tokio::time::timeout(timeout, call).await {
+            Ok(result) => result,
+            Err(_) => Err(service_error(ErrorCode::Timeout, format!("no response within {:?}", timeout))),
+        }
+    }
+}
+
+struct Connection {
+    engine: tax_engine::Client,
+    /// Cleared when the RPC system stops or a call reports a disconnect.
+    alive: Rc<Cell<bool>>,
+}
+
+/// Owns one connection, on its own thread.
+struct Worker {
+    endpoint: Endpoint,
+    connect_timeout: Duration,
+    token: Option<String>,
+    tls: Option<(TlsConnector, ServerName<'static>)>,
+    connection: Option<Connection>,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl Worker {
+    fn new(config: &ClientConfig, tls: Option<(TlsConnector, ServerName<'static>)>) -> Self {
+        Self {
+            endpoint: config.endpoint.clone(),
+            connect_timeout: config.connect_timeout,
+            token: config.token.clone(),
+            tls,
+            connection: None,
+            backoff: INITIAL_BACKOFF,
+            retry_at: None,
+        }
+    }
+
+    async fn run(mut self, mut calls: mpsc::Receiver<Call>) {
+        while let Some(call) = calls.recv().await {
+            // The caller gave up while the call was queued
+            if call.reply.is_closed() {
+                continue;
+            }
+            match self.engine().await {
+                Ok((engine, alive)) => {
+                    tokio::task::spawn_local(send_call(engine, alive, call));
+                }
+                Err(e) => {
+                    let _ = call.reply.send(Err(e));
+                }
+            }
+        }
+        debug!("Tax engine client connection to {} closed", self.endpoint);
+    }
+
+    async fn engine(&mut self) -> Result<(tax_engine::Client, Rc<Cell<bool>>)> {
+        if let Some(connection) = &self.connection
+            && connection.alive.get()
+        {
+            return Ok((connection.engine.clone(), connection.alive.clone()));
+        }
+        self.connection = None;
+
+        if let Some(retry_at) = self.retry_at
+            && Instant::now() < retry_at
+        {
+            return Err(service_error(
+                ErrorCode::Unavailable,
+                format!("not connected to {}; retrying in {:?}", self.endpoint, retry_at - Instant::now()),
+            ));
+        }
+
+        let connected = match tokio::time::timeout(self.connect_timeout, self.connect()).await {
+            Ok(connected) => connected,
+            Err(_) => Err(anyhow::anyhow!("timed out after {:?}", self.connect_timeout)),
+        };
+        match connected {
+            Ok(connection) => {
+                debug!("Connected to tax engine at {}", self.endpoint);
+                self.backoff = INITIAL_BACKOFF;
+                self.retry_at = None;
+                let engine = (connection.engine.clone(), connection.alive.clone());
+                self.connection = Some(connection);
+                Ok(engine)
+            }
+            Err(e) => {
+                warn!("Failed to connect to tax engine at {}: {:#}", self.endpoint, e);
+                self.retry_at = Some(Instant::now() + self.backoff);
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                Err(service_error(
+                    ErrorCode::Unavailable,
+                    format!("failed to connect to {}: {:#}", self.endpoint, e),
+                ))
+            }
+        }
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        match &self.endpoint {
+            Endpoint::Tcp(addr) => {
+                let stream = TcpStream::connect(addr).await?;
+                stream.set_nodelay(true)?;
+                match &self.tls {
+                    Some((connector, server_name)) => {
+                        let stream = connector
+                            .connect(server_name.clone(), stream)
+                            .await
+                            .context("TLS handshake failed")?;
+                        self.bootstrap(stream).await
+                    }
+                    None => self.bootstrap(stream).await,
+                }
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => self.bootstrap(UnixStream::connect(path).await?).await,
+            #[cfg(not(unix))]
+            Endpoint::Unix(path) => anyhow::bail!("{} needs Unix socket support", path.display()),
+        }
+    }
+
+    async fn bootstrap<S>(&self, stream: S) -> Result<Connection>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + 'static,
+    {
+        let (reader, writer) = stream.compat().split();
+        let network = twoparty::VatNetwork::new(reader, writer, rpc_twoparty_capnp::Side::Client, Default::default());
+        let mut rpc_system = RpcSystem::new(Box::new(network), None);
+        let bootstrap: capnp::capability::Client = rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
+        let disconnector = rpc_system.get_disconnector();
+
+        let alive = Rc::new(Cell::new(true));
+        let connection_alive = alive.clone();
+        tokio::task::spawn_local(rpc_system.map(move |result| {
+            if let Err(e) = result {
+                debug!("Tax engine connection closed: {}", e);
+            }
+            connection_alive.set(false);
+        }));
+
+        let engine = match &self.token {
+            None => bootstrap.cast_to::<tax_engine::Client>(),
+            Some(token) => {
+                let gateway = bootstrap.cast_to::<gateway::Client>();
+                let mut request = gateway.login_request();
+                request.get().set_token(token.as_str());
+                let login = async { request.send().promise.await?.get()?.get_engine() };
+                match login.await {
+                    Ok(engine) => engine,
+                    Err(e) => {
+                        let _ = disconnector.await;
+                        return Err(call_error(e)).context("Gateway login failed");
+                    }
+                }
+            }
+        };
+        Ok(Connection { engine, alive })
+    }
+}
+
+async fn send_call(engine: tax_engine::Client, alive: Rc<Cell<bool>>, call: Call) {
+    let Call { request, mut reply } = call;
+
+    let mut rpc = engine.calculate_request();
+    {
+        let mut tx = rpc.get().init_tx();
+        tx.set_client_id(request.client_id.as_str());
+        tx.set_amount(request.amount);
+        tx.set_jurisdiction(request.jurisdiction.as_str());
+        tx.set_product(request.product.as_str());
+        if let Some(date) = request.date {
+            tx.set_date(date.format("%Y-%m-%d").to_string());
+        }
+        if let Some(timeout) = request.timeout {
+            tx.set_timeout_ms(u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX).max(1));
+        }
+    }
+
+    // Dropping the promise when the caller gives up cancels the call
+    let result = tokio::select! {
+        result = rpc.send().promise => result,
+        _ = reply.closed() => return,
+    };
+    let result = match result {
+        Ok(response) => read_response(response),
+        Err(e) => {
+            if e.kind == capnp::ErrorKind::Disconnected {
+                alive.set(false);
+            }
+            Err(call_error(e))
+        }
+    };
+    let _ = reply.send(result);
+}
+
+fn read_response(
+    response: capnp::capability::Response<tax_engine::calculate_results::Owned>,
+) -> Result<TaxResponse> {
+    let response = response.get()?.get_response()?;
+    let details = response.get_breakdown()?;
+    let mut breakdown = Vec::with_capacity(details.len() as usize);
+    for detail in details.iter() {
+        breakdown.push(TaxLine {
+            tax_type: parse_tax_type(detail.get_tax_type()?.to_str()?)?,
+            base: detail.get_base(),
+            rate: detail.get_rate(),
+            amount: detail.get_amount(),
+        });
+    }
+    Ok(TaxResponse { total_amount: response.get_total_amount(), breakdown })
+}
+
+fn parse_tax_type(name: &str) -> Result<TaxType> {
+    TaxType::parse(name).with_context(|| format!("unknown tax type {}", name))
+}
+
+fn service_error(code: ErrorCode, message: impl Into<String>) -> anyhow::Error {
+    ServiceError::new(code, message).into()
+}
+
+/// Recovers the `ErrorCode` the server put in front of the description
+/// (see `ErrorCode::to_capnp`).
+fn call_error(e: capnp::Error) -> anyhow::Error {
+    let description = e.extra.strip_prefix("remote exception: ").unwrap_or(&e.extra);
+    if let Some((code, message)) = description.split_once(": ")
+        && let Some(code) = ErrorCode::parse(code)
+    {
+        return service_error(code, message);
+    }
+    let code = match e.kind {
+        capnp::ErrorKind::Overloaded => ErrorCode::Overloaded,
+        capnp::ErrorKind::Disconnected => ErrorCode::Unavailable,
+        _ => ErrorCode::Internal,
+    };
+    service_error(code, description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::orchestrator::Orchestrator;
+    use crate::app::resolver::ProfileResolver;
+    use crate::app::rpc::TaxEngineImpl;
+    use crate::domain::calculators::IVACalculator;
+    use crate::test_support::FixedStore;
+
+    /// Serves `TaxEngine` on a random local port from a dedicated thread.
+    fn spawn_server() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+            let local = tokio::task::LocalSet::new();
+            local.block_on(&runtime, async move {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                let orchestrator = Arc::new(Orchestrator::new(ProfileResolver::new(FixedStore, FixedStore), IVACalculator));
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let (reader, writer) = stream.compat().split();
+                    let network =
+                        twoparty::VatNetwork::new(reader, writer, rpc_twoparty_capnp::Side::Server, Default::default());
+                    let engine: tax_engine::Client = capnp_rpc::new_client(TaxEngineImpl::new(orchestrator.clone()));
+                    tokio::task::spawn_local(RpcSystem::new(Box::new(network), Some(engine.client)));
+                }
+            });
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_calculate_over_pool() {
+        let client = TaxEngineClient::new(ClientConfig::new(&spawn_server()).with_connections(2)).unwrap();
+
+        for _ in 0..4 {
+            let response = client
+                .calculate(TaxRequest::new("c1", 100.0, "BUENOS_AIRES", "PROD"))
+                .await
+                .unwrap();
+            assert_eq!(response.total_amount, 21.0);
+            assert_eq!(response.breakdown[0].tax_type, TaxType::IVA);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server_is_unavailable() {
+        // Bound and dropped, so nothing listens there
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let client = TaxEngineClient::new(ClientConfig::new(&addr.to_string())).unwrap();
+
+        let err = client.calculate(TaxRequest::new("c1", 100.0, "X", "P")).await.unwrap_err();
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::Unavailable);
+        // Within the backoff, calls fail without reconnecting
+        let err = client.calculate(TaxRequest::new("c1", 100.0, "X", "P")).await.unwrap_err();
+        assert!(err.to_string().contains("retrying"));
+    }
+
+    #[test]
+    fn test_call_error_keeps_server_code() {
+        let err = call_error(capnp::Error::failed("remote exception: PERMISSION_DENIED: not allowed".to_string()));
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::PermissionDenied);
+        assert_eq!(err.to_string(), "not allowed");
+
+        let err = call_error(capnp::Error::disconnected("connection reset".to_string()));
+        assert_eq!(ErrorCode::from_error(&err), ErrorCode::Unavailable);
+    }
+
+    #[test]
+    fn test_endpoint_parse() {
+        assert_eq!(Endpoint::parse("127.0.0.1:50051"), Endpoint::Tcp("127.0.0.1:50051".to_string()));
+        assert_eq!(Endpoint::parse("unix:/run/tax.sock"), Endpoint::Unix(PathBuf::from("/run/tax.sock")));
+    }
+}
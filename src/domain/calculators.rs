@@ -1,24 +1,27 @@
 use crate::domain::models::{Transaction, Profile, TaxBreakdown, TaxType};
+use anyhow::{Context, Result};
+use rust_decimal::Decimal;
 
 #[derive(Debug, Clone)]
 pub struct IVACalculator;
 
 impl IVACalculator {
-    pub fn calculate(&self, tx: &Transaction, profile: &Profile, jurisdiction_rate: f64) -> TaxBreakdown {
+    /// The amount is `tx.amount` times the rate, in decimal and not rounded.
+    pub fn calculate(&self, tx: &Transaction, profile: &Profile, jurisdiction_rate: f64) -> Result<TaxBreakdown> {
         let rate = if profile.fiscal_category == "RESPONSABLE_INSCRIPTO" {
             jurisdiction_rate
         } else {
             0.0 // Monotributo doesn't calculate IVA for this POC
         };
 
-        let amount = tx.amount * rate;
+        let amount = tx.amount * Decimal::try_from(rate).with_context(|| format!("invalid IVA rate {}", rate))?;
 
-        TaxBreakdown {
+        Ok(TaxBreakdown {
             tax_type: TaxType::IVA,
             base: tx.amount,
             rate,
             amount,
-        }
+        })
     }
 }
 
@@ -32,7 +35,7 @@ mod tests {
     fn test_calculate_iva_responsable_inscripto() {
         let calculator = IVACalculator;
         let tx = Transaction {
-            amount: 100.0,
+            amount: Decimal::from(100),
             product: "PROD".to_string(),
             jurisdiction: "BSAS".to_string(),
             client_id: "c1".to_string(),
@@ -44,16 +47,16 @@ mod tests {
             config: serde_json::json!({}),
         };
 
-        let res = calculator.calculate(&tx, &profile, 0.21);
+        let res = calculator.calculate(&tx, &profile, 0.21).unwrap();
         assert_eq!(res.rate, 0.21);
-        assert_eq!(res.amount, 21.0);
+        assert_eq!(res.amount, Decimal::from(21));
     }
 
     #[test]
     fn test_calculate_iva_monotributo() {
         let calculator = IVACalculator;
         let tx = Transaction {
-            amount: 100.0,
+            amount: Decimal::from(100),
             product: "PROD".to_string(),
             jurisdiction: "BSAS".to_string(),
             client_id: "c1".to_string(),
@@ -65,16 +68,16 @@ mod tests {
             config: serde_json::json!({}),
         };
 
-        let res = calculator.calculate(&tx, &profile, 0.21);
+        let res = calculator.calculate(&tx, &profile, 0.21).unwrap();
         assert_eq!(res.rate, 0.0);
-        assert_eq!(res.amount, 0.0);
+        assert_eq!(res.amount, Decimal::ZERO);
     }
 
     #[test]
     fn test_calculate_iva_tdf() {
         let calculator = IVACalculator;
         let tx = Transaction {
-            amount: 100.0,
+            amount: Decimal::from(100),
             product: "PROD".to_string(),
             jurisdiction: "TDF".to_string(),
             client_id: "c1".to_string(),
@@ -86,8 +89,51 @@ mod tests {
             config: serde_json::json!({}),
         };
 
-        let res = calculator.calculate(&tx, &profile, 0.105);
+        let res = calculator.calculate(&tx, &profile, 0.105).unwrap();
         assert_eq!(res.rate, 0.105);
-        assert_eq!(res.amount, 10.5);
+        assert_eq!(res.amount, Decimal::new(105, 1));
+    }
+
+    #[test]
+    fn test_calculate_iva_ignores_stored_exemptions() {
+        let calculator = IVACalculator;
+        let tx = Transaction {
+            amount: Decimal::from(100),
+            product: "PROD".to_string(),
+            jurisdiction: "BSAS".to_string(),
+            client_id: "c1".to_string(),
+            date: Local::now().date_naive(),
+        };
+        // Exemptions only apply to quotes, as a zero rate
+        let profile = Profile {
+            client_id: "c1".to_string(),
+            fiscal_category: "RESPONSABLE_INSCRIPTO".to_string(),
+            config: serde_json::json!({ "exemptions": ["IVA"] }),
+        };
+
+        let res = calculator.calculate(&tx, &profile, 0.21).unwrap();
+        assert_eq!(res.rate, 0.21);
+        assert_eq!(res.amount, Decimal::from(21));
+    }
+
+    #[test]
+    fn test_calculate_iva_keeps_decimal_amounts() {
+        let calculator = IVACalculator;
+        let tx = Transaction {
+            amount: "1234567.89".parse().unwrap(),
+            product: "PROD".to_string(),
+            jurisdiction: "TDF".to_string(),
+            client_id: "c1".to_string(),
+            date: Local::now().date_naive(),
+        };
+        let profile = Profile {
+            client_id: "c1".to_string(),
+            fiscal_category: "RESPONSABLE_INSCRIPTO".to_string(),
+            config: serde_json::json!({}),
+        };
+
+        let res = calculator.calculate(&tx, &profile, 0.105).unwrap();
+        assert_eq!(res.base, "1234567.89".parse::<Decimal>().unwrap());
+        assert_eq!(res.amount, "129629.62845".parse::<Decimal>().unwrap());
     }
 }
@@ -0,0 +1,143 @@
+use crate::domain::models::DEFAULT_JURISDICTION;
+use crate::domain::traits::{ProfileCacheTrait, ProfileRepositoryTrait};
+use anyhow::Result;
+
+/// A cache key dropped by a write, as published on Redis pub/sub and sent by
+/// the database triggers: the cache key itself (`profile:<clientId>`,
+/// `iva_rate:<jurisdiction>`), or `profile:*` / `iva_rate:*` for every
+/// profile / IVA rate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invalidation {
+    Profile(String),
+    IvaRate(String),
+    AllProfiles,
+    AllIvaRates,
+}
+
+impl Invalidation {
+    pub fn parse(message: &str) -> Option<Self> {
+        if let Some(client_id) = message.strip_prefix("profile:") {
+            return Some(match client_id {
+                "*" => Invalidation::AllProfiles,
+                client_id => Invalidation::Profile(client_id.to_string()),
+            });
+        }
+        match message.strip_prefix("iva_rate:")? {
+            "*" => Some(Invalidation::AllIvaRates),
+            jurisdiction => Some(Invalidation::IvaRate(jurisdiction.to_string())),
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Invalidation::Profile(client_id) => format!("profile:{}", client_id),
+            Invalidation::IvaRate(jurisdiction) => format!("iva_rate:{}", jurisdiction),
+            Invalidation::AllProfiles => "profile:*".to_string(),
+            Invalidation::AllIvaRates => "iva_rate:*".to_string(),
+        }
+    }
+
+    /// Drops the key from `cache` (every level, when it is layered).
+    pub async fn apply<C: ProfileCacheTrait>(&self, cache: &C) -> Result<()> {
+        match self {
+            Invalidation::Profile(client_id) => cache.invalidate_profile(client_id).await,
+            Invalidation::IvaRate(jurisdiction) => cache.invalidate_iva_rate(jurisdiction).await,
+            Invalidation::AllProfiles => cache.invalidate_all_profiles().await,
+            Invalidation::AllIvaRates => cache.invalidate_all_iva_rates().await,
+        }
+    }
+
+    /// Like `apply`, then caches again what `repo` now holds for the key, so
+    /// the copy served while `repo` is down has the new data. Deleted rows
+    /// stay out of the cache.
+    pub async fn refresh<C, R>(&self, cache: &C, repo: &R) -> Result<()>
+    where
+        C: ProfileCacheTrait,
+        R: ProfileRepositoryTrait,
+    {
+        self.apply(cache).await?;
+        match self {
+            Invalidation::Profile(client_id) => {
+                if let Some(profile) = repo.get_by_id(client_id).await? {
+                    cache.set(&profile).await?;
+                }
+            }
+            Invalidation::IvaRate(jurisdiction) => {
+                if let Some(rate) = repo.get_iva_rate(jurisdiction).await? {
+                    cache.set_iva_rate(&rate).await?;
+                }
+            }
+            // Jurisdictions cached with the DEFAULT rate are loaded again on
+            // their next read
+            Invalidation::AllIvaRates => {
+                if let Some(rate) = repo.get_iva_rate(DEFAULT_JURISDICTION).await? {
+                    cache.set_iva_rate(&rate).await?;
+                }
+            }
+            Invalidation::AllProfiles => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::{IvaRate, Profile};
+    use crate::test_support::{MockCache, MockRepo};
+    use mockall::predicate::eq;
+
+    #[test]
+    fn test_invalidation_messages_round_trip() {
+        for invalidation in [
+            Invalidation::Profile("c1".to_string()),
+            Invalidation::IvaRate("TDF".to_string()),
+            Invalidation::AllProfiles,
+            Invalidation::AllIvaRates,
+        ] {
+            assert_eq!(Invalidation::parse(&invalidation.message()), Some(invalidation));
+        }
+        assert_eq!(Invalidation::parse("profile:a:b"), Some(Invalidation::Profile("a:b".to_string())));
+        assert_eq!(Invalidation::parse("rate:TDF"), None);
+    }
+
+    #[tokio::test]
+    async fn test_apply_invalidates_matching_key() {
+        let mut cache = MockCache::new();
+        cache.expect_invalidate_profile().with(eq("c1")).times(1).returning(|_| Ok(()));
+        cache.expect_invalidate_iva_rate().with(eq("TDF")).times(1).returning(|_| Ok(()));
+        cache.expect_invalidate_all_profiles().times(1).returning(|| Ok(()));
+        cache.expect_invalidate_all_iva_rates().times(1).returning(|| Ok(()));
+
+        for message in ["profile:c1", "iva_rate:TDF", "profile:*", "iva_rate:*"] {
+            Invalidation::parse(message).unwrap().apply(&cache).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_caches_rows_that_still_exist() {
+        let mut repo = MockRepo::new();
+        repo.expect_get_by_id().with(eq("c1")).returning(|client_id| {
+            Ok(Some(Profile {
+                client_id: client_id.to_string(),
+                fiscal_category: "RESPONSABLE_INSCRIPTO".to_string(),
+                config: serde_json::json!({}),
+            }))
+        });
+        repo.expect_get_by_id().with(eq("gone")).returning(|_| Ok(None));
+        repo.expect_get_iva_rate()
+            .with(eq(DEFAULT_JURISDICTION))
+            .returning(|j| Ok(Some(IvaRate { jurisdiction: j.to_string(), rate: 0.21 })));
+
+        let mut cache = MockCache::new();
+        cache.expect_invalidate_profile().times(2).returning(|_| Ok(()));
+        cache.expect_invalidate_all_iva_rates().times(1).returning(|| Ok(()));
+        // Only the updated profile and the DEFAULT rate are cached again
+        cache.expect_set().withf(|p| p.client_id == "c1").times(1).returning(|_| Ok(()));
+        cache.expect_set_iva_rate().withf(|r| r.jurisdiction == DEFAULT_JURISDICTION).times(1).returning(|_| Ok(()));
+
+        for message in ["profile:c1", "profile:gone", "iva_rate:*"] {
+            Invalidation::parse(message).unwrap().refresh(&cache, &repo).await.unwrap();
+        }
+    }
+}
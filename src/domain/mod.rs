@@ -1,3 +1,4 @@
 pub mod models;
 pub mod calculators;
 pub mod traits;
+pub mod invalidation;
@@ -1,9 +1,13 @@
 use serde::{Deserialize, Serialize};
 use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+/// Jurisdiction whose rate applies to jurisdictions without their own row.
+pub const DEFAULT_JURISDICTION: &str = "DEFAULT";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Transaction {
-    pub amount: f64,
+    pub amount: Decimal,
     pub product: String,
     pub jurisdiction: String,
     pub client_id: String,
@@ -17,12 +21,25 @@ pub struct Profile {
     pub config: serde_json::Value,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Profile {
+    /// Profile of a prospect with no row in `profiles`, used by quotes.
+    /// `inscriptions` is kept in `config` like for stored profiles.
+    pub fn inline(fiscal_category: &str, inscriptions: &[String]) -> Self {
+        Self {
+            client_id: String::new(),
+            fiscal_category: fiscal_category.to_string(),
+            config: serde_json::json!({ "inscriptions": inscriptions }),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct TaxBreakdown {
     pub tax_type: TaxType,
-    pub base: f64,
+    pub base: Decimal,
     pub rate: f64,
-    pub amount: f64,
+    /// Not rounded; each API rounds it when writing it out.
+    pub amount: Decimal,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -31,10 +48,28 @@ pub struct IvaRate {
     pub rate: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TaxType {
     IVA,
     Sellos,
     IIBB,
     Ganancias,
 }
+
+impl TaxType {
+    /// Name used on the wire and in profile configs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TaxType::IVA => "IVA",
+            TaxType::Sellos => "Sellos",
+            TaxType::IIBB => "IIBB",
+            TaxType::Ganancias => "Ganancias",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [TaxType::IVA, TaxType::Sellos, TaxType::IIBB, TaxType::Ganancias]
+            .into_iter()
+            .find(|tax_type| tax_type.name() == name)
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use crate::domain::models::{Profile, IvaRate};
+use std::time::Duration;
 
 #[async_trait]
 pub trait ProfileRepositoryTrait: Send + Sync {
@@ -8,10 +10,160 @@ pub trait ProfileRepositoryTrait: Send + Sync {
     async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>>;
 }
 
+/// Default TTL of "not found" entries at every cache level, kept short so a
+/// row created outside the admin path is picked up soon.
+pub const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(60);
+
+/// Outcome of a cache read that tells a cached "not found" apart from a miss.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CacheLookup<T> {
+    Hit(T),
+    /// The key is known not to exist in the database.
+    NotFound,
+    Miss,
+}
+
+impl<T> CacheLookup<T> {
+    /// The cached value; `None` for both a miss and a cached "not found".
+    pub fn into_value(self) -> Option<T> {
+        match self {
+            CacheLookup::Hit(value) => Some(value),
+            CacheLookup::NotFound | CacheLookup::Miss => None,
+        }
+    }
+}
+
+/// A cached value kept past its TTL, served only when the database fails.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleEntry<T> {
+    pub value: T,
+    pub stored_at: DateTime<Utc>,
+}
+
+impl<T> StaleEntry<T> {
+    /// Time since the value was cached; zero if the clock went backwards.
+    pub fn age(&self) -> Duration {
+        (Utc::now() - self.stored_at).to_std().unwrap_or_default()
+    }
+}
+
+/// Where invalidations stood when a DB load started, taken by
+/// `ProfileCacheTrait::profile_generation` and `iva_rate_generation`: the
+/// in-process level's counter and the shared cache's. A level that could not
+/// read its counter stores nothing from the load; caches without counters
+/// leave both `None` and ignore them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Generation {
+    pub local: Option<u64>,
+    pub shared: Option<u64>,
+}
+
 #[async_trait]
 pub trait ProfileCacheTrait: Send + Sync {
     async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>>;
     async fn set(&self, profile: &Profile) -> Result<()>;
     async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>>;
     async fn set_iva_rate(&self, rate: &IvaRate) -> Result<()>;
+    /// Like `get_by_id`, reporting cached "not found" entries. Caches
+    /// without negative entries only report hits and misses.
+    async fn lookup_profile(&self, client_id: &str) -> Result<CacheLookup<Profile>> {
+        Ok(match self.get_by_id(client_id).await? {
+            Some(profile) => CacheLookup::Hit(profile),
+            None => CacheLookup::Miss,
+        })
+    }
+    /// Caches, with a short TTL, that `client_id` has no profile. Removed by
+    /// `set` and `invalidate_profile` like a cached profile.
+    async fn set_profile_not_found(&self, _client_id: &str) -> Result<()> {
+        Ok(())
+    }
+    /// Like `get_iva_rate`, reporting cached "not found" entries.
+    async fn lookup_iva_rate(&self, jurisdiction: &str) -> Result<CacheLookup<IvaRate>> {
+        Ok(match self.get_iva_rate(jurisdiction).await? {
+            Some(rate) => CacheLookup::Hit(rate),
+            None => CacheLookup::Miss,
+        })
+    }
+    /// Caches, with a short TTL, that neither `jurisdiction` nor `DEFAULT`
+    /// has a rate.
+    async fn set_iva_rate_not_found(&self, _jurisdiction: &str) -> Result<()> {
+        Ok(())
+    }
+    /// Last profile cached for `client_id`, even if expired or invalidated.
+    /// Caches without stale copies return `None`.
+    async fn get_stale_profile(&self, _client_id: &str) -> Result<Option<StaleEntry<Profile>>> {
+        Ok(None)
+    }
+    /// Last IVA rate cached for `jurisdiction`, even if expired or
+    /// invalidated.
+    async fn get_stale_iva_rate(&self, _jurisdiction: &str) -> Result<Option<StaleEntry<IvaRate>>> {
+        Ok(None)
+    }
+    /// Taken before loading `client_id` from the DB, so `fill_profile` can
+    /// tell whether an invalidation landed while the load was running.
+    /// Caches without invalidation counters return the default.
+    async fn profile_generation(&self, _client_id: &str) -> Result<Generation> {
+        Ok(Generation::default())
+    }
+    /// Caches a profile, or its absence, loaded from the DB since
+    /// `generation`, unless it was invalidated meanwhile: the load may have
+    /// read the row before the write that caused the invalidation.
+    async fn fill_profile(&self, client_id: &str, profile: Option<&Profile>, _generation: Generation) -> Result<()> {
+        match profile {
+            Some(profile) => self.set(profile).await,
+            None => self.set_profile_not_found(client_id).await,
+        }
+    }
+    /// Like `profile_generation`, for the IVA rate of `jurisdiction`.
+    async fn iva_rate_generation(&self, _jurisdiction: &str) -> Result<Generation> {
+        Ok(Generation::default())
+    }
+    /// Like `fill_profile`, for the IVA rate of `jurisdiction`.
+    async fn fill_iva_rate(&self, jurisdiction: &str, rate: Option<&IvaRate>, _generation: Generation) -> Result<()> {
+        match rate {
+            Some(rate) => self.set_iva_rate(rate).await,
+            None => self.set_iva_rate_not_found(jurisdiction).await,
+        }
+    }
+    async fn invalidate_profile(&self, client_id: &str) -> Result<()>;
+    async fn invalidate_iva_rate(&self, jurisdiction: &str) -> Result<()>;
+    /// Drops every cached profile. Stale copies are kept: a bulk flush says
+    /// nothing about any one row, and they are what a DB outage is served from.
+    async fn invalidate_all_profiles(&self) -> Result<()>;
+    /// Drops every cached IVA rate, including jurisdictions cached with the
+    /// `DEFAULT` rate. Stale copies are kept.
+    async fn invalidate_all_iva_rates(&self) -> Result<()>;
+    /// Drops every profile and IVA rate after invalidations may have been
+    /// missed. A cache shared between instances does it once per `window`,
+    /// whichever instance asks first; returns whether this call did.
+    async fn resync(&self, _window: Duration) -> Result<bool> {
+        self.invalidate_all_profiles().await?;
+        self.invalidate_all_iva_rates().await?;
+        Ok(true)
+    }
+}
+
+/// Writes behind the `TaxAdmin` interface. Every change is recorded in the
+/// audit log together with the `actor` that made it, in the same transaction.
+///
+/// Writes return `false` when nothing changed: the row already exists
+/// (insert) or does not exist (update/delete).
+#[async_trait]
+pub trait ProfileAdminRepositoryTrait: Send + Sync {
+    async fn list_profiles(&self) -> Result<Vec<Profile>>;
+    async fn insert_profile(&self, profile: &Profile, actor: &str) -> Result<bool>;
+    async fn update_profile(&self, profile: &Profile, actor: &str) -> Result<bool>;
+    async fn delete_profile(&self, client_id: &str, actor: &str) -> Result<bool>;
+    async fn list_iva_rates(&self) -> Result<Vec<IvaRate>>;
+    async fn insert_iva_rate(&self, rate: &IvaRate, actor: &str) -> Result<bool>;
+    async fn update_iva_rate(&self, rate: &IvaRate, actor: &str) -> Result<bool>;
+    async fn delete_iva_rate(&self, jurisdiction: &str, actor: &str) -> Result<bool>;
+}
+
+/// A dependency that can be probed by liveness/readiness checks.
+#[async_trait]
+pub trait HealthCheckTrait: Send + Sync {
+    /// Name reported in health responses (e.g. `postgres`).
+    fn name(&self) -> &'static str;
+    async fn ping(&self) -> Result<()>;
 }
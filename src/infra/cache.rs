@@ -1,69 +1,678 @@
-use crate::domain::models::Profile;
-use crate::domain::traits::ProfileCacheTrait;
+use crate::domain::models::{IvaRate, Profile};
+use crate::domain::invalidation::Invalidation;
+use crate::domain::traits::{CacheLookup, DEFAULT_NEGATIVE_TTL, Generation, HealthCheckTrait, ProfileCacheTrait, StaleEntry};
+use crate::infra::memory_cache::MemoryCache;
 use anyhow::{Context, Result};
-use redis::AsyncCommands;
-use std::sync::Arc;
+use futures_util::StreamExt;
+use redis::aio::{ConnectionLike, MultiplexedConnection};
+use redis::{AsyncCommands, Cmd, Pipeline, RedisFuture, RedisResult, Value};
+use std::io;
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use std::time::Duration;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+/// Pub/sub channel on which every invalidation is announced, so instances
+/// can evict the key from their in-memory cache.
+pub const INVALIDATION_CHANNEL: &str = "tax-engine:invalidations";
+
+/// TTL of cached profiles and IVA rates (30 minutes as per RT-002).
+const TTL_SECS: u64 = 1800;
+
+/// Value cached for a key known not to exist.
+const NOT_FOUND: &str = "null";
+
+/// Default TTL of the stale copies served when PostgreSQL fails.
+pub const DEFAULT_STALE_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Held by the instance flushing the cache in `resync`, so the others skip it.
+const RESYNC_KEY: &str = "lock:resync";
+
+/// Bounds opening the shared connection; while it is being opened every
+/// other call fails, so a stalled connect cannot hold the cache off for long.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Counters bumped by every invalidation of a profile or IVA rate, single or
+/// bulk, and checked by `fill_profile` and `fill_iva_rate`.
+const PROFILES_GENERATION_KEY: &str = "generation:profiles";
+const IVA_RATES_GENERATION_KEY: &str = "generation:iva_rates";
+
+/// Stores a value loaded from the DB only if the generation counter
+/// (KEYS[1]) still holds the value read before the load (ARGV[1]). Writes
+/// ARGV[2] under KEYS[2] for ARGV[3] seconds and, unless empty, the stale
+/// copy ARGV[4] under KEYS[3] for ARGV[5] seconds.
+static FILL_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| redis::Script::new(r#"
+if tonumber(redis.call('GET', KEYS[1]) or '0') ~= tonumber(ARGV[1]) then
+    return 0
+end
+redis.call('SET', KEYS[2], ARGV[2], 'EX', ARGV[3])
+if ARGV[4] ~= '' then
+    redis.call('SET', KEYS[3], ARGV[4], 'EX', ARGV[5])
+end
+return 1
+"#));
 
 #[derive(Clone)]
 pub struct ProfileCache {
-    client: Arc<redis::Client>,
+    connection: SharedConnection,
+    early_refresh: Option<Duration>,
+    negative_ttl: Duration,
+    stale_ttl: Duration,
 }
 
 impl ProfileCache {
     pub fn new(client: Arc<redis::Client>) -> Self {
-        Self { client }
+        Self { connection: SharedConnection::new(client), early_refresh: None, negative_ttl: DEFAULT_NEGATIVE_TTL, stale_ttl: DEFAULT_STALE_TTL }
+    }
+
+    /// How long a copy of each stored value is kept, past its TTL and
+    /// invalidations, to be served when the database fails; zero disables
+    /// stale copies.
+    pub fn with_stale_ttl(mut self, ttl: Duration) -> Self {
+        self.stale_ttl = ttl;
+        self
+    }
+
+    /// Stores `value` under `key` for `TTL_SECS`, and its stale copy.
+    async fn write<T: Serialize>(&self, key: String, value: &T) -> Result<()> {
+        let json = serde_json::to_string(value).context("Failed to serialize value for cache")?;
+        let mut pipe = redis::pipe();
+        pipe.set_ex(&key, json, TTL_SECS).ignore();
+        if let Some(copy) = self.stale_copy(value)? {
+            pipe.set_ex(stale_key(&key), copy, self.stale_ttl.as_secs().max(1)).ignore();
+        }
+        let mut conn = self.connection.get().await?;
+        let _: () = pipe.query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    /// JSON of the stale copy of `value`; `None` if stale copies are
+    /// disabled.
+    fn stale_copy<T: Serialize>(&self, value: &T) -> Result<Option<String>> {
+        if self.stale_ttl.is_zero() {
+            return Ok(None);
+        }
+        let copy = StaleCopy { value, stored_at_ms: Utc::now().timestamp_millis() };
+        Ok(Some(serde_json::to_string(&copy).context("Failed to serialize stale copy for cache")?))
+    }
+
+    async fn generation(&self, generation_key: &str) -> Result<Generation> {
+        let mut conn = self.connection.get().await?;
+        let shared: Option<u64> = conn.get(generation_key).await?;
+        Ok(Generation { local: None, shared: Some(shared.unwrap_or(0)) })
+    }
+
+    /// Like `write`, or `set_not_found` for `None`, through `FILL_SCRIPT`:
+    /// nothing is stored if `generation_key` moved past `generation`, or if
+    /// it could not be read before the load.
+    async fn fill<T: Serialize>(
+        &self,
+        generation_key: &str,
+        generation: Generation,
+        key: String,
+        value: Option<&T>,
+    ) -> Result<()> {
+        let Some(generation) = generation.shared else {
+            return Ok(());
+        };
+        let (json, ttl, copy) = match value {
+            Some(value) => {
+                let json = serde_json::to_string(value).context("Failed to serialize value for cache")?;
+                (json, TTL_SECS, self.stale_copy(value)?)
+            }
+            None if self.negative_ttl.is_zero() => return Ok(()),
+            None => (NOT_FOUND.to_string(), self.negative_ttl.as_secs().max(1), None),
+        };
+        let mut conn = self.connection.get().await?;
+        let stored: i64 = FILL_SCRIPT
+            .key(generation_key)
+            .key(&key)
+            .key(stale_key(&key))
+            .arg(generation)
+            .arg(json)
+            .arg(ttl)
+            .arg(copy.unwrap_or_default())
+            .arg(self.stale_ttl.as_secs().max(1))
+            .invoke_async(&mut conn)
+            .await?;
+        if stored == 0 {
+            debug!("Skipped caching {}: invalidated while it was loaded", key);
+        }
+        Ok(())
+    }
+
+    async fn get_stale<T: DeserializeOwned>(&self, key: &str) -> Result<Option<StaleEntry<T>>> {
+        let mut conn = self.connection.get().await?;
+        let cached: Option<String> = conn.get(stale_key(key)).await?;
+        let Some(json) = cached else {
+            return Ok(None);
+        };
+        let copy: StaleCopy<T> = serde_json::from_str(&json)?;
+        let stored_at = DateTime::from_timestamp_millis(copy.stored_at_ms).context("Invalid stale copy timestamp")?;
+        Ok(Some(StaleEntry { value: copy.value, stored_at }))
+    }
+
+    /// TTL of "not found" entries; zero disables negative caching.
+    pub fn with_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = ttl;
+        self
+    }
+
+    async fn set_not_found(&self, key: String) -> RedisResult<()> {
+        if self.negative_ttl.is_zero() {
+            return Ok(());
+        }
+        let mut conn = self.connection.get().await?;
+        // Whole seconds, at least one
+        conn.set_ex(key, NOT_FOUND, self.negative_ttl.as_secs().max(1)).await
+    }
+
+    /// Reports a hit as a miss, with a probability that grows as the entry
+    /// nears expiry (about 37% with `window` left), so one request reloads
+    /// it before it expires instead of every request missing at once.
+    pub fn with_early_refresh(mut self, window: Duration) -> Self {
+        self.early_refresh = Some(window);
+        self
+    }
+
+    /// Reads `key`; with early refresh, its remaining TTL comes in the same
+    /// round trip.
+    async fn get_fresh(&self, key: &str) -> RedisResult<Option<String>> {
+        let mut conn = self.connection.get().await?;
+        let Some(window) = self.early_refresh else {
+            return conn.get(key).await;
+        };
+        let (cached, ttl_ms): (Option<String>, i64) =
+            redis::pipe().get(key).pttl(key).query_async(&mut conn).await?;
+        // Negative when the key has no TTL or is gone
+        if cached.is_some()
+            && ttl_ms >= 0
+            && refresh_early(Duration::from_millis(ttl_ms as u64), window, rand::random())
+        {
+            debug!("Refreshing {} early, {} ms left", key, ttl_ms);
+            return Ok(None);
+        }
+        Ok(cached)
+    }
+}
+
+/// One multiplexed connection shared by every call of a `ProfileCache` and
+/// its clones, opened on first use and reopened once it breaks.
+#[derive(Clone)]
+struct SharedConnection {
+    client: Arc<redis::Client>,
+    state: Arc<Mutex<ConnectionState>>,
+}
+
+#[derive(Default)]
+struct ConnectionState {
+    /// The open connection and its number, so a handle on an older one that
+    /// breaks does not drop it.
+    current: Option<(MultiplexedConnection, u64)>,
+    connecting: bool,
+    opened: u64,
+}
+
+impl SharedConnection {
+    fn new(client: Arc<redis::Client>) -> Self {
+        Self { client, state: Arc::default() }
+    }
+
+    /// The open connection, else opens one. Only one call connects at a
+    /// time, outside the lock; the others fail right away instead of
+    /// queueing behind it for longer than their timeout.
+    async fn get(&self) -> RedisResult<Connection> {
+        {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some((conn, id)) = &state.current {
+                return Ok(Connection { conn: conn.clone(), id: *id, state: self.state.clone() });
+            }
+            if state.connecting {
+                return Err(io::Error::new(io::ErrorKind::ResourceBusy, "Redis connection is being reopened").into());
+            }
+            state.connecting = true;
+        }
+        // In its own task, so a caller giving up on its timeout does not
+        // cancel the connect for everyone else
+        let shared = self.clone();
+        tokio::spawn(async move { shared.connect().await }).await.map_err(io::Error::other)?
+    }
+
+    async fn connect(&self) -> RedisResult<Connection> {
+        let connected = match tokio::time::timeout(CONNECT_TIMEOUT, self.client.get_multiplexed_async_connection()).await {
+            Ok(connected) => connected,
+            Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "Timed out connecting to Redis").into()),
+        };
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.connecting = false;
+        let conn = connected?;
+        state.opened += 1;
+        let id = state.opened;
+        state.current = Some((conn.clone(), id));
+        Ok(Connection { conn, id, state: self.state.clone() })
+    }
+}
+
+/// A handle on the shared connection. A command that finds it broken drops
+/// it, so the next call reconnects.
+struct Connection {
+    conn: MultiplexedConnection,
+    id: u64,
+    state: Arc<Mutex<ConnectionState>>,
+}
+
+impl Connection {
+    fn check<T>(&self, result: RedisResult<T>) -> RedisResult<T> {
+        if let Err(e) = &result
+            && e.is_unrecoverable_error()
+        {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if state.current.as_ref().is_some_and(|(_, id)| *id == self.id) {
+                state.current = None;
+            }
+        }
+        result
+    }
+}
+
+impl ConnectionLike for Connection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(async move {
+            let result = self.conn.req_packed_command(cmd).await;
+            self.check(result)
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            let result = self.conn.req_packed_commands(cmd, offset, count).await;
+            self.check(result)
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.conn.get_db()
+    }
+}
+
+/// A cached value, or `NOT_FOUND` for a key known not to exist.
+fn parse_cached<T: serde::de::DeserializeOwned>(cached: Option<String>) -> Result<CacheLookup<T>> {
+    let Some(json) = cached else {
+        return Ok(CacheLookup::Miss);
+    };
+    Ok(match serde_json::from_str::<Option<T>>(&json)? {
+        Some(value) => CacheLookup::Hit(value),
+        None => CacheLookup::NotFound,
+    })
+}
+
+/// Whether a Redis call failed in a way a retry may fix: timeouts, lost or
+/// refused connections, and server replies such as `TRYAGAIN` or `LOADING`.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().filter_map(|cause| cause.downcast_ref::<redis::RedisError>()).any(|e| {
+        e.is_timeout()
+            || e.is_connection_dropped()
+            || e.is_connection_refusal()
+            || matches!(e.retry_method(), redis::RetryMethod::RetryImmediately | redis::RetryMethod::WaitAndRetry)
+    })
+}
+
+/// XFetch: refresh when `remaining <= window * -ln(u)`, `u` uniform in
+/// `[0, 1)`.
+fn refresh_early(remaining: Duration, window: Duration, u: f64) -> bool {
+    remaining.as_secs_f64() <= window.as_secs_f64() * -(1.0 - u).ln()
+}
+
+/// Stale copy of a cached value, with when it was stored.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StaleCopy<T> {
+    value: T,
+    stored_at_ms: i64,
+}
+
+/// Outside the `profile:*` and `iva_rate:*` patterns, so expiry, early
+/// refresh and bulk invalidations leave stale copies in place. Invalidating a
+/// single key deletes its copy: the data it holds is known to be outdated.
+fn stale_key(key: &str) -> String {
+    format!("stale:{}", key)
+}
+
+pub fn profile_key(client_id: &str) -> String {
+    format!("profile:{}", client_id)
+}
+
+pub fn iva_rate_key(jurisdiction: &str) -> String {
+    format!("iva_rate:{}", jurisdiction)
+}
+
+/// Deletes every key matching `pattern`. SCAN instead of KEYS so a large
+/// keyspace does not block Redis.
+async fn delete_matching(conn: &mut Connection, pattern: &str) -> RedisResult<()> {
+    let mut cursor: u64 = 0;
+    loop {
+        let (next, keys): (u64, Vec<String>) =
+            redis::cmd("SCAN").arg(cursor).arg("MATCH").arg(pattern).arg("COUNT").arg(500).query_async(conn).await?;
+        if !keys.is_empty() {
+            let _: () = conn.del(keys).await?;
+        }
+        if next == 0 {
+            return Ok(());
+        }
+        cursor = next;
+    }
+}
+
+impl ProfileCache {
+    async fn publish(&self, conn: &mut Connection, invalidation: Invalidation) -> Result<()> {
+        let _: i64 = conn
+            .publish(INVALIDATION_CHANNEL, invalidation.message())
+            .await
+            .context("Failed to publish cache invalidation")?;
+        Ok(())
     }
 }
 
 #[async_trait]
 impl ProfileCacheTrait for ProfileCache {
     async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
-        let mut conn = self.client.get_multiplexed_async_connection().await?;
-        let key = format!("profile:{}", client_id);
-        
-        let cached: Option<String> = conn.get(&key).await.context("Failed to get from Redis")?;
-        
-        match cached {
-            Some(json) => {
-                let profile: Profile = serde_json::from_str(&json).context("Failed to parse cached profile")?;
-                Ok(Some(profile))
-            }
-            None => Ok(None),
-        }
+        Ok(self.lookup_profile(client_id).await?.into_value())
     }
 
     async fn set(&self, profile: &Profile) -> Result<()> {
-        let mut conn = self.client.get_multiplexed_async_connection().await?;
-        let key = format!("profile:{}", profile.client_id);
-        let json = serde_json::to_string(profile).context("Failed to serialize profile for cache")?;
-        
-        // TTL 30 minutes as per RT-002
-        let _: () = conn.set_ex(key, json, 1800).await.context("Failed to set in Redis")?;
+        self.write(profile_key(&profile.client_id), profile).await.context("Failed to set in Redis")
+    }
+
+    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>> {
+        Ok(self.lookup_iva_rate(jurisdiction).await?.into_value())
+    }
+
+    async fn set_iva_rate(&self, rate: &IvaRate) -> Result<()> {
+        self.write(iva_rate_key(&rate.jurisdiction), rate).await.context("Failed to set IVA rate in Redis")
+    }
+
+    async fn lookup_profile(&self, client_id: &str) -> Result<CacheLookup<Profile>> {
+        let cached = self.get_fresh(&profile_key(client_id)).await.context("Failed to get from Redis")?;
+        parse_cached(cached).context("Failed to parse cached profile")
+    }
+
+    async fn set_profile_not_found(&self, client_id: &str) -> Result<()> {
+        self.set_not_found(profile_key(client_id)).await.context("Failed to set missing profile in Redis")
+    }
+
+    async fn lookup_iva_rate(&self, jurisdiction: &str) -> Result<CacheLookup<IvaRate>> {
+        let cached = self.get_fresh(&iva_rate_key(jurisdiction)).await.context("Failed to get IVA rate from Redis")?;
+        parse_cached(cached).context("Failed to parse cached IVA rate")
+    }
+
+    async fn set_iva_rate_not_found(&self, jurisdiction: &str) -> Result<()> {
+        self.set_not_found(iva_rate_key(jurisdiction)).await.context("Failed to set missing IVA rate in Redis")
+    }
+
+    async fn get_stale_profile(&self, client_id: &str) -> Result<Option<StaleEntry<Profile>>> {
+        self.get_stale(&profile_key(client_id)).await.context("Failed to get stale profile from Redis")
+    }
+
+    async fn get_stale_iva_rate(&self, jurisdiction: &str) -> Result<Option<StaleEntry<IvaRate>>> {
+        self.get_stale(&iva_rate_key(jurisdiction)).await.context("Failed to get stale IVA rate from Redis")
+    }
+
+    async fn profile_generation(&self, _client_id: &str) -> Result<Generation> {
+        self.generation(PROFILES_GENERATION_KEY).await.context("Failed to get profiles generation from Redis")
+    }
+
+    async fn fill_profile(&self, client_id: &str, profile: Option<&Profile>, generation: Generation) -> Result<()> {
+        self.fill(PROFILES_GENERATION_KEY, generation, profile_key(client_id), profile)
+            .await
+            .context("Failed to set in Redis")
+    }
+
+    async fn iva_rate_generation(&self, _jurisdiction: &str) -> Result<Generation> {
+        self.generation(IVA_RATES_GENERATION_KEY).await.context("Failed to get IVA rates generation from Redis")
+    }
+
+    async fn fill_iva_rate(&self, jurisdiction: &str, rate: Option<&IvaRate>, generation: Generation) -> Result<()> {
+        self.fill(IVA_RATES_GENERATION_KEY, generation, iva_rate_key(jurisdiction), rate)
+            .await
+            .context("Failed to set IVA rate in Redis")
+    }
+
+    async fn invalidate_profile(&self, client_id: &str) -> Result<()> {
+        let mut conn = self.connection.get().await?;
+        let key = profile_key(client_id);
+        let _: () = redis::pipe()
+            .atomic()
+            .incr(PROFILES_GENERATION_KEY, 1)
+            .ignore()
+            .del(&[stale_key(&key), key])
+            .ignore()
+            .query_async(&mut conn)
+            .await
+            .context("Failed to invalidate profile in Redis")?;
+        self.publish(&mut conn, Invalidation::Profile(client_id.to_string())).await
+    }
+
+    async fn invalidate_iva_rate(&self, jurisdiction: &str) -> Result<()> {
+        let mut conn = self.connection.get().await?;
+        let key = iva_rate_key(jurisdiction);
+        let _: () = redis::pipe()
+            .atomic()
+            .incr(IVA_RATES_GENERATION_KEY, 1)
+            .ignore()
+            .del(&[stale_key(&key), key])
+            .ignore()
+            .query_async(&mut conn)
+            .await
+            .context("Failed to invalidate IVA rate in Redis")?;
+        self.publish(&mut conn, Invalidation::IvaRate(jurisdiction.to_string())).await
+    }
+
+    async fn invalidate_all_profiles(&self) -> Result<()> {
+        let mut conn = self.connection.get().await?;
+        let _: i64 = conn.incr(PROFILES_GENERATION_KEY, 1).await.context("Failed to invalidate profiles in Redis")?;
+        delete_matching(&mut conn, &profile_key("*")).await.context("Failed to invalidate profiles in Redis")?;
+        self.publish(&mut conn, Invalidation::AllProfiles).await
+    }
+
+    async fn invalidate_all_iva_rates(&self) -> Result<()> {
+        let mut conn = self.connection.get().await?;
+        let _: i64 = conn.incr(IVA_RATES_GENERATION_KEY, 1).await.context("Failed to invalidate IVA rates in Redis")?;
+        delete_matching(&mut conn, &iva_rate_key("*")).await.context("Failed to invalidate IVA rates in Redis")?;
+        self.publish(&mut conn, Invalidation::AllIvaRates).await
+    }
+
+    async fn resync(&self, window: Duration) -> Result<bool> {
+        // Every instance misses the same changes; one SCAN is enough
+        let mut conn = self.connection.get().await?;
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(RESYNC_KEY)
+            .arg(1)
+            .arg("NX")
+            .arg("PX")
+            .arg(u64::try_from(window.as_millis()).unwrap_or(u64::MAX).max(1))
+            .query_async(&mut conn)
+            .await
+            .context("Failed to claim cache resync in Redis")?;
+        if claimed.is_none() {
+            return Ok(false);
+        }
+        self.invalidate_all_profiles().await?;
+        self.invalidate_all_iva_rates().await?;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl HealthCheckTrait for ProfileCache {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn ping(&self) -> Result<()> {
+        let mut conn = self.connection.get().await?;
+        let _: String = redis::cmd("PING")
+            .query_async(&mut conn)
+            .await
+            .context("Redis health check failed")?;
         Ok(())
     }
+}
 
-    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<crate::domain::models::IvaRate>> {
-        let mut conn = self.client.get_multiplexed_async_connection().await?;
-        let key = format!("iva_rate:{}", jurisdiction);
-        
-        let cached: Option<String> = conn.get(&key).await.context("Failed to get IVA rate from Redis")?;
-        
-        match cached {
-            Some(json) => {
-                let rate: crate::domain::models::IvaRate = serde_json::from_str(&json).context("Failed to parse cached IVA rate")?;
-                Ok(Some(rate))
+/// Keeps `cache` in sync with invalidations published by every instance
+/// (including this one) on `INVALIDATION_CHANNEL`. Runs until the task is
+/// dropped. When the subscription drops it resubscribes with backoff; after
+/// every subscription the whole of `cache` is flushed, since anything
+/// published while unsubscribed was missed.
+///
+/// `on_change` is called with every invalidation received, and with `None`
+/// when some may have been missed.
+pub async fn run_invalidation_listener<C, F>(client: Arc<redis::Client>, cache: MemoryCache<C>, on_change: F)
+where
+    C: ProfileCacheTrait,
+    F: Fn(Option<&Invalidation>),
+{
+    const MIN_BACKOFF: Duration = Duration::from_millis(100);
+    const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let mut pubsub = match subscribe(&client).await {
+            Ok(pubsub) => pubsub,
+            Err(e) => {
+                warn!("Cache invalidation subscription failed, retrying in {:?}: {:#}", backoff, e);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+        };
+        backoff = MIN_BACKOFF;
+        cache.flush();
+        on_change(None);
+        info!("Subscribed to cache invalidations on {}", INVALIDATION_CHANNEL);
+
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            let payload: String = match message.get_payload() {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!("Ignoring unreadable cache invalidation: {}", e);
+                    continue;
+                }
+            };
+            match Invalidation::parse(&payload) {
+                Some(invalidation) => {
+                    debug!("Evicting {} from L1 cache", payload);
+                    cache.evict(&invalidation);
+                    on_change(Some(&invalidation));
+                }
+                None => {
+                    warn!("Unknown cache invalidation {:?}, flushing L1 cache", payload);
+                    cache.flush();
+                    on_change(None);
+                }
             }
-            None => Ok(None),
         }
+        warn!("Cache invalidation subscription dropped, resubscribing");
     }
+}
 
-    async fn set_iva_rate(&self, rate: &crate::domain::models::IvaRate) -> Result<()> {
-        let mut conn = self.client.get_multiplexed_async_connection().await?;
-        let key = format!("iva_rate:{}", rate.jurisdiction);
-        let json = serde_json::to_string(rate).context("Failed to serialize IVA rate for cache")?;
-        
-        let _: () = conn.set_ex(key, json, 1800).await.context("Failed to set IVA rate in Redis")?;
-        Ok(())
+async fn subscribe(client: &redis::Client) -> Result<redis::aio::PubSub> {
+    let mut pubsub = client.get_async_pubsub().await.context("Failed to connect to Redis")?;
+    pubsub.subscribe(INVALIDATION_CHANNEL).await.context("Failed to subscribe")?;
+    Ok(pubsub)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_invalidation_messages_are_cache_keys() {
+        assert_eq!(Invalidation::Profile("c1".to_string()).message(), profile_key("c1"));
+        assert_eq!(Invalidation::IvaRate("TDF".to_string()).message(), iva_rate_key("TDF"));
+        assert_eq!(Invalidation::AllIvaRates.message(), iva_rate_key("*"));
+        assert_eq!(Invalidation::AllProfiles.message(), profile_key("*"));
+    }
+
+    #[test]
+    fn test_parse_cached_tells_not_found_from_miss() {
+        let rate = parse_cached::<IvaRate>(Some(r#"{"jurisdiction": "TDF", "rate": 0.105}"#.to_string())).unwrap();
+        assert!(matches!(rate, CacheLookup::Hit(IvaRate { rate: 0.105, .. })));
+        assert!(matches!(parse_cached::<Profile>(Some(NOT_FOUND.to_string())).unwrap(), CacheLookup::NotFound));
+        assert!(matches!(parse_cached::<Profile>(None).unwrap(), CacheLookup::Miss));
+        assert!(parse_cached::<Profile>(Some("{".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let timed_out = redis::RedisError::from(std::io::Error::from(std::io::ErrorKind::TimedOut));
+        assert!(is_transient(&anyhow::Error::new(timed_out).context("Failed to get from Redis")));
+        let refused = redis::RedisError::from(std::io::Error::from(std::io::ErrorKind::ConnectionRefused));
+        assert!(is_transient(&anyhow::Error::new(refused)));
+
+        let bad_reply = redis::RedisError::from((redis::ErrorKind::UnexpectedReturnType, "not a string"));
+        assert!(!is_transient(&anyhow::Error::new(bad_reply)));
+        assert!(!is_transient(&anyhow::anyhow!("timeout")));
+    }
+
+    #[test]
+    fn test_refresh_early_grows_near_expiry() {
+        let window = Duration::from_secs(10);
+        let refreshes = |remaining: Duration| {
+            (0..1000).filter(|i| refresh_early(remaining, window, *i as f64 / 1000.0)).count()
+        };
+
+        assert_eq!(refreshes(Duration::ZERO), 1000);
+        assert_eq!(refreshes(Duration::from_secs(10)), 367);
+        assert_eq!(refreshes(Duration::from_secs(60)), 2);
+        assert_eq!(refreshes(Duration::from_secs(1800)), 0);
+    }
+
+    #[test]
+    fn test_stale_copies_are_keyed_outside_invalidation_patterns() {
+        assert_eq!(stale_key("profile:c1"), "stale:profile:c1");
+
+        let rate = IvaRate { jurisdiction: "TDF".to_string(), rate: 0.0 };
+        let json = serde_json::to_string(&StaleCopy { value: &rate, stored_at_ms: 1_700_000_000_000 }).unwrap();
+        assert_eq!(json, r#"{"value":{"jurisdiction":"TDF","rate":0.0},"storedAtMs":1700000000000}"#);
+        let copy: StaleCopy<IvaRate> = serde_json::from_str(&json).unwrap();
+        assert_eq!(copy.value.jurisdiction, "TDF");
+    }
+
+    #[tokio::test]
+    async fn test_one_reconnect_at_a_time_and_waiters_fail_fast() {
+        // Accepts connections but never answers the AUTH sent while connecting
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://:secret@{}", listener.local_addr().unwrap());
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                held.push(stream);
+            }
+        });
+        let connection = SharedConnection::new(Arc::new(redis::Client::open(url).unwrap()));
+
+        // The caller gives up, the connect keeps going
+        assert!(tokio::time::timeout(Duration::from_millis(20), connection.get()).await.is_err());
+        for _ in 0..3 {
+            let started = tokio::time::Instant::now();
+            let err = connection.get().await.err().unwrap();
+            assert_eq!(err.kind(), redis::ErrorKind::Io);
+            assert!(is_transient(&err.into()));
+            assert!(started.elapsed() < Duration::from_millis(20));
+        }
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
     }
 }
@@ -1,8 +1,18 @@
-use crate::domain::models::Profile;
-use crate::domain::traits::ProfileRepositoryTrait;
+use crate::domain::models::{IvaRate, Profile};
+use crate::domain::traits::{HealthCheckTrait, ProfileAdminRepositoryTrait, ProfileCacheTrait, ProfileRepositoryTrait};
+use crate::domain::invalidation::Invalidation;
 use anyhow::{Context, Result};
-use sqlx::PgPool;
+use sqlx::pool::CloseEvent;
+use sqlx::postgres::PgListener;
+use sqlx::{PgConnection, PgPool, Row};
+use std::time::Duration;
 use async_trait::async_trait;
+use tracing::{debug, info, warn};
+
+/// Channel notified by the triggers on `profiles` and `iva_rates` (see
+/// `migrations/20260401_cache_invalidation_triggers.sql`), with the cache
+/// key of the changed row as payload.
+pub const CHANGE_CHANNEL: &str = "cache_invalidation";
 
 #[derive(Clone)]
 pub struct ProfileRepository {
@@ -18,7 +28,6 @@ impl ProfileRepository {
 #[async_trait]
 impl ProfileRepositoryTrait for ProfileRepository {
     async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
-        use sqlx::Row;
         let row = sqlx::query(
             "SELECT client_id, fiscal_category, config FROM profiles WHERE client_id = $1"
         )
@@ -34,8 +43,7 @@ impl ProfileRepositoryTrait for ProfileRepository {
         }))
     }
 
-    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<crate::domain::models::IvaRate>> {
-        use sqlx::Row;
+    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>> {
         let row = sqlx::query(
             "SELECT jurisdiction, rate FROM iva_rates WHERE jurisdiction = $1"
         )
@@ -44,9 +52,351 @@ impl ProfileRepositoryTrait for ProfileRepository {
         .await
         .context("Failed to fetch IVA rate from DB")?;
 
-        Ok(row.map(|r| crate::domain::models::IvaRate {
+        Ok(row.map(|r| IvaRate {
             jurisdiction: r.get("jurisdiction"),
             rate: r.get("rate"),
         }))
     }
 }
+
+/// Whether a repository call failed in a way a retry may fix: lost or
+/// refused connections, pool timeouts, and serialization failures,
+/// deadlocks or restarts reported by the server.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().filter_map(|cause| cause.downcast_ref::<sqlx::Error>()).any(|e| match e {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::WorkerCrashed => true,
+        sqlx::Error::Database(db) => db.code().is_some_and(|code| {
+            // Class 08 is connection exceptions
+            code.starts_with("08") || matches!(&*code, "40001" | "40P01" | "53300" | "57P01" | "57P03")
+        }),
+        _ => false,
+    })
+}
+
+#[async_trait]
+impl HealthCheckTrait for ProfileRepository {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .context("PostgreSQL health check failed")?;
+        Ok(())
+    }
+}
+
+/// Evicts from `cache` every row changed in the database, including edits
+/// made directly in SQL, as announced on `CHANGE_CHANNEL`. Runs until `pool`
+/// is closed, then returns the connection it holds so closing can finish.
+///
+/// Changed rows still present are cached again, so the stale copy served
+/// during a database outage is never older than the last change.
+/// Notifications sent while the connection is down are lost, so once the
+/// listener is back every cached profile and IVA rate is dropped through
+/// `ProfileCacheTrait::resync`, which keeps the stale copies and, for Redis,
+/// runs on a single instance per `RESYNC_WINDOW`. Nothing is flushed while
+/// the database is unreachable.
+///
+/// `on_change` is called with every change received, and with `None` when
+/// some may have been missed.
+pub async fn run_change_listener<C, F>(pool: PgPool, cache: C, on_change: F)
+where
+    C: ProfileCacheTrait,
+    F: Fn(Option<&Invalidation>),
+{
+    // The listener keeps a pool connection checked out, which would make
+    // `PgPool::close` wait forever
+    let mut closed = pool.close_event();
+    let repo = ProfileRepository::new(pool.clone());
+    let Some(mut listener) = listen_with_backoff(&pool, &mut closed).await else {
+        return;
+    };
+    info!("Listening for database changes on {}", CHANGE_CHANNEL);
+
+    loop {
+        let Ok(received) = closed.do_until(listener.try_recv()).await else {
+            return;
+        };
+        match received {
+            Ok(Some(notification)) => {
+                let payload = notification.payload();
+                let Some(invalidation) = Invalidation::parse(payload) else {
+                    warn!("Ignoring unknown database change {:?}", payload);
+                    continue;
+                };
+                debug!("Database change, invalidating {}", payload);
+                if let Err(e) = invalidation.refresh(&cache, &repo).await {
+                    warn!("Failed to invalidate {} after a database change: {:#}", payload, e);
+                }
+                on_change(Some(&invalidation));
+            }
+            Ok(None) | Err(_) if pool.is_closed() => return,
+            lost => {
+                if let Err(e) = lost {
+                    warn!("Database change listener failed: {}", e);
+                }
+                warn!("Database change listener lost its connection, changes meanwhile may be missed");
+                drop(listener);
+                let Some(reconnected) = listen_with_backoff(&pool, &mut closed).await else {
+                    return;
+                };
+                listener = reconnected;
+                info!("Database change listener reconnected");
+                match cache.resync(RESYNC_WINDOW).await {
+                    Ok(true) => info!("Flushed the cache after reconnecting"),
+                    Ok(false) => debug!("Cache already flushed by another instance after reconnecting"),
+                    Err(e) => warn!("Failed to flush the cache after reconnecting: {:#}", e),
+                }
+                on_change(None);
+            }
+        }
+    }
+}
+
+/// Instances reconnecting after the same outage within this window share
+/// one cache flush.
+const RESYNC_WINDOW: Duration = Duration::from_secs(30);
+
+/// Starts listening on `CHANGE_CHANNEL`, retrying with backoff. `None` once
+/// `pool` is closed.
+async fn listen_with_backoff(pool: &PgPool, closed: &mut CloseEvent) -> Option<PgListener> {
+    const MIN_BACKOFF: Duration = Duration::from_millis(100);
+    const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        match closed.do_until(listen(pool)).await {
+            Ok(Ok(listener)) => return Some(listener),
+            Err(_) => return None,
+            Ok(Err(_)) if pool.is_closed() => return None,
+            Ok(Err(e)) => {
+                warn!("Database change listener failed to connect, retrying in {:?}: {:#}", backoff, e);
+                closed.do_until(tokio::time::sleep(backoff)).await.ok()?;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+async fn listen(pool: &PgPool) -> Result<PgListener> {
+    let mut listener = PgListener::connect_with(pool).await.context("Failed to connect to PostgreSQL")?;
+    listener.listen(CHANGE_CHANNEL).await.context("Failed to LISTEN")?;
+    Ok(listener)
+}
+
+/// Appends a row to `admin_audit_log`. Runs on the caller's transaction so the
+/// change and its audit record are committed together.
+async fn record_change(
+    conn: &mut PgConnection,
+    actor: &str,
+    entity: &str,
+    entity_key: &str,
+    action: &str,
+    old_value: Option<serde_json::Value>,
+    new_value: Option<serde_json::Value>,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO admin_audit_log (actor, entity, entity_key, action, old_value, new_value) \
+         VALUES ($1, $2, $3, $4, $5, $6)"
+    )
+    .bind(actor)
+    .bind(entity)
+    .bind(entity_key)
+    .bind(action)
+    .bind(old_value)
+    .bind(new_value)
+    .execute(conn)
+    .await
+    .context("Failed to write audit log entry")?;
+    Ok(())
+}
+
+#[async_trait]
+impl ProfileAdminRepositoryTrait for ProfileRepository {
+    async fn list_profiles(&self) -> Result<Vec<Profile>> {
+        let rows = sqlx::query(
+            "SELECT client_id, fiscal_category, config FROM profiles ORDER BY client_id"
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to list profiles from DB")?;
+
+        Ok(rows
+            .into_iter()
+            .map(|r| Profile {
+                client_id: r.get("client_id"),
+                fiscal_category: r.get("fiscal_category"),
+                config: r.get("config"),
+            })
+            .collect())
+    }
+
+    async fn insert_profile(&self, profile: &Profile, actor: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let inserted = sqlx::query(
+            "INSERT INTO profiles (client_id, fiscal_category, config) VALUES ($1, $2, $3) \
+             ON CONFLICT (client_id) DO NOTHING"
+        )
+        .bind(&profile.client_id)
+        .bind(&profile.fiscal_category)
+        .bind(&profile.config)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to insert profile")?
+        .rows_affected();
+        if inserted == 0 {
+            return Ok(false);
+        }
+
+        let new_value = serde_json::to_value(profile)?;
+        record_change(&mut tx, actor, "profile", &profile.client_id, "CREATE", None, Some(new_value)).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn update_profile(&self, profile: &Profile, actor: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let old_value: Option<serde_json::Value> = sqlx::query_scalar(
+            "SELECT to_jsonb(p) FROM profiles p WHERE client_id = $1 FOR UPDATE"
+        )
+        .bind(&profile.client_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .context("Failed to lock profile for update")?;
+        let Some(old_value) = old_value else {
+            return Ok(false);
+        };
+
+        sqlx::query("UPDATE profiles SET fiscal_category = $2, config = $3 WHERE client_id = $1")
+            .bind(&profile.client_id)
+            .bind(&profile.fiscal_category)
+            .bind(&profile.config)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to update profile")?;
+
+        let new_value = serde_json::to_value(profile)?;
+        record_change(&mut tx, actor, "profile", &profile.client_id, "UPDATE", Some(old_value), Some(new_value)).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn delete_profile(&self, client_id: &str, actor: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let old_value: Option<serde_json::Value> = sqlx::query_scalar(
+            "DELETE FROM profiles p WHERE client_id = $1 RETURNING to_jsonb(p)"
+        )
+        .bind(client_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .context("Failed to delete profile")?;
+        let Some(old_value) = old_value else {
+            return Ok(false);
+        };
+
+        record_change(&mut tx, actor, "profile", client_id, "DELETE", Some(old_value), None).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn list_iva_rates(&self) -> Result<Vec<IvaRate>> {
+        let rows = sqlx::query("SELECT jurisdiction, rate FROM iva_rates ORDER BY jurisdiction")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list IVA rates from DB")?;
+
+        Ok(rows
+            .into_iter()
+            .map(|r| IvaRate {
+                jurisdiction: r.get("jurisdiction"),
+                rate: r.get("rate"),
+            })
+            .collect())
+    }
+
+    async fn insert_iva_rate(&self, rate: &IvaRate, actor: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let inserted = sqlx::query(
+            "INSERT INTO iva_rates (jurisdiction, rate) VALUES ($1, $2) \
+             ON CONFLICT (jurisdiction) DO NOTHING"
+        )
+        .bind(&rate.jurisdiction)
+        .bind(rate.rate)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to insert IVA rate")?
+        .rows_affected();
+        if inserted == 0 {
+            return Ok(false);
+        }
+
+        let new_value = serde_json::to_value(rate)?;
+        record_change(&mut tx, actor, "iva_rate", &rate.jurisdiction, "CREATE", None, Some(new_value)).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn update_iva_rate(&self, rate: &IvaRate, actor: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let old_value: Option<serde_json::Value> = sqlx::query_scalar(
+            "SELECT to_jsonb(r) FROM iva_rates r WHERE jurisdiction = $1 FOR UPDATE"
+        )
+        .bind(&rate.jurisdiction)
+        .fetch_optional(&mut *tx)
+        .await
+        .context("Failed to lock IVA rate for update")?;
+        let Some(old_value) = old_value else {
+            return Ok(false);
+        };
+
+        sqlx::query("UPDATE iva_rates SET rate = $2 WHERE jurisdiction = $1")
+            .bind(&rate.jurisdiction)
+            .bind(rate.rate)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to update IVA rate")?;
+
+        let new_value = serde_json::to_value(rate)?;
+        record_change(&mut tx, actor, "iva_rate", &rate.jurisdiction, "UPDATE", Some(old_value), Some(new_value)).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn delete_iva_rate(&self, jurisdiction: &str, actor: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let old_value: Option<serde_json::Value> = sqlx::query_scalar(
+            "DELETE FROM iva_rates r WHERE jurisdiction = $1 RETURNING to_jsonb(r)"
+        )
+        .bind(jurisdiction)
+        .fetch_optional(&mut *tx)
+        .await
+        .context("Failed to delete IVA rate")?;
+        let Some(old_value) = old_value else {
+            return Ok(false);
+        };
+
+        record_change(&mut tx, actor, "iva_rate", jurisdiction, "DELETE", Some(old_value), None).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_transient() {
+        let timed_out = anyhow::Error::new(sqlx::Error::PoolTimedOut).context("Failed to fetch profile from DB");
+        assert!(is_transient(&timed_out));
+        let reset = sqlx::Error::Io(std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+        assert!(is_transient(&anyhow::Error::new(reset)));
+
+        assert!(!is_transient(&anyhow::Error::new(sqlx::Error::RowNotFound)));
+        assert!(!is_transient(&anyhow::Error::new(sqlx::Error::PoolClosed)));
+        assert!(!is_transient(&anyhow::anyhow!("timeout")));
+    }
+}
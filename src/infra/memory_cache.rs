@@ -0,0 +1,776 @@
+use crate::domain::models::{IvaRate, Profile};
+use crate::domain::invalidation::Invalidation;
+use crate::domain::traits::{CacheLookup, DEFAULT_NEGATIVE_TTL, Generation, ProfileCacheTrait, StaleEntry};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::debug;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryCacheConfig {
+    /// How long an entry is served from memory (5 minutes as per RT-002).
+    pub ttl: Duration,
+    /// Entries kept per kind (profiles and IVA rates); the oldest are
+    /// evicted first. `0` disables the in-memory level.
+    pub capacity: usize,
+    /// How long a "not found" entry is served; zero disables negative
+    /// caching.
+    pub negative_ttl: Duration,
+    /// "Not found" entries kept per kind, apart from `capacity`, so lookups
+    /// of missing keys only evict each other.
+    pub negative_capacity: usize,
+}
+
+impl Default for MemoryCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(300),
+            capacity: 10_000,
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
+            negative_capacity: 1_000,
+        }
+    }
+}
+
+impl MemoryCacheConfig {
+    /// Reads `L1_CACHE_TTL_SECS` (default 300), `L1_CACHE_CAPACITY` (default
+    /// 10000), `NEGATIVE_CACHE_TTL_SECS` (default 60) and
+    /// `L1_NEGATIVE_CACHE_CAPACITY` (default 1000).
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+        if let Ok(value) = env::var("L1_CACHE_TTL_SECS") {
+            config.ttl = Duration::from_secs(value.parse().context("Invalid value for L1_CACHE_TTL_SECS")?);
+        }
+        if let Ok(value) = env::var("L1_CACHE_CAPACITY") {
+            config.capacity = value.parse().context("Invalid value for L1_CACHE_CAPACITY")?;
+        }
+        if let Ok(value) = env::var("NEGATIVE_CACHE_TTL_SECS") {
+            config.negative_ttl =
+                Duration::from_secs(value.parse().context("Invalid value for NEGATIVE_CACHE_TTL_SECS")?);
+        }
+        if let Ok(value) = env::var("L1_NEGATIVE_CACHE_CAPACITY") {
+            config.negative_capacity = value.parse().context("Invalid value for L1_NEGATIVE_CACHE_CAPACITY")?;
+        }
+        Ok(config)
+    }
+}
+
+/// Lookups answered by one cache level.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LevelStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Answered with a cached "not found".
+    pub not_found: u64,
+}
+
+/// Counters of a `MemoryCache` and the cache it wraps. Every lookup counts
+/// once in `l1`; only L1 misses reach `l2`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub l1: LevelStats,
+    pub l2: LevelStats,
+}
+
+#[derive(Default)]
+struct LevelCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    not_found: AtomicU64,
+}
+
+impl LevelCounters {
+    fn record<T>(&self, lookup: &CacheLookup<T>) {
+        let counter = match lookup {
+            CacheLookup::Hit(_) => &self.hits,
+            CacheLookup::NotFound => &self.not_found,
+            CacheLookup::Miss => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LevelStats {
+        LevelStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            not_found: self.not_found.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Entries of one kind, values and "not found" each bounded by their own
+/// capacity so lookups of missing keys cannot evict real entries.
+///
+/// Every write and removal takes the next sequence number, which doubles as
+/// the generation of the map: a value read elsewhere is only stored if its
+/// key was not removed since the read started (see `generation` and
+/// `insert_if_unchanged`).
+struct Entries<V> {
+    found: Bounded<V>,
+    not_found: Bounded<()>,
+    next_seq: u64,
+    /// Sequence number of the last removal of each key, bounded by the
+    /// capacity.
+    removed: HashMap<String, u64>,
+    /// Reads started before this sequence number store nothing: set by
+    /// `clear` and when `removed` is trimmed.
+    floor: u64,
+}
+
+impl<V: Clone> Entries<V> {
+    fn new(capacity: usize, negative_capacity: usize) -> Self {
+        Self {
+            found: Bounded::new(capacity),
+            not_found: Bounded::new(negative_capacity),
+            next_seq: 0,
+            removed: HashMap::new(),
+            floor: 0,
+        }
+    }
+
+    /// Taken before reading a value from the next level.
+    fn generation(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Stores a value read since `generation` unless its key was removed or
+    /// the map cleared meanwhile, which would bring back what an
+    /// invalidation just dropped.
+    fn insert_if_unchanged(&mut self, generation: u64, key: String, value: Option<V>, expires: Instant) {
+        let removed_since = generation < self.floor || self.removed.get(&key).is_some_and(|seq| *seq >= generation);
+        if !removed_since {
+            self.insert(key, value, expires);
+        }
+    }
+
+    /// `Some(None)` is a cached "not found".
+    fn get(&mut self, key: &str, now: Instant) -> Option<Option<V>> {
+        if let Some(value) = self.found.get(key, now) {
+            return Some(Some(value));
+        }
+        self.not_found.get(key, now).map(|()| None)
+    }
+
+    fn insert(&mut self, key: String, value: Option<V>, expires: Instant) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        match value {
+            Some(value) => {
+                self.not_found.remove(&key);
+                self.found.insert(key, value, expires, seq);
+            }
+            None => {
+                self.found.remove(&key);
+                self.not_found.insert(key, (), expires, seq);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.found.remove(key);
+        self.not_found.remove(key);
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.removed.insert(key.to_string(), seq);
+        if self.removed.len() > self.found.capacity.max(1) {
+            // Forgetting removals is safe as long as reads in flight are
+            // treated as if everything had been removed
+            self.removed.clear();
+            self.floor = self.next_seq;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.found.clear();
+        self.not_found.clear();
+        self.removed.clear();
+        self.next_seq += 1;
+        self.floor = self.next_seq;
+    }
+}
+
+/// Map of at most `capacity` entries that expire at the instant given when
+/// stored; the oldest writes are evicted first.
+struct Bounded<V> {
+    capacity: usize,
+    /// Value, expiry and the sequence number of the write that stored it.
+    map: HashMap<String, (V, Instant, u64)>,
+    /// Writes in order, the oldest at the front. Positions of keys
+    /// overwritten or removed since are skipped when popped.
+    order: VecDeque<(String, u64)>,
+}
+
+impl<V: Clone> Bounded<V> {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, map: HashMap::new(), order: VecDeque::new() }
+    }
+
+    fn get(&mut self, key: &str, now: Instant) -> Option<V> {
+        match self.map.get(key) {
+            Some((value, expires, _)) if *expires > now => Some(value.clone()),
+            Some(_) => {
+                self.map.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&mut self, key: String, value: V, expires: Instant, seq: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.map.insert(key.clone(), (value, expires, seq));
+        self.order.push_back((key, seq));
+
+        while self.map.len() > self.capacity {
+            let Some((key, seq)) = self.order.pop_front() else {
+                break;
+            };
+            if self.is_current(&key, seq) {
+                self.map.remove(&key);
+            }
+        }
+        // Rewriting the same keys leaves stale positions behind
+        if self.order.len() > self.capacity.saturating_mul(2) {
+            let map = &self.map;
+            self.order.retain(|(key, seq)| map.get(key).is_some_and(|(_, _, current)| current == seq));
+        }
+    }
+
+    fn is_current(&self, key: &str, seq: u64) -> bool {
+        self.map.get(key).is_some_and(|(_, _, current)| *current == seq)
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.map.remove(key);
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+        self.order.clear();
+    }
+}
+
+struct Inner {
+    config: MemoryCacheConfig,
+    profiles: Mutex<Entries<Profile>>,
+    iva_rates: Mutex<Entries<IvaRate>>,
+    l1: LevelCounters,
+    l2: LevelCounters,
+}
+
+/// In-process L1 cache in front of another `ProfileCacheTrait` (the Redis
+/// L2 in production), as RT-002 specifies. Reads are served from memory
+/// until the entry's TTL expires; writes and invalidations go to both
+/// levels. Clones share the same entries and counters.
+///
+/// Writes made on other instances reach this level through `evict` (see
+/// `cache::run_invalidation_listener`), or once the entry expires.
+#[derive(Clone)]
+pub struct MemoryCache<C> {
+    next: C,
+    inner: Arc<Inner>,
+}
+
+impl<C> MemoryCache<C>
+where
+    C: ProfileCacheTrait,
+{
+    pub fn new(next: C, config: MemoryCacheConfig) -> Self {
+        Self {
+            next,
+            inner: Arc::new(Inner {
+                config,
+                profiles: Mutex::new(Entries::new(config.capacity, config.negative_capacity)),
+                iva_rates: Mutex::new(Entries::new(config.capacity, config.negative_capacity)),
+                l1: LevelCounters::default(),
+                l2: LevelCounters::default(),
+            }),
+        }
+    }
+
+    /// This level in front of `next` instead, sharing its entries and
+    /// counters. Lets reads reach the wrapped cache through other layers
+    /// (e.g. timeouts) than writes and invalidations do.
+    pub fn with_next<N: ProfileCacheTrait>(&self, next: N) -> MemoryCache<N> {
+        MemoryCache { next, inner: self.inner.clone() }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats { l1: self.inner.l1.snapshot(), l2: self.inner.l2.snapshot() }
+    }
+
+    /// Drops a key from this level only, leaving the wrapped cache as is.
+    /// Lookups already reading the key from the wrapped cache will not store
+    /// what they read.
+    pub fn evict(&self, invalidation: &Invalidation) {
+        match invalidation {
+            Invalidation::Profile(client_id) => {
+                self.inner.profiles.lock().unwrap_or_else(PoisonError::into_inner).remove(client_id)
+            }
+            Invalidation::IvaRate(jurisdiction) => {
+                self.inner.iva_rates.lock().unwrap_or_else(PoisonError::into_inner).remove(jurisdiction)
+            }
+            Invalidation::AllProfiles => self.inner.profiles.lock().unwrap_or_else(PoisonError::into_inner).clear(),
+            Invalidation::AllIvaRates => self.inner.iva_rates.lock().unwrap_or_else(PoisonError::into_inner).clear(),
+        }
+    }
+
+    /// Empties this level.
+    pub fn flush(&self) {
+        self.inner.profiles.lock().unwrap_or_else(PoisonError::into_inner).clear();
+        self.inner.iva_rates.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    /// Answers from this level, else from the wrapped cache through `next`,
+    /// keeping its hit or "not found".
+    async fn read_through<V: Clone>(
+        &self,
+        entries: &Mutex<Entries<V>>,
+        key: &str,
+        next: impl Future<Output = Result<CacheLookup<V>>>,
+    ) -> Result<CacheLookup<V>> {
+        let (local, generation) = {
+            let mut entries = entries.lock().unwrap_or_else(PoisonError::into_inner);
+            let local = match entries.get(key, Instant::now()) {
+                Some(Some(value)) => CacheLookup::Hit(value),
+                Some(None) => CacheLookup::NotFound,
+                None => CacheLookup::Miss,
+            };
+            (local, entries.generation())
+        };
+        self.inner.l1.record(&local);
+        if !matches!(local, CacheLookup::Miss) {
+            debug!("L1 answered for {}", key);
+            return Ok(local);
+        }
+
+        let lookup = next.await?;
+        self.inner.l2.record(&lookup);
+        match &lookup {
+            CacheLookup::Hit(value) => self.store(entries, key, Some(value.clone()), Some(generation)),
+            CacheLookup::NotFound => self.store(entries, key, None, Some(generation)),
+            CacheLookup::Miss => {}
+        }
+        Ok(lookup)
+    }
+
+    /// Generation of `entries`, and of the wrapped cache through `next`. If
+    /// the wrapped cache cannot tell its own, what is loaded is only kept in
+    /// this level, which keeps serving while the wrapped cache is down.
+    async fn generation<V: Clone>(
+        &self,
+        entries: &Mutex<Entries<V>>,
+        key: &str,
+        next: impl Future<Output = Result<Generation>>,
+    ) -> Generation {
+        let local = entries.lock().unwrap_or_else(PoisonError::into_inner).generation();
+        let shared = match next.await {
+            Ok(generation) => generation.shared,
+            Err(e) => {
+                debug!("No generation from the wrapped cache for {}: {:#}", key, e);
+                None
+            }
+        };
+        Generation { local: Some(local), shared }
+    }
+
+    /// `generation` is given for values read from the wrapped cache, so
+    /// they are dropped if the key was invalidated during the read.
+    fn store<V: Clone>(&self, entries: &Mutex<Entries<V>>, key: &str, value: Option<V>, generation: Option<u64>) {
+        let config = self.inner.config;
+        let ttl = if value.is_some() { config.ttl } else { config.negative_ttl };
+        if config.capacity == 0 || ttl.is_zero() {
+            return;
+        }
+        let mut entries = entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let expires = Instant::now() + ttl;
+        match generation {
+            Some(generation) => entries.insert_if_unchanged(generation, key.to_string(), value, expires),
+            None => entries.insert(key.to_string(), value, expires),
+        }
+    }
+}
+
+#[async_trait]
+impl<C> ProfileCacheTrait for MemoryCache<C>
+where
+    C: ProfileCacheTrait,
+{
+    async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
+        Ok(self.lookup_profile(client_id).await?.into_value())
+    }
+
+    async fn set(&self, profile: &Profile) -> Result<()> {
+        // Kept in L1 even if Redis fails, so L1 keeps serving while it is down
+        self.store(&self.inner.profiles, &profile.client_id, Some(profile.clone()), None);
+        self.next.set(profile).await
+    }
+
+    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>> {
+        Ok(self.lookup_iva_rate(jurisdiction).await?.into_value())
+    }
+
+    async fn set_iva_rate(&self, rate: &IvaRate) -> Result<()> {
+        self.store(&self.inner.iva_rates, &rate.jurisdiction, Some(rate.clone()), None);
+        self.next.set_iva_rate(rate).await
+    }
+
+    async fn lookup_profile(&self, client_id: &str) -> Result<CacheLookup<Profile>> {
+        self.read_through(&self.inner.profiles, client_id, self.next.lookup_profile(client_id)).await
+    }
+
+    async fn set_profile_not_found(&self, client_id: &str) -> Result<()> {
+        self.store(&self.inner.profiles, client_id, None, None);
+        self.next.set_profile_not_found(client_id).await
+    }
+
+    async fn lookup_iva_rate(&self, jurisdiction: &str) -> Result<CacheLookup<IvaRate>> {
+        self.read_through(&self.inner.iva_rates, jurisdiction, self.next.lookup_iva_rate(jurisdiction)).await
+    }
+
+    async fn set_iva_rate_not_found(&self, jurisdiction: &str) -> Result<()> {
+        self.store(&self.inner.iva_rates, jurisdiction, None, None);
+        self.next.set_iva_rate_not_found(jurisdiction).await
+    }
+
+    async fn get_stale_profile(&self, client_id: &str) -> Result<Option<StaleEntry<Profile>>> {
+        self.next.get_stale_profile(client_id).await
+    }
+
+    async fn get_stale_iva_rate(&self, jurisdiction: &str) -> Result<Option<StaleEntry<IvaRate>>> {
+        self.next.get_stale_iva_rate(jurisdiction).await
+    }
+
+    async fn profile_generation(&self, client_id: &str) -> Result<Generation> {
+        Ok(self.generation(&self.inner.profiles, client_id, self.next.profile_generation(client_id)).await)
+    }
+
+    async fn fill_profile(&self, client_id: &str, profile: Option<&Profile>, generation: Generation) -> Result<()> {
+        if let Some(local) = generation.local {
+            self.store(&self.inner.profiles, client_id, profile.cloned(), Some(local));
+        }
+        self.next.fill_profile(client_id, profile, generation).await
+    }
+
+    async fn iva_rate_generation(&self, jurisdiction: &str) -> Result<Generation> {
+        Ok(self.generation(&self.inner.iva_rates, jurisdiction, self.next.iva_rate_generation(jurisdiction)).await)
+    }
+
+    async fn fill_iva_rate(&self, jurisdiction: &str, rate: Option<&IvaRate>, generation: Generation) -> Result<()> {
+        if let Some(local) = generation.local {
+            self.store(&self.inner.iva_rates, jurisdiction, rate.cloned(), Some(local));
+        }
+        self.next.fill_iva_rate(jurisdiction, rate, generation).await
+    }
+
+    async fn invalidate_profile(&self, client_id: &str) -> Result<()> {
+        self.evict(&Invalidation::Profile(client_id.to_string()));
+        self.next.invalidate_profile(client_id).await
+    }
+
+    async fn invalidate_iva_rate(&self, jurisdiction: &str) -> Result<()> {
+        self.evict(&Invalidation::IvaRate(jurisdiction.to_string()));
+        self.next.invalidate_iva_rate(jurisdiction).await
+    }
+
+    async fn invalidate_all_profiles(&self) -> Result<()> {
+        self.evict(&Invalidation::AllProfiles);
+        self.next.invalidate_all_profiles().await
+    }
+
+    async fn invalidate_all_iva_rates(&self) -> Result<()> {
+        self.evict(&Invalidation::AllIvaRates);
+        self.next.invalidate_all_iva_rates().await
+    }
+
+    /// This level is always flushed; whoever flushes the wrapped cache
+    /// evicts every other instance's L1 through its invalidations.
+    async fn resync(&self, window: Duration) -> Result<bool> {
+        self.flush();
+        self.next.resync(window).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockCache;
+    use mockall::predicate::eq;
+
+    fn profile(client_id: &str) -> Profile {
+        Profile {
+            client_id: client_id.to_string(),
+            fiscal_category: "RESPONSABLE_INSCRIPTO".to_string(),
+            config: serde_json::json!({}),
+        }
+    }
+
+    fn config(capacity: usize) -> MemoryCacheConfig {
+        MemoryCacheConfig {
+            ttl: Duration::from_secs(300),
+            capacity,
+            negative_ttl: Duration::from_secs(60),
+            negative_capacity: capacity,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_serves_from_memory_until_ttl_expires() {
+        let mut l2 = MockCache::new();
+        l2.expect_get_by_id().with(eq("c1")).times(2).returning(|id| Ok(Some(profile(id))));
+        l2.expect_get_by_id().with(eq("c2")).times(1).returning(|_| Ok(None));
+        let cache = MemoryCache::new(l2, config(10));
+
+        for _ in 0..3 {
+            assert!(cache.get_by_id("c1").await.unwrap().is_some());
+        }
+        assert!(cache.get_by_id("c2").await.unwrap().is_none());
+        assert_eq!(
+            cache.stats(),
+            CacheStats { l1: LevelStats { hits: 2, misses: 2, not_found: 0 }, l2: LevelStats { hits: 1, misses: 1, not_found: 0 } }
+        );
+
+        tokio::time::advance(Duration::from_secs(301)).await;
+        assert!(cache.get_by_id("c1").await.unwrap().is_some());
+        assert_eq!(cache.stats().l2.hits, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_not_found_expires_after_negative_ttl() {
+        let mut l2 = MockCache::new();
+        l2.expect_get_by_id().with(eq("c9")).times(1).returning(|_| Ok(None));
+        let cache = MemoryCache::new(l2, config(10));
+
+        cache.set_profile_not_found("c9").await.unwrap();
+        assert!(matches!(cache.lookup_profile("c9").await.unwrap(), CacheLookup::NotFound));
+        assert_eq!(cache.stats().l1, LevelStats { hits: 0, misses: 0, not_found: 1 });
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert!(matches!(cache.lookup_profile("c9").await.unwrap(), CacheLookup::Miss));
+        assert_eq!(cache.stats().l2.misses, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_evicts_oldest_write_when_full() {
+        let mut l2 = MockCache::new();
+        l2.expect_set_iva_rate().returning(|_| Ok(()));
+        l2.expect_get_iva_rate().with(eq("J2")).times(1).returning(|_| Ok(None));
+        let cache = MemoryCache::new(l2, config(2));
+
+        for jurisdiction in ["J1", "J2", "J1", "J3"] {
+            cache.set_iva_rate(&IvaRate { jurisdiction: jurisdiction.to_string(), rate: 0.21 }).await.unwrap();
+        }
+        // J1 was rewritten after J2, so J2 is the oldest write
+        assert!(cache.get_iva_rate("J3").await.unwrap().is_some());
+        assert!(cache.get_iva_rate("J1").await.unwrap().is_some());
+        assert!(cache.get_iva_rate("J2").await.unwrap().is_none());
+        assert_eq!(cache.inner.iva_rates.lock().unwrap().found.map.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_not_found_entries_have_their_own_capacity() {
+        let mut l2 = MockCache::new();
+        l2.expect_set().returning(|_| Ok(()));
+        let cache = MemoryCache::new(l2, MemoryCacheConfig { negative_capacity: 2, ..config(2) });
+
+        cache.set(&profile("c1")).await.unwrap();
+        cache.set(&profile("c2")).await.unwrap();
+        for client_id in ["m1", "m2", "m3", "m4"] {
+            cache.set_profile_not_found(client_id).await.unwrap();
+        }
+
+        let entries = cache.inner.profiles.lock().unwrap();
+        assert_eq!(entries.found.map.len(), 2);
+        assert_eq!(entries.not_found.map.len(), 2);
+        assert!(entries.not_found.map.contains_key("m4"));
+    }
+
+    #[tokio::test]
+    async fn test_invalidation_reaches_both_levels() {
+        let mut l2 = MockCache::new();
+        l2.expect_set().returning(|_| Ok(()));
+        l2.expect_invalidate_profile().with(eq("c1")).times(1).returning(|_| Ok(()));
+        l2.expect_set_iva_rate().returning(|_| Ok(()));
+        l2.expect_invalidate_all_iva_rates().times(1).returning(|| Ok(()));
+        l2.expect_get_by_id().times(1).returning(|_| Ok(None));
+        l2.expect_get_iva_rate().times(1).returning(|_| Ok(None));
+        let cache = MemoryCache::new(l2, config(10));
+
+        cache.set(&profile("c1")).await.unwrap();
+        cache.set_iva_rate(&IvaRate { jurisdiction: "TDF".to_string(), rate: 0.105 }).await.unwrap();
+        cache.invalidate_profile("c1").await.unwrap();
+        cache.invalidate_all_iva_rates().await.unwrap();
+
+        assert!(cache.get_by_id("c1").await.unwrap().is_none());
+        assert!(cache.get_iva_rate("TDF").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resync_flushes_memory_and_the_wrapped_cache() {
+        let mut l2 = MockCache::new();
+        l2.expect_set().returning(|_| Ok(()));
+        l2.expect_invalidate_all_profiles().times(1).returning(|| Ok(()));
+        l2.expect_invalidate_all_iva_rates().times(1).returning(|| Ok(()));
+        l2.expect_get_by_id().times(1).returning(|_| Ok(None));
+        let cache = MemoryCache::new(l2, config(10));
+
+        cache.set(&profile("c1")).await.unwrap();
+        assert!(cache.resync(Duration::from_secs(30)).await.unwrap());
+
+        assert!(cache.get_by_id("c1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_keeps_serving_from_memory_when_redis_fails() {
+        let mut l2 = MockCache::new();
+        l2.expect_set().times(1).returning(|_| Err(anyhow::anyhow!("redis down")));
+        l2.expect_get_by_id().times(0);
+        let cache = MemoryCache::new(l2, config(10));
+
+        assert!(cache.set(&profile("c1")).await.is_err());
+        assert!(cache.get_by_id("c1").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_evict_only_drops_local_entries() {
+        let mut l2 = MockCache::new();
+        l2.expect_set().returning(|_| Ok(()));
+        l2.expect_get_by_id().times(1).returning(|id| Ok(Some(profile(id))));
+        let cache = MemoryCache::new(l2, config(10));
+
+        cache.set(&profile("c1")).await.unwrap();
+        cache.set(&profile("c2")).await.unwrap();
+        cache.evict(&Invalidation::Profile("c1".to_string()));
+
+        assert!(cache.get_by_id("c2").await.unwrap().is_some());
+        assert!(cache.get_by_id("c1").await.unwrap().is_some());
+        assert_eq!(cache.stats().l2, LevelStats { hits: 1, misses: 0, not_found: 0 });
+    }
+    /// Redis stand-in whose profile reads wait for `gate`.
+    struct GatedCache {
+        gate: Arc<tokio::sync::Notify>,
+    }
+
+    #[async_trait]
+    impl ProfileCacheTrait for GatedCache {
+        async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
+            self.gate.notified().await;
+            Ok(Some(profile(client_id)))
+        }
+
+        async fn set(&self, _profile: &Profile) -> Result<()> {
+            Ok(())
+        }
+
+        async fn get_iva_rate(&self, _jurisdiction: &str) -> Result<Option<IvaRate>> {
+            Ok(None)
+        }
+
+        async fn set_iva_rate(&self, _rate: &IvaRate) -> Result<()> {
+            Ok(())
+        }
+
+        async fn invalidate_profile(&self, _client_id: &str) -> Result<()> {
+            Ok(())
+        }
+
+        async fn invalidate_iva_rate(&self, _jurisdiction: &str) -> Result<()> {
+            Ok(())
+        }
+
+        async fn invalidate_all_profiles(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn invalidate_all_iva_rates(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalidation_during_a_read_is_not_undone() {
+        let gate = Arc::new(tokio::sync::Notify::new());
+        let cache = MemoryCache::new(GatedCache { gate: gate.clone() }, config(10));
+        let profiles = |cache: &MemoryCache<GatedCache>| cache.inner.profiles.lock().unwrap().found.map.len();
+
+        let (cache, gate) = (&cache, &gate);
+        let invalidate_during_read = |invalidate: fn(&MemoryCache<GatedCache>)| async move {
+            tokio::task::yield_now().await;
+            invalidate(cache);
+            gate.notify_one();
+        };
+        let evict_c1: fn(&MemoryCache<GatedCache>) = |cache| cache.evict(&Invalidation::Profile("c1".to_string()));
+
+        let (read, ()) = tokio::join!(cache.get_by_id("c1"), invalidate_during_read(evict_c1));
+        assert!(read.unwrap().is_some());
+        assert_eq!(profiles(cache), 0);
+
+        let (read, ()) = tokio::join!(cache.get_by_id("c1"), invalidate_during_read(|cache| cache.flush()));
+        assert!(read.unwrap().is_some());
+        assert_eq!(profiles(cache), 0);
+
+        // Other keys, and reads started after the invalidation, are stored
+        let (read, ()) = tokio::join!(cache.get_by_id("c2"), invalidate_during_read(evict_c1));
+        assert!(read.unwrap().is_some());
+        gate.notify_one();
+        assert!(cache.get_by_id("c1").await.unwrap().is_some());
+        assert_eq!(profiles(cache), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fill_skips_loads_invalidated_since_their_generation() {
+        let mut l2 = MockCache::new();
+        l2.expect_invalidate_profile().with(eq("c1")).times(1).returning(|_| Ok(()));
+        l2.expect_invalidate_all_iva_rates().times(1).returning(|| Ok(()));
+        // MockCache keeps no generation, so its fills are plain writes
+        l2.expect_set().times(2).returning(|_| Ok(()));
+        l2.expect_set_iva_rate().times(1).returning(|_| Ok(()));
+        let cache = MemoryCache::new(l2, config(10));
+        let profiles = |cache: &MemoryCache<MockCache>| cache.inner.profiles.lock().unwrap().found.map.len();
+        let rates = |cache: &MemoryCache<MockCache>| cache.inner.iva_rates.lock().unwrap().found.map.len();
+
+        let generation = cache.profile_generation("c1").await.unwrap();
+        cache.invalidate_profile("c1").await.unwrap();
+        cache.fill_profile("c1", Some(&profile("c1")), generation).await.unwrap();
+        assert_eq!(profiles(&cache), 0);
+
+        let generation = cache.iva_rate_generation("TDF").await.unwrap();
+        cache.invalidate_all_iva_rates().await.unwrap();
+        cache.fill_iva_rate("TDF", Some(&IvaRate { jurisdiction: "TDF".to_string(), rate: 0.105 }), generation).await.unwrap();
+        assert_eq!(rates(&cache), 0);
+
+        // Loads started after the invalidation are stored
+        let generation = cache.profile_generation("c1").await.unwrap();
+        cache.fill_profile("c1", Some(&profile("c1")), generation).await.unwrap();
+        assert_eq!(profiles(&cache), 1);
+    }
+
+    #[tokio::test]
+    async fn test_levels_with_another_next_share_entries() {
+        let mut reads = MockCache::new();
+        reads.expect_get_by_id().times(1).returning(|client_id| Ok(Some(profile(client_id))));
+        let mut writes = MockCache::new();
+        writes.expect_invalidate_profile().with(eq("c1")).times(1).returning(|_| Ok(()));
+
+        let admin = MemoryCache::new(writes, config(10));
+        let resolver = admin.with_next(reads);
+
+        assert!(resolver.get_by_id("c1").await.unwrap().is_some());
+        assert!(resolver.get_by_id("c1").await.unwrap().is_some());
+        assert_eq!(admin.stats().l1.hits, 1);
+
+        // Invalidated through one, gone from the other without reaching `reads`
+        admin.invalidate_profile("c1").await.unwrap();
+        let mut reads = MockCache::new();
+        reads.expect_get_by_id().times(1).returning(|_| Ok(None));
+        assert!(admin.with_next(reads).get_by_id("c1").await.unwrap().is_none());
+    }
+}
@@ -1,2 +1,3 @@
 pub mod db;
 pub mod cache;
+pub mod memory_cache;
@@ -1,6 +1,10 @@
 pub mod domain;
 pub mod infra;
 pub mod app;
+pub mod client;
+
+#[cfg(test)]
+mod test_support;
 
 pub mod schema_capnp {
     include!(concat!(env!("OUT_DIR"), "/schema_capnp.rs"));
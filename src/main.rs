@@ -1,33 +1,32 @@
 use anyhow::{Context, Result};
-use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
 use dotenvy::dotenv;
-use futures_util::{AsyncReadExt, FutureExt};
 use sqlx::postgres::PgPoolOptions;
 use std::env;
-use std::net::ToSocketAddrs;
 use std::sync::Arc;
-use tokio::net::TcpListener;
-use tracing::info;
+use std::time::Duration;
+use tracing::{error, info};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 
 use tax_manager::domain::calculators::IVACalculator;
-use tax_manager::infra::db::ProfileRepository;
-use tax_manager::infra::cache::ProfileCache;
+use tax_manager::infra::db::{self, ProfileRepository};
+use tax_manager::infra::cache::{self, ProfileCache};
+use tax_manager::infra::memory_cache::{MemoryCache, MemoryCacheConfig};
 use tax_manager::app::resolver::ProfileResolver;
 use tax_manager::app::orchestrator::Orchestrator;
-use tax_manager::app::rpc::TaxEngineImpl;
-use tax_manager::schema_capnp::tax_engine;
+use tax_manager::app::admin::AdminService;
+use tax_manager::app::api::CalculateApi;
+use tax_manager::app::changes::{Change, ChangeNotifier};
+use tax_manager::domain::invalidation::Invalidation;
+use tax_manager::app::health::HealthService;
+use tax_manager::app::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, Guarded};
+use tax_manager::app::retry::{RetryPolicy, Retrying};
+use tax_manager::app::timeout::{DependencyTimeouts, TimeLimited};
+use tax_manager::app::http::HttpServer;
+use tax_manager::app::server::{AdminServer, RpcServer, ServerConfig};
+use tax_manager::app::shutdown::{self, Shutdown};
 
 
-// Also need Clone for ProfileResolver and trait objects if possible, 
-// or I'll just use Arc for them inside the implementation.
-// Let's check how they are defined.
-// Actually, let's wrap the whole Orchestrator in an Arc if it's easier.
-
-// Wait, Orchestrator has private fields. I might need to make them public or provide a clone.
-// Let's check src/app/orchestrator.rs
-
 #[tokio::main]
 async fn main() -> Result<()> {
     // Load environment variables
@@ -42,8 +41,11 @@ async fn main() -> Result<()> {
 
     info!("Starting Tax Engine RPC Server...");
 
-    let addr_str = env::var("RPC_ADDR").unwrap_or_else(|_| "0.0.0.0:50051".to_string());
-    let addr = addr_str.to_socket_addrs()?.next().context("Invalid address")?;
+    let server_config = ServerConfig::from_env()?;
+    let l1_config = MemoryCacheConfig::from_env()?;
+    let breaker_config = CircuitBreakerConfig::from_env()?;
+    let retry_policy = RetryPolicy::from_env()?;
+    let timeouts = DependencyTimeouts::from_env()?;
 
     // Infrastructure setup
     let database_url = env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
@@ -51,43 +53,187 @@ async fn main() -> Result<()> {
 
     info!("Connecting to PostgreSQL...");
     let db_pool = PgPoolOptions::new()
-        .max_connections(5)
+        // One connection is held by the change listener until the pool closes
+        .max_connections(6)
         .connect(&database_url)
         .await
         .context("Failed to connect to PostgreSQL")?;
 
     info!("Connecting to Redis...");
     let redis_client = Arc::new(redis::Client::open(redis_url)?);
-    
+
     // Wire layers
-    let db_repo = ProfileRepository::new(db_pool);
-    let cache_repo = ProfileCache::new(redis_client);
-    let profile_resolver = ProfileResolver::new(db_repo, cache_repo);
+    let db_repo = ProfileRepository::new(db_pool.clone());
+    // NEGATIVE_CACHE_TTL_SECS (read with the L1 config) applies to both levels
+    let mut redis_cache = ProfileCache::new(redis_client.clone()).with_negative_ttl(l1_config.negative_ttl);
+    if let Ok(value) = env::var("CACHE_EARLY_REFRESH_SECS") {
+        let window: u64 = value.parse().context("Invalid value for CACHE_EARLY_REFRESH_SECS")?;
+        redis_cache = redis_cache.with_early_refresh(Duration::from_secs(window));
+    }
+    if let Ok(value) = env::var("CACHE_STALE_TTL_SECS") {
+        let ttl: u64 = value.parse().context("Invalid value for CACHE_STALE_TTL_SECS")?;
+        redis_cache = redis_cache.with_stale_ttl(Duration::from_secs(ttl));
+    }
+
+    let shutdown = Shutdown::new();
+
+    // RT-003: calls to a failing dependency fail fast instead of waiting on
+    // it. Health probes, admin access and cache invalidations bypass the
+    // breakers.
+    let db_breaker = Arc::new(CircuitBreaker::new("postgres", breaker_config));
+    let redis_breaker = Arc::new(CircuitBreaker::new("redis", breaker_config));
+
+    // Only PostgreSQL is critical: without Redis calculations read from
+    // L1 and the DB, and the instance reports DEGRADED
+    let health = Arc::new(
+        HealthService::new()
+            .with_check(Arc::new(db_repo.clone()), true)
+            .with_check(Arc::new(redis_cache.clone()), false)
+            .with_circuit(db_breaker.clone())
+            .with_circuit(redis_breaker.clone())
+            .with_shutdown(shutdown.clone()),
+    );
+
+    // Each attempt is bounded by its dependency's timeout, and transient
+    // errors are retried inside the breakers, which count each call once
+    // whatever its attempts
+    let redis_retries = Retrying::new(
+        TimeLimited::new(redis_cache.clone(), "redis", timeouts.cache),
+        retry_policy.clone(),
+        cache::is_transient,
+    );
+    let db_retries =
+        Retrying::new(TimeLimited::new(db_repo.clone(), "postgres", timeouts.db), retry_policy, db::is_transient);
+
+    // L1 in memory in front of Redis (RT-002). Admin writes and the
+    // listeners invalidate both levels directly; only calculation reads go
+    // through the timeout, retries and breaker. Both share the same L1.
+    let cache_writes = MemoryCache::new(redis_cache, l1_config);
+    let cache_repo = cache_writes.with_next(Guarded::new(redis_retries.clone(), redis_breaker));
+
+    // Admin writes, on this instance or any other, and SQL edits are pushed
+    // to `TaxEngine.subscribe` listeners
+    let changes = Arc::new(ChangeNotifier::new());
+    let notify = |changes: Arc<ChangeNotifier>| {
+        move |invalidation: Option<&Invalidation>| {
+            changes.publish(invalidation.map_or_else(Change::resync, Change::from_invalidation))
+        }
+    };
+    // Writes on any instance are published on Redis and evicted here
+    tokio::spawn(cache::run_invalidation_listener(redis_client, cache_writes.clone(), notify(changes.clone())));
+    // SQL edits to profiles/iva_rates are notified by triggers and evicted
+    // from Redis and L1; stops when the pool is closed
+    tokio::spawn(db::run_change_listener(db_pool.clone(), cache_writes.clone(), notify(changes.clone())));
+
+    let admin_service = Arc::new(
+        AdminService::new(db_repo.clone(), cache_writes).with_notifier(changes.clone()),
+    );
+    let profile_resolver = ProfileResolver::new(Guarded::new(db_retries.clone(), db_breaker), cache_repo.clone())
+        .with_transient_errors(db::is_transient);
     let iva_calculator = IVACalculator;
-    
-    let orchestrator = Orchestrator::new(profile_resolver, iva_calculator);
-
-    let tax_engine_impl = TaxEngineImpl { orchestrator };
-    let client: tax_engine::Client = capnp_rpc::new_client::<tax_engine::Client, _>(tax_engine_impl);
-
-    let listener = TcpListener::bind(&addr).await?;
-    info!("RPC Server listening on {}", addr);
-
-    let local = tokio::task::LocalSet::new();
-    local.run_until(async move {
-        loop {
-            let (stream, _) = listener.accept().await?;
-            stream.set_nodelay(true)?;
-            let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
-            let network = twoparty::VatNetwork::new(
-                reader,
-                writer,
-                rpc_twoparty_capnp::Side::Server,
-                Default::default(),
-            );
-
-            let rpc_system = RpcSystem::new(Box::new(network), Some(client.clone().client));
-            tokio::task::spawn_local(rpc_system.map(|_| ()));
+
+    // Shared by every RPC worker thread
+    let orchestrator = Arc::new(Orchestrator::new(profile_resolver, iva_calculator));
+
+    // HTTP/JSON clients share the RPC rate limit and gateway tokens
+    let rate_limiter = server_config.rate_limiter();
+    let keys = server_config.load_keys()?;
+    let mut api = CalculateApi::new(orchestrator.clone(), shutdown.clone(), server_config.request_timeout)
+        .with_timeout_bounds(server_config.timeout_bounds);
+    if let Some(rate_limiter) = &rate_limiter {
+        api = api.with_rate_limiter(rate_limiter.clone());
+    }
+    if let Some(keys) = &keys {
+        api = api.with_keys(keys.clone());
+    }
+
+    // Keeps answering probes (NOT_SERVING) while the RPC server drains
+    let http_server = HttpServer::bind(server_config.http_addr, health.clone()).await?;
+    // Without probes the orchestrator cannot tell the instance is alive, so
+    // losing the listener stops the server
+    let http_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        if let Err(e) = http_server.run().await {
+            error!("HTTP health endpoint stopped: {:#}", e);
+            http_shutdown.trigger();
+        }
+    });
+
+    // The JSON API gets the same TLS and connection limit as the RPC
+    // listener; only the probes stay on HTTP_ADDR
+    if let Some(api_addr) = server_config.api_addr {
+        let mut api_server = HttpServer::bind(api_addr, health.clone())
+            .await?
+            .with_api(Arc::new(api))
+            .with_max_connections(server_config.max_connections);
+        if let Some(tls) = &server_config.tls {
+            api_server = api_server.with_tls(tls.acceptor().context("Invalid TLS configuration")?);
+        }
+        tokio::spawn(async move {
+            if let Err(e) = api_server.run().await {
+                error!("HTTP API stopped: {:#}", e);
+            }
+        });
+    }
+
+    let mut admin_server = AdminServer::new(server_config.admin_addr, admin_service);
+    if let Some(tls) = &server_config.tls {
+        admin_server = admin_server.with_tls(tls.acceptor().context("Invalid TLS configuration")?);
+    }
+    let admin_shutdown = shutdown.clone();
+    let admin_task = tokio::spawn(async move {
+        if let Err(e) = admin_server.run(admin_shutdown).await {
+            error!("Admin RPC Server stopped: {:#}", e);
         }
-    }).await
+    });
+
+    let signal_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        shutdown::wait_for_signal().await;
+        info!("Shutting down...");
+        signal_shutdown.trigger();
+    });
+
+    // Returns once in-flight calls are drained; the Redis client is released
+    // together with the orchestrator
+    let mut rpc_server = RpcServer::new(server_config, orchestrator.clone())
+        .with_health(health)
+        .with_changes(changes);
+    if let Some(rate_limiter) = rate_limiter {
+        rpc_server = rpc_server.with_rate_limiter(rate_limiter);
+    }
+    if let Some(keys) = keys {
+        rpc_server = rpc_server.with_keys(keys);
+    }
+    rpc_server.run(shutdown).await?;
+
+    admin_task.await?;
+
+    let stats = cache_repo.stats();
+    info!(
+        "Profile cache: L1 {} hits / {} not found / {} misses, Redis {} hits / {} not found / {} misses",
+        stats.l1.hits,
+        stats.l1.not_found,
+        stats.l1.misses,
+        stats.l2.hits,
+        stats.l2.not_found,
+        stats.l2.misses
+    );
+    info!("Cache errors skipped by calculations: {}", orchestrator.profile_resolver.cache_errors());
+    let (db_stats, redis_stats) = (db_retries.stats(), redis_retries.stats());
+    info!(
+        "Retries: PostgreSQL {} ({} recovered, {} exhausted), Redis {} ({} recovered, {} exhausted)",
+        db_stats.retries,
+        db_stats.recovered,
+        db_stats.exhausted,
+        redis_stats.retries,
+        redis_stats.recovered,
+        redis_stats.exhausted
+    );
+
+    info!("Closing PostgreSQL pool...");
+    db_pool.close().await;
+
+    info!("Tax Engine RPC Server stopped");
+    Ok(())
 }
@@ -0,0 +1,224 @@
+//! Repository and cache doubles shared by the unit tests.
+
+use crate::domain::models::{IvaRate, Profile};
+use crate::domain::traits::{
+    CacheLookup, ProfileAdminRepositoryTrait, ProfileCacheTrait, ProfileRepositoryTrait, StaleEntry,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use mockall::mock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+mock! {
+    pub Repo {}
+    #[async_trait]
+    impl ProfileRepositoryTrait for Repo {
+        async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>>;
+        async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>>;
+    }
+}
+
+mock! {
+    pub AdminRepo {}
+    #[async_trait]
+    impl ProfileAdminRepositoryTrait for AdminRepo {
+        async fn list_profiles(&self) -> Result<Vec<Profile>>;
+        async fn insert_profile(&self, profile: &Profile, actor: &str) -> Result<bool>;
+        async fn update_profile(&self, profile: &Profile, actor: &str) -> Result<bool>;
+        async fn delete_profile(&self, client_id: &str, actor: &str) -> Result<bool>;
+        async fn list_iva_rates(&self) -> Result<Vec<IvaRate>>;
+        async fn insert_iva_rate(&self, rate: &IvaRate, actor: &str) -> Result<bool>;
+        async fn update_iva_rate(&self, rate: &IvaRate, actor: &str) -> Result<bool>;
+        async fn delete_iva_rate(&self, jurisdiction: &str, actor: &str) -> Result<bool>;
+    }
+}
+
+mock! {
+    /// Cache that keeps the trait's default negative and stale lookups.
+    pub Cache {}
+    #[async_trait]
+    impl ProfileCacheTrait for Cache {
+        async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>>;
+        async fn set(&self, profile: &Profile) -> Result<()>;
+        async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>>;
+        async fn set_iva_rate(&self, rate: &IvaRate) -> Result<()>;
+        async fn invalidate_profile(&self, client_id: &str) -> Result<()>;
+        async fn invalidate_iva_rate(&self, jurisdiction: &str) -> Result<()>;
+        async fn invalidate_all_profiles(&self) -> Result<()>;
+        async fn invalidate_all_iva_rates(&self) -> Result<()>;
+    }
+}
+
+mock! {
+    /// Cache with every method mocked, including negative and stale lookups.
+    pub NegativeCache {}
+    #[async_trait]
+    impl ProfileCacheTrait for NegativeCache {
+        async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>>;
+        async fn set(&self, profile: &Profile) -> Result<()>;
+        async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>>;
+        async fn set_iva_rate(&self, rate: &IvaRate) -> Result<()>;
+        async fn lookup_profile(&self, client_id: &str) -> Result<CacheLookup<Profile>>;
+        async fn set_profile_not_found(&self, client_id: &str) -> Result<()>;
+        async fn lookup_iva_rate(&self, jurisdiction: &str) -> Result<CacheLookup<IvaRate>>;
+        async fn set_iva_rate_not_found(&self, jurisdiction: &str) -> Result<()>;
+        async fn get_stale_profile(&self, client_id: &str) -> Result<Option<StaleEntry<Profile>>>;
+        async fn get_stale_iva_rate(&self, jurisdiction: &str) -> Result<Option<StaleEntry<IvaRate>>>;
+        async fn invalidate_profile(&self, client_id: &str) -> Result<()>;
+        async fn invalidate_iva_rate(&self, jurisdiction: &str) -> Result<()>;
+        async fn invalidate_all_profiles(&self) -> Result<()>;
+        async fn invalidate_all_iva_rates(&self) -> Result<()>;
+    }
+}
+
+/// `c1` is `RESPONSABLE_INSCRIPTO`, `mono` is `MONOTRIBUTO` and any other
+/// client is missing. TDF has its own rate; the rest use 21%. As a cache it
+/// always misses.
+#[derive(Clone)]
+pub struct FixedStore;
+
+#[async_trait]
+impl ProfileRepositoryTrait for FixedStore {
+    async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
+        let fiscal_category = match client_id {
+            "c1" => "RESPONSABLE_INSCRIPTO",
+            "mono" => "MONOTRIBUTO",
+            _ => return Ok(None),
+        };
+        Ok(Some(Profile {
+            client_id: client_id.to_string(),
+            fiscal_category: fiscal_category.to_string(),
+            config: serde_json::json!({}),
+        }))
+    }
+
+    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>> {
+        let rate = if jurisdiction == "TDF" { 0.105 } else { 0.21 };
+        Ok(Some(IvaRate { jurisdiction: jurisdiction.to_string(), rate }))
+    }
+}
+
+#[async_trait]
+impl ProfileCacheTrait for FixedStore {
+    async fn get_by_id(&self, _client_id: &str) -> Result<Option<Profile>> {
+        Ok(None)
+    }
+    async fn set(&self, _profile: &Profile) -> Result<()> {
+        Ok(())
+    }
+    async fn get_iva_rate(&self, _jurisdiction: &str) -> Result<Option<IvaRate>> {
+        Ok(None)
+    }
+    async fn set_iva_rate(&self, _rate: &IvaRate) -> Result<()> {
+        Ok(())
+    }
+    async fn invalidate_profile(&self, _client_id: &str) -> Result<()> {
+        Ok(())
+    }
+    async fn invalidate_iva_rate(&self, _jurisdiction: &str) -> Result<()> {
+        Ok(())
+    }
+    async fn invalidate_all_profiles(&self) -> Result<()> {
+        Ok(())
+    }
+    async fn invalidate_all_iva_rates(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Cache whose every call fails, as when Redis is down.
+#[derive(Clone)]
+pub struct DownCache;
+
+#[async_trait]
+impl ProfileCacheTrait for DownCache {
+    async fn get_by_id(&self, _client_id: &str) -> Result<Option<Profile>> {
+        anyhow::bail!("redis down")
+    }
+    async fn set(&self, _profile: &Profile) -> Result<()> {
+        anyhow::bail!("redis down")
+    }
+    async fn get_iva_rate(&self, _jurisdiction: &str) -> Result<Option<IvaRate>> {
+        anyhow::bail!("redis down")
+    }
+    async fn set_iva_rate(&self, _rate: &IvaRate) -> Result<()> {
+        anyhow::bail!("redis down")
+    }
+    async fn invalidate_profile(&self, _client_id: &str) -> Result<()> {
+        anyhow::bail!("redis down")
+    }
+    async fn invalidate_iva_rate(&self, _jurisdiction: &str) -> Result<()> {
+        anyhow::bail!("redis down")
+    }
+    async fn invalidate_all_profiles(&self) -> Result<()> {
+        anyhow::bail!("redis down")
+    }
+    async fn invalidate_all_iva_rates(&self) -> Result<()> {
+        anyhow::bail!("redis down")
+    }
+}
+
+/// Cache that never answers, standing in for a stalled dependency.
+#[derive(Clone)]
+pub struct StalledCache;
+
+#[async_trait]
+impl ProfileCacheTrait for StalledCache {
+    async fn get_by_id(&self, _client_id: &str) -> Result<Option<Profile>> {
+        std::future::pending().await
+    }
+    async fn set(&self, _profile: &Profile) -> Result<()> {
+        std::future::pending().await
+    }
+    async fn get_iva_rate(&self, _jurisdiction: &str) -> Result<Option<IvaRate>> {
+        std::future::pending().await
+    }
+    async fn set_iva_rate(&self, _rate: &IvaRate) -> Result<()> {
+        std::future::pending().await
+    }
+    async fn invalidate_profile(&self, _client_id: &str) -> Result<()> {
+        std::future::pending().await
+    }
+    async fn invalidate_iva_rate(&self, _jurisdiction: &str) -> Result<()> {
+        std::future::pending().await
+    }
+    async fn invalidate_all_profiles(&self) -> Result<()> {
+        std::future::pending().await
+    }
+    async fn invalidate_all_iva_rates(&self) -> Result<()> {
+        std::future::pending().await
+    }
+}
+
+/// Repository that answers like [`FixedStore`] after `delay`, counting its
+/// lookups.
+pub struct SlowRepo {
+    delay: Duration,
+    loads: AtomicUsize,
+}
+
+impl SlowRepo {
+    pub fn new(delay: Duration) -> Self {
+        Self { delay, loads: AtomicUsize::new(0) }
+    }
+
+    pub fn loads(&self) -> usize {
+        self.loads.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl ProfileRepositoryTrait for SlowRepo {
+    async fn get_by_id(&self, client_id: &str) -> Result<Option<Profile>> {
+        self.loads.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(self.delay).await;
+        ProfileRepositoryTrait::get_by_id(&FixedStore, client_id).await
+    }
+
+    async fn get_iva_rate(&self, jurisdiction: &str) -> Result<Option<IvaRate>> {
+        self.loads.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(self.delay).await;
+        ProfileRepositoryTrait::get_iva_rate(&FixedStore, jurisdiction).await
+    }
+}
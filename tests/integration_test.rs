@@ -1,11 +1,18 @@
+use rust_decimal::Decimal;
 use sqlx::PgPool;
 use std::sync::Arc;
+use std::time::Duration;
+use tax_manager::app::changes::{Change, ChangeAction, ChangeKind, ChangeNotifier};
+use tax_manager::app::circuit_breaker::CircuitOpen;
 use tax_manager::app::orchestrator::Orchestrator;
 use tax_manager::app::resolver::ProfileResolver;
 use tax_manager::domain::calculators::IVACalculator;
-use tax_manager::domain::models::Transaction;
+use tax_manager::domain::invalidation::Invalidation;
+use tax_manager::domain::models::{IvaRate, Profile, Transaction};
+use tax_manager::domain::traits::ProfileRepositoryTrait;
 use tax_manager::infra::cache::ProfileCache;
-use tax_manager::infra::db::ProfileRepository;
+use tax_manager::infra::db::{self, ProfileRepository};
+use tax_manager::infra::memory_cache::{MemoryCache, MemoryCacheConfig};
 use testcontainers_modules::postgres::Postgres;
 use testcontainers_modules::redis::Redis;
 use testcontainers::runners::AsyncRunner;
@@ -46,7 +53,7 @@ async fn test_integration_full_flow() {
 
     // 4. Run calculation
     let tx = Transaction {
-        amount: 1000.0,
+        amount: Decimal::from(1000),
         product: "TEST".to_string(),
         jurisdiction: "TEST_J".to_string(),
         client_id: "client_test".to_string(),
@@ -57,7 +64,7 @@ async fn test_integration_full_flow() {
     let res1 = orchestrator.process_calculation(tx.clone()).await.expect("Calculation failed");
     assert_eq!(res1.len(), 1);
     assert_eq!(res1[0].rate, 0.15);
-    assert_eq!(res1[0].amount, 150.0);
+    assert_eq!(res1[0].amount, Decimal::from(150));
 
     // Change DB value to verify cache hit
     sqlx::query("UPDATE iva_rates SET rate = 0.50 WHERE jurisdiction = 'TEST_J'")
@@ -66,5 +73,202 @@ async fn test_integration_full_flow() {
     // Second call (Should hit cache and still be 0.15)
     let res2 = orchestrator.process_calculation(tx).await.expect("Calculation failed");
     assert_eq!(res2[0].rate, 0.15);
-    assert_eq!(res2[0].amount, 150.0);
+    assert_eq!(res2[0].amount, Decimal::from(150));
+}
+
+#[tokio::test]
+async fn test_integration_sql_update_invalidates_cache() {
+    let pg_node = Postgres::default().start().await.expect("Failed to start Postgres");
+    let pg_host = pg_node.get_host().await.expect("Failed to get PG host");
+    let pg_port = pg_node.get_host_port_ipv4(5432).await.expect("Failed to get PG port");
+    let db_url = format!("postgres://postgres:postgres@{}:{}/postgres", pg_host, pg_port);
+
+    let db_pool = PgPool::connect(&db_url).await.expect("Failed to connect to test PG");
+
+    sqlx::query("CREATE TABLE profiles (client_id TEXT PRIMARY KEY, fiscal_category TEXT NOT NULL, config JSONB NOT NULL DEFAULT '{}')")
+        .execute(&db_pool).await.unwrap();
+    sqlx::query("CREATE TABLE iva_rates (jurisdiction TEXT PRIMARY KEY, rate FLOAT8 NOT NULL)")
+        .execute(&db_pool).await.unwrap();
+    sqlx::raw_sql(include_str!("../migrations/20260401_cache_invalidation_triggers.sql"))
+        .execute(&db_pool).await.unwrap();
+    sqlx::query("INSERT INTO iva_rates (jurisdiction, rate) VALUES ('TEST_J', 0.15), ('DEFAULT', 0.21)")
+        .execute(&db_pool).await.unwrap();
+    sqlx::query("INSERT INTO profiles (client_id, fiscal_category, config) VALUES ('client_test', 'RESPONSABLE_INSCRIPTO', '{}')")
+        .execute(&db_pool).await.unwrap();
+
+    let redis_node = Redis::default().start().await.expect("Failed to start Redis");
+    let redis_host = redis_node.get_host().await.expect("Failed to get Redis host");
+    let redis_port = redis_node.get_host_port_ipv4(6379).await.expect("Failed to get Redis port");
+    let redis_url = format!("redis://{}:{}", redis_host, redis_port);
+
+    let redis_client = Arc::new(redis::Client::open(redis_url).expect("Failed to create redis client"));
+
+    let db_repo = ProfileRepository::new(db_pool.clone());
+    let cache_repo = MemoryCache::new(ProfileCache::new(redis_client), MemoryCacheConfig::default());
+    let changes = Arc::new(ChangeNotifier::new());
+    let mut received = changes.subscribe();
+    let notifier = changes.clone();
+    let listener = tokio::spawn(db::run_change_listener(
+        db_pool.clone(),
+        cache_repo.clone(),
+        move |invalidation: Option<&Invalidation>| {
+            notifier.publish(invalidation.map_or_else(Change::resync, Change::from_invalidation))
+        },
+    ));
+    let resolver = ProfileResolver::new(db_repo, cache_repo);
+    let orchestrator = Orchestrator::new(resolver, IVACalculator);
+
+    let tx = Transaction {
+        amount: Decimal::from(1000),
+        product: "TEST".to_string(),
+        jurisdiction: "TEST_J".to_string(),
+        client_id: "client_test".to_string(),
+        date: chrono::Local::now().date_naive(),
+    };
+
+    // Populates Redis and L1
+    let res1 = orchestrator.process_calculation(tx.clone()).await.expect("Calculation failed");
+    assert_eq!(res1[0].rate, 0.15);
+
+    // Give the listener time to LISTEN before changing the row
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    sqlx::query("UPDATE iva_rates SET rate = 0.50 WHERE jurisdiction = 'TEST_J'")
+        .execute(&db_pool).await.unwrap();
+
+    // The trigger's NOTIFY evicts the rate from both cache levels
+    let mut rate = 0.0;
+    for _ in 0..50 {
+        rate = orchestrator.process_calculation(tx.clone()).await.expect("Calculation failed")[0].rate;
+        if rate == 0.50 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(rate, 0.50);
+
+    // SQL edits also reach `subscribe` listeners
+    let change = received.recv().await.unwrap();
+    assert_eq!(
+        change,
+        Change { kind: ChangeKind::IvaRate, key: "TEST_J".to_string(), action: ChangeAction::Updated }
+    );
+
+    db_pool.close().await;
+    listener.await.unwrap();
+}
+
+/// PostgreSQL as seen by calculations during the outage.
+struct DownRepo;
+
+#[async_trait::async_trait]
+impl ProfileRepositoryTrait for DownRepo {
+    async fn get_by_id(&self, _client_id: &str) -> anyhow::Result<Option<Profile>> {
+        Err(CircuitOpen { name: "postgres" }.into())
+    }
+
+    async fn get_iva_rate(&self, _jurisdiction: &str) -> anyhow::Result<Option<IvaRate>> {
+        Err(CircuitOpen { name: "postgres" }.into())
+    }
+}
+
+#[tokio::test]
+async fn test_integration_listener_reconnect_keeps_stale_copies() {
+    let pg_node = Postgres::default().start().await.expect("Failed to start Postgres");
+    let pg_host = pg_node.get_host().await.expect("Failed to get PG host");
+    let pg_port = pg_node.get_host_port_ipv4(5432).await.expect("Failed to get PG port");
+    let db_url = format!("postgres://postgres:postgres@{}:{}/postgres", pg_host, pg_port);
+
+    let db_pool = PgPool::connect(&db_url).await.expect("Failed to connect to test PG");
+
+    sqlx::query("CREATE TABLE profiles (client_id TEXT PRIMARY KEY, fiscal_category TEXT NOT NULL, config JSONB NOT NULL DEFAULT '{}')")
+        .execute(&db_pool).await.unwrap();
+    sqlx::query("CREATE TABLE iva_rates (jurisdiction TEXT PRIMARY KEY, rate FLOAT8 NOT NULL)")
+        .execute(&db_pool).await.unwrap();
+    sqlx::raw_sql(include_str!("../migrations/20260401_cache_invalidation_triggers.sql"))
+        .execute(&db_pool).await.unwrap();
+    sqlx::query("INSERT INTO iva_rates (jurisdiction, rate) VALUES ('TEST_J', 0.15), ('DEFAULT', 0.21)")
+        .execute(&db_pool).await.unwrap();
+    sqlx::query("INSERT INTO profiles (client_id, fiscal_category, config) VALUES ('client_test', 'RESPONSABLE_INSCRIPTO', '{}')")
+        .execute(&db_pool).await.unwrap();
+
+    let redis_node = Redis::default().start().await.expect("Failed to start Redis");
+    let redis_host = redis_node.get_host().await.expect("Failed to get Redis host");
+    let redis_port = redis_node.get_host_port_ipv4(6379).await.expect("Failed to get Redis port");
+    let redis_url = format!("redis://{}:{}", redis_host, redis_port);
+
+    let redis_client = Arc::new(redis::Client::open(redis_url).expect("Failed to create redis client"));
+    let cache_repo = MemoryCache::new(ProfileCache::new(redis_client), MemoryCacheConfig::default());
+    let changes = Arc::new(ChangeNotifier::new());
+    let mut received = changes.subscribe();
+    let notifier = changes.clone();
+    let listener = tokio::spawn(db::run_change_listener(
+        db_pool.clone(),
+        cache_repo.clone(),
+        move |invalidation: Option<&Invalidation>| {
+            notifier.publish(invalidation.map_or_else(Change::resync, Change::from_invalidation))
+        },
+    ));
+
+    let tx = Transaction {
+        amount: Decimal::from(1000),
+        product: "TEST".to_string(),
+        jurisdiction: "TEST_J".to_string(),
+        client_id: "client_test".to_string(),
+        date: chrono::Local::now().date_naive(),
+    };
+
+    // Stores the profile and rate, with their stale copies
+    let orchestrator = Orchestrator::new(ProfileResolver::new(ProfileRepository::new(db_pool.clone()), cache_repo.clone()), IVACalculator);
+    orchestrator.process_calculation(tx.clone()).await.expect("Calculation failed");
+
+    // The outage drops the listener's connection; it flushes the cache once back
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    sqlx::query("SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE query LIKE 'LISTEN%'")
+        .execute(&db_pool).await.unwrap();
+    let change = tokio::time::timeout(Duration::from_secs(10), received.recv()).await.unwrap().unwrap();
+    assert_eq!(change, Change::resync());
+
+    // With PostgreSQL down again, the stale copies still answer
+    let orchestrator = Orchestrator::new(ProfileResolver::new(DownRepo, cache_repo), IVACalculator);
+    let res = orchestrator.process_calculation(tx).await.expect("Stale copies were not served");
+    assert_eq!(res[0].rate, 0.15);
+
+    db_pool.close().await;
+    listener.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_integration_redis_fill_skips_loads_invalidated_meanwhile() {
+    use tax_manager::domain::traits::ProfileCacheTrait;
+
+    let redis_node = Redis::default().start().await.expect("Failed to start Redis");
+    let redis_host = redis_node.get_host().await.expect("Failed to get Redis host");
+    let redis_port = redis_node.get_host_port_ipv4(6379).await.expect("Failed to get Redis port");
+    let redis_client =
+        Arc::new(redis::Client::open(format!("redis://{}:{}", redis_host, redis_port)).expect("Failed to create redis client"));
+    let cache = ProfileCache::new(redis_client);
+    let old = Profile {
+        client_id: "c1".to_string(),
+        fiscal_category: "MONOTRIBUTO".to_string(),
+        config: serde_json::json!({}),
+    };
+
+    // A load that read the row before an admin write must not bring it back
+    let generation = cache.profile_generation("c1").await.unwrap();
+    cache.invalidate_profile("c1").await.unwrap();
+    cache.fill_profile("c1", Some(&old), generation).await.unwrap();
+    assert!(cache.get_by_id("c1").await.unwrap().is_none());
+    assert!(cache.get_stale_profile("c1").await.unwrap().is_none());
+
+    let generation = cache.iva_rate_generation("TDF").await.unwrap();
+    cache.invalidate_all_iva_rates().await.unwrap();
+    let rate = IvaRate { jurisdiction: "TDF".to_string(), rate: 0.21 };
+    cache.fill_iva_rate("TDF", Some(&rate), generation).await.unwrap();
+    assert!(cache.get_iva_rate("TDF").await.unwrap().is_none());
+
+    // Loads started after the invalidation are cached, stale copy included
+    let generation = cache.profile_generation("c1").await.unwrap();
+    cache.fill_profile("c1", Some(&old), generation).await.unwrap();
+    assert!(cache.get_by_id("c1").await.unwrap().is_some());
+    assert!(cache.get_stale_profile("c1").await.unwrap().is_some());
 }